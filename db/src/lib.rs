@@ -1,30 +1,131 @@
 pub use self::serialized::{
-    blob::{Blob, BlobId, BlobType},
+    blob::BlobType,
     media_type::MediaType,
     source_type::SourceType,
     tag::{Tag, TagId},
     tag_category::{Category, CategoryId},
 };
+use bincode::{ErrorKind, Options};
 use serde::{Deserialize, Serialize};
 use std::collections::{BTreeMap, BTreeSet};
 use table::Table;
+use v2::DbV2;
 
 mod serialized;
 mod table;
 pub mod traits;
 pub mod v2;
+pub mod v3;
 
-pub use v2::DbV2 as Db;
-pub use v2::{Piece, PieceId};
+pub use v3::DbV3 as Db;
+pub use v3::{
+    Blob, BlobId, Collection, CollectionEntry, CollectionId, Inbox, Piece, PieceId, Relation,
+    RelationType, SavedSearch, SavedSearchId, StorageLayout,
+};
 
 #[derive(Debug, Serialize, Deserialize, Default, PartialEq, Eq, Clone)]
 pub struct DbV1 {
     pieces: Table<self::serialized::piece::Piece>,
-    blobs: Table<Blob>,
+    blobs: Table<self::serialized::blob::Blob>,
     tags: Table<Tag>,
     categories: Table<Category>,
 
-    media: BTreeSet<(PieceId, BlobId)>,
+    media: BTreeSet<(PieceId, self::serialized::blob::BlobId)>,
     piece_tags: BTreeSet<(PieceId, TagId)>,
     tag_category: BTreeMap<TagId, CategoryId>,
 }
+
+/// Starts every database file written since v0.4, followed by the version of its layout.
+const MAGIC: &[u8; 4] = b"AODB";
+const VERSION: u32 = 3;
+
+/// Writes a database file.
+pub fn to_bytes(db: &Db) -> bincode::Result<Vec<u8>> {
+    let mut data = MAGIC.to_vec();
+    data.extend(VERSION.to_le_bytes());
+    bincode::serialize_into(&mut data, db)?;
+    Ok(data)
+}
+
+/// Reads a database file written by any version, upgrading it to the current layout.
+pub fn from_bytes(data: &[u8]) -> bincode::Result<Db> {
+    let options = || {
+        bincode::DefaultOptions::new()
+            .with_fixint_encoding()
+            .reject_trailing_bytes()
+    };
+    match data.strip_prefix(MAGIC) {
+        Some(rest) if rest.len() >= 4 => {
+            let (version, rest) = rest.split_at(4);
+            match u32::from_le_bytes([version[0], version[1], version[2], version[3]]) {
+                VERSION => options().deserialize(rest),
+                version => Err(Box::new(ErrorKind::Custom(format!(
+                    "The database was saved by a newer version of the program (layout {}).",
+                    version
+                )))),
+            }
+        }
+        // older files have no header, so they're told apart by which layout reads all of them
+        _ => options()
+            .deserialize::<DbV1>(data)
+            .map(Db::from)
+            .or_else(|_| options().deserialize::<DbV2>(data).map(Db::from)),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use chrono::NaiveDate;
+
+    /// A database saved by v0.3, with a deleted piece leaving a gap in the IDs.
+    const V0_3: &[u8] = include_bytes!("../fixtures/v0.3.aodb");
+
+    #[test]
+    fn reads_v0_3() {
+        let db = from_bytes(V0_3).unwrap();
+
+        let pieces = db.pieces().collect::<Vec<_>>();
+        assert_eq!(pieces.len(), 2);
+        let (first, piece) = pieces[0];
+        assert_eq!(piece.external_id.as_deref(), Some("skeb-123"));
+        assert_eq!(piece.description, "A commission");
+        assert_eq!(piece.added, NaiveDate::from_ymd(2021, 5, 1));
+        assert_eq!((piece.base_price, piece.tip_price), (Some(5000), Some(500)));
+        assert_eq!(piece.source_type, SourceType::Commission);
+        let (second, piece) = pieces[1];
+        assert_eq!(usize::from(second), 2);
+        assert_eq!(piece.description, "A personal piece");
+
+        let canon = db.blobs_for_piece(first).collect::<Vec<_>>();
+        assert_eq!(canon.len(), 1);
+        assert_eq!(db[canon[0]].file_name, "final.png");
+        assert_eq!(db[canon[0]].hash, 0xdead_beef);
+        assert_eq!(db[canon[0]].location, None);
+        let raw = db.blobs_for_piece(second).next().unwrap();
+        assert_eq!(db[raw].blob_type, BlobType::Raw);
+
+        let tag = db.tags_for_piece(first).next().unwrap();
+        assert_eq!(db[tag].links, vec!["https://example.com".to_string()]);
+        let category = db.category_for_tag(tag).unwrap();
+        assert_eq!(db[category].color, [255, 0, 0, 255]);
+        assert_eq!(db[db.tags_for_piece(second).next().unwrap()].name, "wip");
+        assert_eq!(db.storage_layout, StorageLayout::Flat);
+    }
+
+    #[test]
+    fn round_trips_v0_3() {
+        let db = from_bytes(V0_3).unwrap();
+        let data = to_bytes(&db).unwrap();
+        assert!(data.starts_with(MAGIC));
+        assert_eq!(from_bytes(&data).unwrap(), db);
+    }
+
+    #[test]
+    fn refuses_newer_layouts() {
+        let mut data = MAGIC.to_vec();
+        data.extend((VERSION + 1).to_le_bytes());
+        data.extend(&to_bytes(&Db::default()).unwrap()[8..]);
+        assert!(from_bytes(&data).is_err());
+    }
+}
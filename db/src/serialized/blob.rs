@@ -1,4 +1,4 @@
-use std::{fmt::Display, path::PathBuf};
+use std::fmt::Display;
 
 use chrono::NaiveDate;
 use serde::{Deserialize, Serialize};
@@ -15,13 +15,6 @@ pub struct Blob {
     pub blob_type: BlobType,
     pub added: NaiveDate,
 }
-
-impl Blob {
-    pub fn storage_name(&self, id: BlobId) -> PathBuf {
-        format!("[{}] {}", id, self.file_name).parse().unwrap()
-    }
-}
-
 #[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone, Copy, EnumIter, PartialOrd, Ord)]
 pub enum BlobType {
     Canon,
@@ -6,6 +6,73 @@ use strum::EnumIter;
 pub enum MediaType {
     Image,
     Text,
+    Video,
+    Audio,
+}
+
+impl MediaType {
+    /// Recognizes a file from the start of its contents, or None if it could be anything.
+    pub fn detect(header: &[u8]) -> Option<Self> {
+        let at = |offset: usize, signature: &[u8]| {
+            header.get(offset..offset + signature.len()) == Some(signature)
+        };
+
+        if at(4, b"ftyp") {
+            Some(iso_brand(header))
+        } else if at(0, b"\x1a\x45\xdf\xa3") || at(8, b"AVI ") {
+            Some(MediaType::Video)
+        } else if at(0, b"ID3")
+            || at(0, b"OggS")
+            || at(0, b"fLaC")
+            || at(8, b"WAVE")
+            // an MPEG audio frame
+            || (header.len() > 1 && header[0] == 0xff && header[1] & 0xe0 == 0xe0)
+        {
+            Some(MediaType::Audio)
+        } else if !header.is_empty() && !header.contains(&0) && is_utf8(header) {
+            Some(MediaType::Text)
+        } else {
+            None
+        }
+    }
+}
+
+/// Brands of ISO media files (HEIF, AVIF, MP4...) which hold still images or image sequences.
+const IMAGE_BRANDS: &[&[u8; 4]] = &[
+    b"avif", b"avis", b"heic", b"heix", b"heim", b"heis", b"hevc", b"hevx", b"mif1", b"msf1",
+];
+/// Brands of ISO media files which only hold sound.
+const AUDIO_BRANDS: &[&[u8; 4]] = &[b"M4A ", b"M4B ", b"M4P ", b"F4A ", b"F4B "];
+
+/// Tells ISO media files apart by their `ftyp` box, which has the major brand at offset 8
+/// followed by a version and the compatible brands. The major brand is checked first, then the
+/// compatible ones, and anything unknown is taken to be a video.
+fn iso_brand(header: &[u8]) -> MediaType {
+    let size = header.get(..4).map_or(0, |size| {
+        u32::from_be_bytes([size[0], size[1], size[2], size[3]]) as usize
+    });
+    let major = header.get(8..12).into_iter();
+    let compatible = header
+        .get(16..size.min(header.len()))
+        .unwrap_or_default()
+        .chunks_exact(4);
+    for brand in major.chain(compatible) {
+        if IMAGE_BRANDS.iter().any(|known| &known[..] == brand) {
+            return MediaType::Image;
+        }
+        if AUDIO_BRANDS.iter().any(|known| &known[..] == brand) {
+            return MediaType::Audio;
+        }
+    }
+    MediaType::Video
+}
+
+/// Whether `header` is UTF-8, allowing for a character cut off at the end.
+fn is_utf8(header: &[u8]) -> bool {
+    match std::str::from_utf8(header) {
+        Ok(_) => true,
+        Err(err) => err.error_len().is_none(),
+    }
 }
 
 impl Display for MediaType {
@@ -16,7 +83,40 @@ impl Display for MediaType {
             match self {
                 MediaType::Image => "Image",
                 MediaType::Text => "Text",
+                MediaType::Video => "Video",
+                MediaType::Audio => "Audio",
             }
         )
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// An `ftyp` box with the given brands, as ISO media files start.
+    fn ftyp(major: &[u8; 4], compatible: &[&[u8; 4]]) -> Vec<u8> {
+        let size = 16 + 4 * compatible.len() as u32;
+        let mut header = size.to_be_bytes().to_vec();
+        header.extend_from_slice(b"ftyp");
+        header.extend_from_slice(major);
+        header.extend_from_slice(&[0; 4]);
+        for brand in compatible {
+            header.extend_from_slice(&brand[..]);
+        }
+        // the start of the next box, which isn't a brand
+        header.extend_from_slice(b"\0\0\0\x08heic");
+        header
+    }
+
+    #[test]
+    fn test_iso_brands() {
+        let detect = |major, compatible| MediaType::detect(&ftyp(major, compatible));
+        assert_eq!(detect(b"heic", &[b"mif1", b"heic"]), Some(MediaType::Image));
+        assert_eq!(detect(b"mif1", &[b"mif1", b"miaf"]), Some(MediaType::Image));
+        assert_eq!(detect(b"avif", &[b"avif", b"mif1"]), Some(MediaType::Image));
+        assert_eq!(detect(b"M4A ", &[b"M4A ", b"mp42"]), Some(MediaType::Audio));
+        assert_eq!(detect(b"isom", &[b"isom", b"mp41"]), Some(MediaType::Video));
+        assert_eq!(detect(b"qt  ", &[]), Some(MediaType::Video));
+    }
+}
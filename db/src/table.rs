@@ -118,6 +118,21 @@ impl<T> Table<T> {
     pub fn remove(&mut self, data: TableId<T>) -> T {
         self.data.remove(data.0)
     }
+    /// Puts `data` at `index`, replacing whatever was there.
+    pub fn set(&mut self, index: TableId<T>, data: T) {
+        if let Some(row) = self.data.get_mut(index.0) {
+            *row = data;
+        } else if self.data.vacant_entry().key() == index.0 {
+            self.data.insert(data);
+        } else {
+            // slabs can only be filled in order, so it's rebuilt around the new row
+            let mut rows = std::mem::take(&mut self.data)
+                .into_iter()
+                .collect::<Vec<_>>();
+            rows.push((index.0, data));
+            self.data = rows.into_iter().collect();
+        }
+    }
 
     pub fn get(&self, index: TableId<T>) -> Option<&T> {
         self.data.get(index.0)
@@ -1,13 +1,22 @@
-use crate::{v2::DbV2, BlobId, CategoryId, TagId};
+use crate::{
+    v3::{CollectionId, DbV3, SavedSearchId},
+    BlobId, CategoryId, TagId,
+};
 
 use super::DeleteFrom;
 
-impl DeleteFrom<DbV2> for crate::v2::PieceId {
-    fn delete_from(self, db: &mut DbV2) -> bool {
+impl DeleteFrom<DbV3> for crate::v3::PieceId {
+    fn delete_from(self, db: &mut DbV3) -> bool {
         if db.exists(self) {
             db.pieces.remove(self);
             db.media.retain(|(piece, _)| *piece != self);
             db.piece_tags.retain(|(piece, _)| *piece != self);
+            db.relations
+                .retain(|relation| relation.other(self).is_none());
+            for collection in db.collections.values_mut() {
+                collection.entries.retain(|entry| entry.piece != self);
+            }
+            db.review.remove(&self);
 
             true
         } else {
@@ -16,11 +25,14 @@ impl DeleteFrom<DbV2> for crate::v2::PieceId {
     }
 }
 
-impl DeleteFrom<DbV2> for BlobId {
-    fn delete_from(self, db: &mut DbV2) -> bool {
+impl DeleteFrom<DbV3> for BlobId {
+    fn delete_from(self, db: &mut DbV3) -> bool {
         if db.exists(self) {
             db.blobs.remove(self);
             db.media.retain(|(_, blob)| *blob != self);
+            for collection in db.collections.values_mut() {
+                collection.entries.retain(|entry| entry.blob != Some(self));
+            }
             true
         } else {
             false
@@ -28,8 +40,8 @@ impl DeleteFrom<DbV2> for BlobId {
     }
 }
 
-impl DeleteFrom<DbV2> for TagId {
-    fn delete_from(self, db: &mut DbV2) -> bool {
+impl DeleteFrom<DbV3> for TagId {
+    fn delete_from(self, db: &mut DbV3) -> bool {
         if db.exists(self) {
             db.tags.remove(self);
             db.piece_tags.retain(|(_, tag)| *tag != self);
@@ -41,8 +53,8 @@ impl DeleteFrom<DbV2> for TagId {
     }
 }
 
-impl DeleteFrom<DbV2> for CategoryId {
-    fn delete_from(self, db: &mut DbV2) -> bool {
+impl DeleteFrom<DbV3> for CategoryId {
+    fn delete_from(self, db: &mut DbV3) -> bool {
         if db.exists(self) {
             db.categories.remove(self);
             db.tag_category.retain(|_, tag| *tag != self);
@@ -52,3 +64,25 @@ impl DeleteFrom<DbV2> for CategoryId {
         }
     }
 }
+
+impl DeleteFrom<DbV3> for SavedSearchId {
+    fn delete_from(self, db: &mut DbV3) -> bool {
+        if db.exists(self) {
+            db.saved_searches.remove(self);
+            true
+        } else {
+            false
+        }
+    }
+}
+
+impl DeleteFrom<DbV3> for CollectionId {
+    fn delete_from(self, db: &mut DbV3) -> bool {
+        if db.exists(self) {
+            db.collections.remove(self);
+            true
+        } else {
+            false
+        }
+    }
+}
@@ -1,6 +1,6 @@
 use crate::{
-    v2::commands::{EditBlob, EditCategory, EditPiece, EditTag},
-    Db,
+    v3::commands::{EditBlob, EditCategory, EditCollection, EditPiece, EditSavedSearch, EditTag},
+    Blob, Db,
 };
 
 use super::EditFrom;
@@ -16,9 +16,15 @@ impl EditFrom<Db> for EditPiece {
     }
 }
 impl EditFrom<Db> for EditBlob {
+    /// The blob's file stays where it is, whatever the new file name.
     fn edit_from(self, db: &mut Db) -> bool {
-        if let Some(blob) = db.blobs.get_mut(self.id) {
-            *blob = self.data;
+        if db.exists(self.id) {
+            let location = db.storage_for(self.id);
+            let blob = db.blobs.get_mut(self.id).unwrap();
+            *blob = Blob {
+                location: Some(location),
+                ..self.data
+            };
             true
         } else {
             false
@@ -47,3 +53,25 @@ impl EditFrom<Db> for EditCategory {
         }
     }
 }
+
+impl EditFrom<Db> for EditSavedSearch {
+    fn edit_from(self, db: &mut Db) -> bool {
+        if let Some(saved) = db.saved_searches.get_mut(self.id) {
+            *saved = self.data;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+impl EditFrom<Db> for EditCollection {
+    fn edit_from(self, db: &mut Db) -> bool {
+        if let Some(collection) = db.collections.get_mut(self.id) {
+            *collection = self.data;
+            true
+        } else {
+            false
+        }
+    }
+}
@@ -1,33 +1,46 @@
-use crate::{v2::DbV2, BlobId, CategoryId, TagId};
+use crate::{
+    v3::{CollectionId, DbV3, SavedSearchId},
+    BlobId, CategoryId, TagId,
+};
 
 use super::IdExist;
 
-impl IdExist<DbV2> for BlobId {
-    fn exists_in(self, db: &DbV2) -> bool {
+impl IdExist<DbV3> for BlobId {
+    fn exists_in(self, db: &DbV3) -> bool {
         db.blobs.has(self)
     }
 }
-impl IdExist<DbV2> for crate::v2::PieceId {
-    fn exists_in(self, db: &DbV2) -> bool {
+impl IdExist<DbV3> for crate::v3::PieceId {
+    fn exists_in(self, db: &DbV3) -> bool {
         db.pieces.has(self)
     }
 }
-impl IdExist<DbV2> for TagId {
-    fn exists_in(self, db: &DbV2) -> bool {
+impl IdExist<DbV3> for TagId {
+    fn exists_in(self, db: &DbV3) -> bool {
         db.tags.has(self)
     }
 }
-impl IdExist<DbV2> for CategoryId {
-    fn exists_in(self, db: &DbV2) -> bool {
+impl IdExist<DbV3> for CategoryId {
+    fn exists_in(self, db: &DbV3) -> bool {
         db.categories.has(self)
     }
 }
+impl IdExist<DbV3> for SavedSearchId {
+    fn exists_in(self, db: &DbV3) -> bool {
+        db.saved_searches.has(self)
+    }
+}
+impl IdExist<DbV3> for CollectionId {
+    fn exists_in(self, db: &DbV3) -> bool {
+        db.collections.has(self)
+    }
+}
 
-impl<'a, T: Copy> IdExist<DbV2> for &'a T
+impl<'a, T: Copy> IdExist<DbV3> for &'a T
 where
-    T: IdExist<DbV2>,
+    T: IdExist<DbV3>,
 {
-    fn exists_in(self, db: &DbV2) -> bool {
+    fn exists_in(self, db: &DbV3) -> bool {
         db.exists(*self)
     }
 }
@@ -1,6 +1,9 @@
 use std::ops::Index;
 
-use crate::{v2::Piece, v2::PieceId, Blob, BlobId, Category, CategoryId, Db, Tag, TagId};
+use crate::{
+    v3::{Collection, CollectionId, Piece, PieceId, SavedSearch, SavedSearchId},
+    Blob, BlobId, Category, CategoryId, Db, Tag, TagId,
+};
 
 impl Index<PieceId> for Db {
     type Output = Piece;
@@ -34,6 +37,22 @@ impl Index<CategoryId> for Db {
     }
 }
 
+impl Index<SavedSearchId> for Db {
+    type Output = SavedSearch;
+
+    fn index(&self, index: SavedSearchId) -> &Self::Output {
+        &self.saved_searches[index]
+    }
+}
+
+impl Index<CollectionId> for Db {
+    type Output = Collection;
+
+    fn index(&self, index: CollectionId) -> &Self::Output {
+        &self.collections[index]
+    }
+}
+
 impl<'a, T: Copy> Index<&'a T> for Db
 where
     Db: Index<T>,
@@ -0,0 +1,427 @@
+pub use self::blob::{Blob, BlobId};
+pub use self::collection::{Collection, CollectionEntry, CollectionId};
+pub use self::inbox::Inbox;
+pub use self::merge::MergeConflict;
+pub use self::piece::{Piece, PieceId};
+pub use self::relation::{Relation, RelationType};
+pub use self::saved_search::{SavedSearch, SavedSearchId};
+pub use self::storage::StorageLayout;
+use super::{
+    serialized::{
+        blob::BlobType,
+        tag::{Tag, TagId},
+        tag_category::{Category, CategoryId},
+    },
+    v2::DbV2,
+    DbV1, MediaType, SourceType,
+};
+use crate::table::Table;
+use crate::traits::{DeleteFrom, EditFrom, IdExist};
+use commands::{AttachBlob, AttachCategory, AttachTag, MergePiece};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::PathBuf,
+};
+
+pub mod blob;
+pub mod collection;
+pub mod commands;
+pub mod inbox;
+pub mod journal;
+mod merge;
+pub mod piece;
+pub mod relation;
+pub mod saved_search;
+pub mod storage;
+
+#[derive(Debug, Serialize, Deserialize, Default, PartialEq, Eq, Clone)]
+pub struct DbV3 {
+    pub pieces: Table<Piece>,
+    pub blobs: Table<Blob>,
+    pub tags: Table<Tag>,
+    pub categories: Table<Category>,
+    pub saved_searches: Table<SavedSearch>,
+    pub collections: Table<Collection>,
+
+    pub media: BTreeSet<(PieceId, BlobId)>,
+    pub piece_tags: BTreeSet<(PieceId, TagId)>,
+    pub tag_category: BTreeMap<TagId, CategoryId>,
+    pub relations: BTreeSet<Relation>,
+
+    pub inbox: Inbox,
+    /// Pieces imported from the inbox that still need looking over.
+    pub review: BTreeSet<PieceId>,
+    /// Where new blobs are stored.
+    pub storage_layout: StorageLayout,
+}
+
+impl From<DbV1> for DbV3 {
+    fn from(value: DbV1) -> Self {
+        Self {
+            pieces: value
+                .pieces
+                .iter()
+                .map(|(id, value)| {
+                    (
+                        usize::from(id),
+                        Piece {
+                            external_id: None,
+                            description: value.name.clone(),
+                            source_type: value.source_type,
+                            media_type: value.media_type,
+                            added: value.added,
+                            base_price: value.base_price,
+                            tip_price: value.tip_price,
+                        },
+                    )
+                })
+                .collect(),
+            blobs: value
+                .blobs
+                .iter()
+                .map(|(id, value)| (usize::from(id), value.clone().into()))
+                .collect(),
+            tags: value.tags,
+            categories: value.categories,
+            saved_searches: Table::default(),
+            collections: Table::default(),
+            media: value
+                .media
+                .into_iter()
+                .map(|(lhs, rhs)| (usize::from(lhs).into(), usize::from(rhs).into()))
+                .collect(),
+            piece_tags: value
+                .piece_tags
+                .into_iter()
+                .map(|(lhs, rhs)| (usize::from(lhs).into(), rhs))
+                .collect(),
+            tag_category: value.tag_category,
+            relations: BTreeSet::new(),
+            inbox: Inbox::default(),
+            review: BTreeSet::new(),
+            storage_layout: StorageLayout::Flat,
+        }
+    }
+}
+
+impl From<DbV2> for DbV3 {
+    fn from(value: DbV2) -> Self {
+        Self {
+            pieces: value
+                .pieces
+                .iter()
+                .map(|(id, value)| {
+                    (
+                        usize::from(id),
+                        Piece {
+                            external_id: value.external_id.clone(),
+                            description: value.description.clone(),
+                            source_type: SourceType::Commission,
+                            media_type: MediaType::Image,
+                            added: value.added,
+                            base_price: value.base_price,
+                            tip_price: value.tip_price,
+                        },
+                    )
+                })
+                .collect(),
+            blobs: value
+                .blobs
+                .iter()
+                .map(|(id, value)| (usize::from(id), value.clone().into()))
+                .collect(),
+            tags: value.tags,
+            categories: value.categories,
+            saved_searches: Table::default(),
+            collections: Table::default(),
+            media: value
+                .media
+                .into_iter()
+                .map(|(lhs, rhs)| (usize::from(lhs).into(), usize::from(rhs).into()))
+                .collect(),
+            piece_tags: value
+                .piece_tags
+                .into_iter()
+                .map(|(lhs, rhs)| (usize::from(lhs).into(), rhs))
+                .collect(),
+            tag_category: value.tag_category,
+            relations: BTreeSet::new(),
+            inbox: Inbox::default(),
+            review: BTreeSet::new(),
+            storage_layout: StorageLayout::Flat,
+        }
+    }
+}
+
+impl DbV3 {
+    pub fn create_blob(&mut self, data: Blob) -> BlobId {
+        self.blobs.insert(data)
+    }
+
+    pub fn attach_blob(&mut self, AttachBlob { src, dest }: AttachBlob) -> bool {
+        self.media.insert((src, dest))
+    }
+
+    pub fn attach_category(&mut self, AttachCategory { src, dest }: AttachCategory) -> bool {
+        match dest {
+            Some(new_category) => {
+                self.tag_category.insert(src, new_category);
+                true
+            }
+            None => self.tag_category.remove(&src).is_some(),
+        }
+    }
+    pub fn attach_tag(&mut self, AttachTag { src, dest }: AttachTag) -> bool {
+        self.piece_tags.insert((src, dest))
+    }
+
+    pub fn remove_tag(&mut self, AttachTag { src, dest }: AttachTag) -> bool {
+        self.piece_tags.remove(&(src, dest))
+    }
+
+    /// Pieces can't be related to themselves, or to pieces that don't exist.
+    pub fn relate(&mut self, relation: Relation) -> bool {
+        relation.src != relation.dest
+            && self.exists(relation.src)
+            && self.exists(relation.dest)
+            && self.relations.insert(relation)
+    }
+    pub fn unrelate(&mut self, relation: Relation) -> bool {
+        self.relations.remove(&relation)
+    }
+
+    /// Moves the blobs, tags, relations and collection entries of `src` over to `dest`,
+    /// then deletes `src`. Otherwise `dest` keeps its own details, only taking the
+    /// description of `src` if it has none.
+    pub fn merge_piece(&mut self, MergePiece { src, dest }: MergePiece) -> bool {
+        if src == dest || !self.exists(src) || !self.exists(dest) {
+            return false;
+        }
+
+        let blobs = self.blobs_for_piece(src).collect::<Vec<_>>();
+        self.media
+            .extend(blobs.into_iter().map(|blob_id| (dest, blob_id)));
+        let tags = self.tags_for_piece(src).collect::<Vec<_>>();
+        self.piece_tags
+            .extend(tags.into_iter().map(|tag_id| (dest, tag_id)));
+        let relations = self.relations_for_piece(src).collect::<Vec<_>>();
+        for relation in relations {
+            let moved = |piece_id| if piece_id == src { dest } else { piece_id };
+            self.relate(Relation {
+                src: moved(relation.src),
+                dest: moved(relation.dest),
+                ..relation
+            });
+        }
+        for collection in self.collections.values_mut() {
+            let mut seen = BTreeSet::new();
+            collection.entries.retain(|entry| {
+                let piece = if entry.piece == src {
+                    dest
+                } else {
+                    entry.piece
+                };
+                seen.insert((piece, entry.blob))
+            });
+            for entry in &mut collection.entries {
+                if entry.piece == src {
+                    entry.piece = dest;
+                }
+            }
+        }
+        if self[dest].description.trim().is_empty() {
+            let description = std::mem::take(&mut self.pieces.get_mut(src).unwrap().description);
+            self.pieces.get_mut(dest).unwrap().description = description;
+        }
+
+        self.delete(src)
+    }
+
+    pub fn create_piece(&mut self, data: Piece) -> PieceId {
+        self.pieces.insert(data)
+    }
+    pub fn create_tag(&mut self, data: Tag) -> TagId {
+        self.tags.insert(data)
+    }
+    pub fn create_category(&mut self, data: Category) -> CategoryId {
+        self.categories.insert(data)
+    }
+    pub fn create_saved_search(&mut self, data: SavedSearch) -> SavedSearchId {
+        self.saved_searches.insert(data)
+    }
+    pub fn create_collection(&mut self, data: Collection) -> CollectionId {
+        self.collections.insert(data)
+    }
+
+    pub fn find_tag_pair(&self, raw: &str) -> Option<TagId> {
+        let (tag_name, category_id) = if let Some((category_name, tag_name)) = raw.split_once(':') {
+            (
+                tag_name,
+                Some(
+                    self.categories()
+                        .find(|(_, category)| category.name == category_name)
+                        .map(|(category_id, _)| category_id)?,
+                ),
+            )
+        } else {
+            (raw, None)
+        };
+
+        self.tags()
+            .filter(|(tag_id, _)| self.category_for_tag(*tag_id) == category_id)
+            .find(|(_, tag)| tag.name == tag_name)
+            .map(|(id, _)| id)
+    }
+
+    pub fn find_saved_search(&self, name: &str) -> Option<SavedSearchId> {
+        self.saved_searches()
+            .find(|(_, saved)| saved.name == name)
+            .map(|(id, _)| id)
+    }
+
+    pub fn blobs_for_piece(&self, piece_id: PieceId) -> impl Iterator<Item = BlobId> + Clone + '_ {
+        self.media
+            .iter()
+            .filter(move |(id, _)| id == &piece_id)
+            .map(|(_, id)| *id)
+    }
+    pub fn pieces_for_blob(&self, blob_id: BlobId) -> impl Iterator<Item = PieceId> + Clone + '_ {
+        self.media
+            .iter()
+            .filter(move |(_, id)| id == &blob_id)
+            .map(|(id, _)| *id)
+    }
+
+    pub fn primary_blob_for_piece(&self, piece_id: PieceId) -> Option<BlobId> {
+        self.blobs_for_piece(piece_id)
+            .find(|blob_id| self.blobs[*blob_id].blob_type == BlobType::Canon)
+    }
+
+    /// Looked up by range, since tags are stored sorted by piece.
+    pub fn tags_for_piece(&self, piece_id: PieceId) -> impl Iterator<Item = TagId> + Clone + '_ {
+        self.piece_tags
+            .range((piece_id, TagId::from(0))..)
+            .take_while(move |(id, _)| id == &piece_id)
+            .map(|(_, id)| *id)
+    }
+
+    pub fn pieces_for_tag(&self, tag_id: TagId) -> impl Iterator<Item = PieceId> + Clone + '_ {
+        self.piece_tags
+            .iter()
+            .filter(move |(_, id)| id == &tag_id)
+            .map(|(id, _)| *id)
+    }
+
+    pub fn tags_for_category(
+        &self,
+        category_id: CategoryId,
+    ) -> impl Iterator<Item = TagId> + Clone + '_ {
+        self.tag_category
+            .iter()
+            .filter(move |(_, id)| **id == category_id)
+            .map(|(id, _)| *id)
+    }
+
+    pub fn category_for_tag(&self, tag_id: TagId) -> Option<CategoryId> {
+        self.tag_category.get(&tag_id).copied()
+    }
+
+    /// Relations in either direction.
+    pub fn relations_for_piece(
+        &self,
+        piece_id: PieceId,
+    ) -> impl Iterator<Item = Relation> + Clone + '_ {
+        self.relations
+            .iter()
+            .filter(move |relation| relation.other(piece_id).is_some())
+            .copied()
+    }
+
+    pub fn collections_for_piece(
+        &self,
+        piece_id: PieceId,
+    ) -> impl Iterator<Item = CollectionId> + '_ {
+        self.collections
+            .iter()
+            .filter(move |(_, collection)| {
+                collection
+                    .entries
+                    .iter()
+                    .any(|entry| entry.piece == piece_id)
+            })
+            .map(|(id, _)| id)
+    }
+
+    /// The blobs making up a collection, in order.
+    /// Entries without a specific blob contribute all of their piece's blobs.
+    pub fn blobs_for_collection(
+        &self,
+        collection_id: CollectionId,
+    ) -> impl Iterator<Item = BlobId> + '_ {
+        self.collections[collection_id]
+            .entries
+            .iter()
+            .flat_map(move |entry| match entry.blob {
+                Some(blob_id) => vec![blob_id],
+                None => self.blobs_for_piece(entry.piece).collect(),
+            })
+    }
+
+    pub fn pieces(&self) -> impl Iterator<Item = (PieceId, &'_ Piece)> {
+        self.pieces.iter()
+    }
+    pub fn blobs(&self) -> impl Iterator<Item = (BlobId, &'_ Blob)> {
+        self.blobs.iter()
+    }
+    pub fn tags(&self) -> impl Iterator<Item = (TagId, &'_ Tag)> {
+        self.tags.iter()
+    }
+    pub fn categories(&self) -> impl Iterator<Item = (CategoryId, &'_ Category)> {
+        self.categories.iter()
+    }
+    pub fn saved_searches(&self) -> impl Iterator<Item = (SavedSearchId, &'_ SavedSearch)> {
+        self.saved_searches.iter()
+    }
+    pub fn collections(&self) -> impl Iterator<Item = (CollectionId, &'_ Collection)> {
+        self.collections.iter()
+    }
+
+    /// Where the blob's file is, relative to the database root.
+    pub fn storage_for(&self, id: BlobId) -> PathBuf {
+        let blob = &self.blobs[id];
+        blob.location
+            .clone()
+            .unwrap_or_else(|| blob.storage_name(id))
+    }
+
+    /// Where the blob's file belongs under the current layout, relative to the database root.
+    pub fn layout_path(&self, id: BlobId) -> PathBuf {
+        self.storage_layout
+            .path_for(id, &self.blobs[id], self.pieces_for_blob(id).next())
+    }
+
+    /// Records where the blob's file is now, so renaming the blob leaves the file alone.
+    pub fn pin_storage(&mut self, id: BlobId) -> bool {
+        let location = self.storage_for(id);
+        match self.blobs.get_mut(id) {
+            Some(blob) => {
+                blob.location = Some(location);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn exists<Id: IdExist<Self>>(&self, id: Id) -> bool {
+        id.exists_in(self)
+    }
+
+    pub fn delete<Id: DeleteFrom<Self>>(&mut self, id: Id) -> bool {
+        id.delete_from(self)
+    }
+
+    pub fn edit<Data: EditFrom<Self>>(&mut self, data: Data) -> bool {
+        data.edit_from(self)
+    }
+}
@@ -0,0 +1,70 @@
+use std::{
+    ffi::OsStr,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+
+use crate::{serialized, table::TableId, BlobType, MediaType};
+
+pub type BlobId = TableId<Blob>;
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Blob {
+    pub file_name: String,
+    pub hash: u64,
+    pub blob_type: BlobType,
+    pub added: NaiveDate,
+    /// What the file's contents look like when it was imported.
+    /// None for blobs from older databases, which go by their extension instead.
+    pub content_type: Option<MediaType>,
+    /// How long it plays for, if it's a video or audio.
+    pub duration: Option<Duration>,
+    /// Where the file is, relative to the database root. Set when the file's stored, so
+    /// changing the layout or renaming the blob doesn't lose track of it.
+    /// None for blobs from older databases, which are where [`Blob::storage_name`] says.
+    pub location: Option<PathBuf>,
+}
+
+impl Blob {
+    pub fn storage_name(&self, id: BlobId) -> PathBuf {
+        format!("[{}] {}", id, self.file_name).parse().unwrap()
+    }
+
+    pub fn media_type(&self) -> MediaType {
+        self.content_type
+            .unwrap_or_else(|| match self.extension().as_deref() {
+                Some("txt" | "md" | "markdown") => MediaType::Text,
+                Some("mp4" | "m4v" | "mov" | "webm" | "mkv" | "avi") => MediaType::Video,
+                Some("mp3" | "m4a" | "wav" | "ogg" | "opus" | "flac") => MediaType::Audio,
+                _ => MediaType::Image,
+            })
+    }
+
+    pub fn is_markdown(&self) -> bool {
+        matches!(self.extension().as_deref(), Some("md" | "markdown"))
+    }
+
+    pub(crate) fn extension(&self) -> Option<String> {
+        Path::new(&self.file_name)
+            .extension()
+            .and_then(OsStr::to_str)
+            .map(str::to_ascii_lowercase)
+    }
+}
+
+impl From<serialized::blob::Blob> for Blob {
+    fn from(value: serialized::blob::Blob) -> Self {
+        Self {
+            file_name: value.file_name,
+            hash: value.hash,
+            blob_type: value.blob_type,
+            added: value.added,
+            content_type: None,
+            duration: None,
+            location: None,
+        }
+    }
+}
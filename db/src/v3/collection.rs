@@ -0,0 +1,40 @@
+use crate::{table::TableId, BlobId, PieceId};
+
+use chrono::{Local, NaiveDate};
+use serde::{Deserialize, Serialize};
+
+pub type CollectionId = TableId<Collection>;
+
+/// A hand-picked, ordered list of pieces, e.g. the pages of a comic.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
+pub struct Collection {
+    pub name: String,
+    pub description: String,
+    pub added: NaiveDate,
+    pub entries: Vec<CollectionEntry>,
+}
+
+impl Default for Collection {
+    fn default() -> Self {
+        Self {
+            name: "new_collection".to_string(),
+            description: String::new(),
+            added: Local::today().naive_local(),
+            entries: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone, Copy)]
+pub struct CollectionEntry {
+    pub piece: PieceId,
+    /// Only this blob of the piece is part of the collection.
+    /// If there isn't one, the whole piece is.
+    pub blob: Option<BlobId>,
+}
+
+impl From<PieceId> for CollectionEntry {
+    fn from(piece: PieceId) -> Self {
+        Self { piece, blob: None }
+    }
+}
@@ -1,4 +1,7 @@
-use super::{Blob, BlobId, Category, CategoryId, Piece, PieceId, Tag, TagId};
+use super::{
+    Blob, BlobId, Category, CategoryId, Collection, CollectionId, Piece, PieceId, SavedSearch,
+    SavedSearchId, Tag, TagId,
+};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct Attach<Left, Right> {
@@ -6,6 +9,13 @@ pub struct Attach<Left, Right> {
     pub dest: Right,
 }
 
+/// Merges `src` into `dest`, which is kept.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MergePiece {
+    pub src: PieceId,
+    pub dest: PieceId,
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct Edit<DataId, Data> {
     pub id: DataId,
@@ -20,3 +30,5 @@ pub type EditPiece = Edit<PieceId, Piece>;
 pub type EditBlob = Edit<BlobId, Blob>;
 pub type EditTag = Edit<TagId, Tag>;
 pub type EditCategory = Edit<CategoryId, Category>;
+pub type EditSavedSearch = Edit<SavedSearchId, SavedSearch>;
+pub type EditCollection = Edit<CollectionId, Collection>;
@@ -0,0 +1,14 @@
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+
+/// A folder new files are imported from automatically.
+#[derive(Debug, Serialize, Deserialize, Default, PartialEq, Eq, Clone)]
+pub struct Inbox {
+    /// None while nothing is being watched.
+    pub dir: Option<PathBuf>,
+    /// A regular expression matched against the names of new files. The first capture group,
+    /// or the whole match without one, is the external ID of the piece the file is added to.
+    /// Files that don't match, or match an external ID no piece has, get a new piece.
+    pub external_id_pattern: String,
+}
@@ -0,0 +1,287 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use serde::{Deserialize, Serialize};
+
+use super::{
+    Blob, BlobId, Collection, CollectionId, DbV3, Inbox, Piece, PieceId, Relation, SavedSearch,
+    SavedSearchId, StorageLayout,
+};
+use crate::{
+    table::{Table, TableId},
+    Category, CategoryId, Tag, TagId,
+};
+
+/// One change to a database, as written to its journal.
+///
+/// Changes say what something is now rather than how it got there, so applying the same
+/// ones twice in a row leaves a database as applying them once did.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub enum Change {
+    /// The row as it is now, or None once it's deleted.
+    Piece(PieceId, Option<Piece>),
+    Blob(BlobId, Option<Blob>),
+    Tag(TagId, Option<Tag>),
+    Category(CategoryId, Option<Category>),
+    SavedSearch(SavedSearchId, Option<SavedSearch>),
+    Collection(CollectionId, Option<Collection>),
+    /// Whether the link is there now.
+    Media(PieceId, BlobId, bool),
+    PieceTag(PieceId, TagId, bool),
+    Relation(Relation, bool),
+    Review(PieceId, bool),
+    TagCategory(TagId, Option<CategoryId>),
+    Inbox(Inbox),
+    StorageLayout(StorageLayout),
+}
+
+impl DbV3 {
+    /// The changes that turn `before` into this database.
+    pub fn changes_since(&self, before: &DbV3) -> Vec<Change> {
+        let mut changes = Vec::new();
+
+        diff_table(&before.pieces, &self.pieces, &mut changes, Change::Piece);
+        diff_table(&before.blobs, &self.blobs, &mut changes, Change::Blob);
+        diff_table(&before.tags, &self.tags, &mut changes, Change::Tag);
+        diff_table(
+            &before.categories,
+            &self.categories,
+            &mut changes,
+            Change::Category,
+        );
+        diff_table(
+            &before.saved_searches,
+            &self.saved_searches,
+            &mut changes,
+            Change::SavedSearch,
+        );
+        diff_table(
+            &before.collections,
+            &self.collections,
+            &mut changes,
+            Change::Collection,
+        );
+
+        diff_set(
+            &before.media,
+            &self.media,
+            &mut changes,
+            |(piece, blob), now| Change::Media(*piece, *blob, now),
+        );
+        diff_set(
+            &before.piece_tags,
+            &self.piece_tags,
+            &mut changes,
+            |(piece, tag), now| Change::PieceTag(*piece, *tag, now),
+        );
+        diff_set(
+            &before.relations,
+            &self.relations,
+            &mut changes,
+            |relation, now| Change::Relation(*relation, now),
+        );
+        diff_set(&before.review, &self.review, &mut changes, |piece, now| {
+            Change::Review(*piece, now)
+        });
+        diff_map(&before.tag_category, &self.tag_category, &mut changes);
+
+        if self.inbox != before.inbox {
+            changes.push(Change::Inbox(self.inbox.clone()));
+        }
+        if self.storage_layout != before.storage_layout {
+            changes.push(Change::StorageLayout(self.storage_layout));
+        }
+
+        changes
+    }
+
+    pub fn apply(&mut self, change: Change) {
+        match change {
+            Change::Piece(id, row) => apply_row(&mut self.pieces, id, row),
+            Change::Blob(id, row) => apply_row(&mut self.blobs, id, row),
+            Change::Tag(id, row) => apply_row(&mut self.tags, id, row),
+            Change::Category(id, row) => apply_row(&mut self.categories, id, row),
+            Change::SavedSearch(id, row) => apply_row(&mut self.saved_searches, id, row),
+            Change::Collection(id, row) => apply_row(&mut self.collections, id, row),
+            Change::Media(piece, blob, now) => apply_link(&mut self.media, (piece, blob), now),
+            Change::PieceTag(piece, tag, now) => {
+                apply_link(&mut self.piece_tags, (piece, tag), now)
+            }
+            Change::Relation(relation, now) => apply_link(&mut self.relations, relation, now),
+            Change::Review(piece, now) => apply_link(&mut self.review, piece, now),
+            Change::TagCategory(tag, Some(category)) => {
+                self.tag_category.insert(tag, category);
+            }
+            Change::TagCategory(tag, None) => {
+                self.tag_category.remove(&tag);
+            }
+            Change::Inbox(inbox) => self.inbox = inbox,
+            Change::StorageLayout(layout) => self.storage_layout = layout,
+        }
+    }
+}
+
+fn diff_table<T: Clone + PartialEq>(
+    before: &Table<T>,
+    after: &Table<T>,
+    changes: &mut Vec<Change>,
+    change: impl Fn(TableId<T>, Option<T>) -> Change,
+) {
+    for (id, row) in after.iter() {
+        if before.get(id) != Some(row) {
+            changes.push(change(id, Some(row.clone())));
+        }
+    }
+    for id in before.keys().filter(|id| !after.has(*id)) {
+        changes.push(change(id, None));
+    }
+}
+
+fn diff_set<T: Ord>(
+    before: &BTreeSet<T>,
+    after: &BTreeSet<T>,
+    changes: &mut Vec<Change>,
+    change: impl Fn(&T, bool) -> Change,
+) {
+    changes.extend(after.difference(before).map(|added| change(added, true)));
+    changes.extend(
+        before
+            .difference(after)
+            .map(|removed| change(removed, false)),
+    );
+}
+
+fn diff_map(
+    before: &BTreeMap<TagId, CategoryId>,
+    after: &BTreeMap<TagId, CategoryId>,
+    changes: &mut Vec<Change>,
+) {
+    let tags = before.keys().chain(after.keys()).collect::<BTreeSet<_>>();
+    for tag in tags {
+        if before.get(tag) != after.get(tag) {
+            changes.push(Change::TagCategory(*tag, after.get(tag).copied()));
+        }
+    }
+}
+
+fn apply_row<T>(table: &mut Table<T>, id: TableId<T>, row: Option<T>) {
+    match row {
+        Some(row) => table.set(id, row),
+        None if table.has(id) => {
+            table.remove(id);
+        }
+        None => {}
+    }
+}
+
+fn apply_link<T: Ord>(links: &mut BTreeSet<T>, link: T, now: bool) {
+    if now {
+        links.insert(link);
+    } else {
+        links.remove(&link);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::v3::{
+        commands::{AttachCategory, AttachTag},
+        RelationType,
+    };
+
+    fn piece(description: &str) -> Piece {
+        Piece {
+            description: description.to_string(),
+            ..Piece::default()
+        }
+    }
+
+    /// Applies `changes` to a copy of `db`, checking applying them twice changes nothing more.
+    fn replay(db: &DbV3, changes: &[Change]) -> DbV3 {
+        let mut once = db.clone();
+        for change in changes {
+            once.apply(change.clone());
+        }
+        let mut twice = once.clone();
+        for change in changes {
+            twice.apply(change.clone());
+        }
+        assert_eq!(
+            crate::to_bytes(&once).unwrap(),
+            crate::to_bytes(&twice).unwrap()
+        );
+        once
+    }
+
+    #[test]
+    fn test_apply() {
+        let mut before = DbV3::default();
+        let first = before.create_piece(piece("first"));
+        let second = before.create_piece(piece("second"));
+        let tag = before.create_tag(Tag::default());
+        let category = before.create_category(Category::default());
+        before.attach_tag(AttachTag {
+            src: first,
+            dest: tag,
+        });
+        before.attach_category(AttachCategory {
+            src: tag,
+            dest: Some(category),
+        });
+        before.review.insert(second);
+
+        let mut after = before.clone();
+        after.pieces.get_mut(second).unwrap().description = "edited".to_string();
+        // the new piece takes the ID of the deleted one
+        after.delete(first);
+        let third = after.create_piece(piece("third"));
+        assert_eq!(third, first);
+        after.attach_tag(AttachTag {
+            src: second,
+            dest: tag,
+        });
+        after.relate(Relation {
+            src: second,
+            kind: RelationType::Sequel,
+            dest: third,
+        });
+        after.attach_category(AttachCategory {
+            src: tag,
+            dest: None,
+        });
+        after.review.remove(&second);
+        after.storage_layout = StorageLayout::Sharded;
+
+        let changes = after.changes_since(&before);
+        let replayed = replay(&before, &changes);
+        assert!(after.changes_since(&replayed).is_empty());
+        assert_eq!(
+            crate::to_bytes(&replayed).unwrap(),
+            crate::to_bytes(&after).unwrap()
+        );
+        assert!(replayed.tags_for_piece(third).next().is_none());
+        assert_eq!(replayed.category_for_tag(tag), None);
+
+        // and back again
+        let undone = replay(&after, &before.changes_since(&after));
+        assert_eq!(
+            crate::to_bytes(&undone).unwrap(),
+            crate::to_bytes(&before).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_apply_out_of_order() {
+        // rows can be put back past the end of a table, as when replaying onto an older snapshot
+        let mut db = DbV3::default();
+        db.create_piece(piece("first"));
+        let later = PieceId::from(3);
+        db.apply(Change::Piece(later, Some(piece("later"))));
+        assert_eq!(db[later].description, "later");
+        assert_eq!(db.pieces().count(), 2);
+
+        db.apply(Change::Piece(later, None));
+        db.apply(Change::Piece(later, None));
+        assert!(!db.exists(later));
+    }
+}
@@ -0,0 +1,423 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt,
+};
+
+use super::{BlobId, CollectionEntry, CollectionId, DbV3, PieceId, Relation, SavedSearchId};
+use crate::{
+    table::{Table, TableId},
+    CategoryId, TagId,
+};
+
+/// Where each of our rows ended up in the merged table. Rows deleted there are missing.
+type IdMap<T> = BTreeMap<TableId<T>, TableId<T>>;
+
+impl DbV3 {
+    /// Combines two copies of a database that were both edited since they were `base`,
+    /// e.g. when someone else saved over the file while it was open here.
+    ///
+    /// `theirs` is kept, with our changes made on top of it. Rows we added get new IDs
+    /// if theirs took ours, and rows we edited or deleted are edited or deleted there too.
+    ///
+    /// Rows are only told apart by ID, and IDs of deleted rows are handed out again,
+    /// so a row changed on both sides might not even be the same row on both.
+    /// Rather than guess, that's refused, as is one side changing what's attached
+    /// to a row the other changed.
+    pub fn merge(base: &DbV3, ours: &DbV3, theirs: DbV3) -> Result<DbV3, MergeConflict> {
+        let (our_rows, their_rows) = (Touched::new(base, ours), Touched::new(base, &theirs));
+        if our_rows.overlaps(&their_rows)
+            || their_rows.links_changed(base, ours)
+            || our_rows.links_changed(base, &theirs)
+        {
+            return Err(MergeConflict);
+        }
+
+        let mut merged = theirs;
+
+        let (pieces, deleted_pieces) =
+            merge_table(&base.pieces, &ours.pieces, &mut merged.pieces, Clone::clone);
+        let (blobs, deleted_blobs) =
+            merge_table(&base.blobs, &ours.blobs, &mut merged.blobs, Clone::clone);
+        let (tags, deleted_tags) =
+            merge_table(&base.tags, &ours.tags, &mut merged.tags, Clone::clone);
+        let (categories, deleted_categories) = merge_table(
+            &base.categories,
+            &ours.categories,
+            &mut merged.categories,
+            Clone::clone,
+        );
+        let (_, deleted_searches) = merge_table(
+            &base.saved_searches,
+            &ours.saved_searches,
+            &mut merged.saved_searches,
+            Clone::clone,
+        );
+        let (_, deleted_collections) = merge_table(
+            &base.collections,
+            &ours.collections,
+            &mut merged.collections,
+            |collection| {
+                let mut collection = collection.clone();
+                collection.entries = collection
+                    .entries
+                    .iter()
+                    .filter_map(|entry| {
+                        Some(CollectionEntry {
+                            piece: *pieces.get(&entry.piece)?,
+                            blob: match entry.blob {
+                                Some(blob) => Some(*blobs.get(&blob)?),
+                                None => None,
+                            },
+                        })
+                    })
+                    .collect();
+                collection
+            },
+        );
+
+        merge_set(
+            &base.media,
+            &ours.media,
+            &mut merged.media,
+            |(piece, blob)| Some((*pieces.get(piece)?, *blobs.get(blob)?)),
+        );
+        merge_set(
+            &base.piece_tags,
+            &ours.piece_tags,
+            &mut merged.piece_tags,
+            |(piece, tag)| Some((*pieces.get(piece)?, *tags.get(tag)?)),
+        );
+        merge_set(
+            &base.relations,
+            &ours.relations,
+            &mut merged.relations,
+            |relation| {
+                Some(Relation {
+                    src: *pieces.get(&relation.src)?,
+                    kind: relation.kind,
+                    dest: *pieces.get(&relation.dest)?,
+                })
+            },
+        );
+        merge_set(&base.review, &ours.review, &mut merged.review, |piece| {
+            pieces.get(piece).copied()
+        });
+
+        let changed = base
+            .tag_category
+            .keys()
+            .chain(ours.tag_category.keys())
+            .filter(|tag| base.tag_category.get(tag) != ours.tag_category.get(tag))
+            .collect::<BTreeSet<_>>();
+        for tag in changed {
+            match ours.tag_category.get(tag) {
+                Some(category) => {
+                    if let (Some(tag), Some(category)) = (tags.get(tag), categories.get(category)) {
+                        merged.tag_category.insert(*tag, *category);
+                    }
+                }
+                None => {
+                    merged.tag_category.remove(tag);
+                }
+            }
+        }
+
+        if ours.inbox != base.inbox {
+            merged.inbox = ours.inbox.clone();
+        }
+        if ours.storage_layout != base.storage_layout {
+            merged.storage_layout = ours.storage_layout;
+        }
+
+        // deleted last, so everything attached to them goes too
+        for id in deleted_pieces {
+            merged.delete(id);
+        }
+        for id in deleted_blobs {
+            merged.delete(id);
+        }
+        for id in deleted_tags {
+            merged.delete(id);
+        }
+        for id in deleted_categories {
+            merged.delete(id);
+        }
+        for id in deleted_searches {
+            merged.delete(id);
+        }
+        for id in deleted_collections {
+            merged.delete(id);
+        }
+
+        Ok(merged)
+    }
+}
+
+/// Both copies changed the same rows, or what's attached to them, so they can't be merged.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MergeConflict;
+
+impl fmt::Display for MergeConflict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Both copies changed the same pieces, blobs, tags or categories, so they can't be merged."
+        )
+    }
+}
+
+impl std::error::Error for MergeConflict {}
+
+/// The rows of the base one side edited or deleted, including any deleted and then reused.
+struct Touched {
+    pieces: BTreeSet<PieceId>,
+    blobs: BTreeSet<BlobId>,
+    tags: BTreeSet<TagId>,
+    categories: BTreeSet<CategoryId>,
+    saved_searches: BTreeSet<SavedSearchId>,
+    collections: BTreeSet<CollectionId>,
+}
+
+impl Touched {
+    fn new(base: &DbV3, side: &DbV3) -> Self {
+        Self {
+            pieces: touched(&base.pieces, &side.pieces),
+            blobs: touched(&base.blobs, &side.blobs),
+            tags: touched(&base.tags, &side.tags),
+            categories: touched(&base.categories, &side.categories),
+            saved_searches: touched(&base.saved_searches, &side.saved_searches),
+            collections: touched(&base.collections, &side.collections),
+        }
+    }
+
+    fn overlaps(&self, other: &Self) -> bool {
+        !self.pieces.is_disjoint(&other.pieces)
+            || !self.blobs.is_disjoint(&other.blobs)
+            || !self.tags.is_disjoint(&other.tags)
+            || !self.categories.is_disjoint(&other.categories)
+            || !self.saved_searches.is_disjoint(&other.saved_searches)
+            || !self.collections.is_disjoint(&other.collections)
+    }
+
+    /// Whether `side` attached anything to or detached anything from these rows since `base`.
+    fn links_changed(&self, base: &DbV3, side: &DbV3) -> bool {
+        let piece = |piece| self.pieces.contains(piece);
+        let blob = |blob| self.blobs.contains(blob);
+        let tag_category = |db: &DbV3| {
+            db.tag_category
+                .iter()
+                .map(|(tag_id, category_id)| (*tag_id, *category_id))
+                .collect::<BTreeSet<_>>()
+        };
+        let entries = |db: &DbV3| {
+            db.collections
+                .iter()
+                .flat_map(|(id, collection)| {
+                    collection
+                        .entries
+                        .iter()
+                        .map(move |entry| (id, entry.piece, entry.blob))
+                })
+                .collect::<BTreeSet<_>>()
+        };
+
+        base.media
+            .symmetric_difference(&side.media)
+            .any(|(piece_id, blob_id)| piece(piece_id) || blob(blob_id))
+            || base
+                .piece_tags
+                .symmetric_difference(&side.piece_tags)
+                .any(|(piece_id, tag_id)| piece(piece_id) || self.tags.contains(tag_id))
+            || base
+                .relations
+                .symmetric_difference(&side.relations)
+                .any(|relation| piece(&relation.src) || piece(&relation.dest))
+            || base.review.symmetric_difference(&side.review).any(piece)
+            || tag_category(base)
+                .symmetric_difference(&tag_category(side))
+                .any(|(tag_id, category_id)| {
+                    self.tags.contains(tag_id) || self.categories.contains(category_id)
+                })
+            || entries(base)
+                .symmetric_difference(&entries(side))
+                .any(|(_, piece_id, blob_id)| piece(piece_id) || blob_id.as_ref().is_some_and(blob))
+    }
+}
+
+fn touched<T: PartialEq>(base: &Table<T>, side: &Table<T>) -> BTreeSet<TableId<T>> {
+    base.iter()
+        .filter(|(id, row)| side.get(*id) != Some(*row))
+        .map(|(id, _)| id)
+        .collect()
+}
+
+/// Makes our changes to one table in `merged`, with `fix` pointing a row we added or edited
+/// at where what it refers to ended up. Returns where our rows ended up, and the rows we
+/// deleted that should be deleted from `merged`.
+fn merge_table<T: Clone + PartialEq>(
+    base: &Table<T>,
+    ours: &Table<T>,
+    merged: &mut Table<T>,
+    fix: impl Fn(&T) -> T,
+) -> (IdMap<T>, Vec<TableId<T>>) {
+    let mut ids = IdMap::new();
+    for (id, row) in ours.iter() {
+        match base.get(id) {
+            Some(original) => {
+                if let Some(theirs) = merged.get_mut(id) {
+                    if row != original {
+                        *theirs = fix(row);
+                    }
+                    ids.insert(id, id);
+                }
+            }
+            None => {
+                ids.insert(id, merged.insert(fix(row)));
+            }
+        }
+    }
+
+    let deleted = base
+        .iter()
+        .filter(|(id, original)| !ours.has(*id) && merged.get(*id) == Some(*original))
+        .map(|(id, _)| id)
+        .collect();
+    (ids, deleted)
+}
+
+/// Adds what we added to `merged`, as `fix` says it's known there, and removes what we removed.
+fn merge_set<T: Ord + Clone>(
+    base: &BTreeSet<T>,
+    ours: &BTreeSet<T>,
+    merged: &mut BTreeSet<T>,
+    fix: impl Fn(&T) -> Option<T>,
+) {
+    for added in ours.difference(base) {
+        if let Some(added) = fix(added) {
+            merged.insert(added);
+        }
+    }
+    for removed in base.difference(ours) {
+        merged.remove(removed);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{v3::commands::AttachTag, Piece, Tag};
+
+    fn piece(description: &str) -> Piece {
+        Piece {
+            description: description.to_string(),
+            ..Piece::default()
+        }
+    }
+
+    /// Two pieces, the first tagged.
+    fn base() -> (DbV3, PieceId, PieceId, TagId) {
+        let mut db = DbV3::default();
+        let first = db.create_piece(piece("first"));
+        let second = db.create_piece(piece("second"));
+        let tag = db.create_tag(Tag::default());
+        db.attach_tag(AttachTag {
+            src: first,
+            dest: tag,
+        });
+        (db, first, second, tag)
+    }
+
+    #[test]
+    fn test_merge_adds() {
+        let (base, first, _, tag) = base();
+        let (mut ours, mut theirs) = (base.clone(), base.clone());
+        let mine = ours.create_piece(piece("mine"));
+        ours.attach_tag(AttachTag {
+            src: mine,
+            dest: tag,
+        });
+        let yours = theirs.create_piece(piece("theirs"));
+        // both took the same ID
+        assert_eq!(mine, yours);
+
+        let merged = DbV3::merge(&base, &ours, theirs).unwrap();
+        let descriptions = merged
+            .pieces()
+            .map(|(_, piece)| piece.description.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(descriptions, ["first", "second", "theirs", "mine"]);
+        let (mine, _) = merged
+            .pieces()
+            .find(|(_, piece)| piece.description == "mine")
+            .unwrap();
+        assert_eq!(merged.tags_for_piece(mine).collect::<Vec<_>>(), [tag]);
+        assert_eq!(merged.pieces_for_tag(tag).count(), 2);
+        assert!(merged.tags_for_piece(first).eq([tag]));
+    }
+
+    #[test]
+    fn test_merge_edits_and_removes() {
+        let (base, first, second, tag) = base();
+        let (mut ours, mut theirs) = (base.clone(), base.clone());
+        ours.pieces.get_mut(first).unwrap().description = "edited".to_string();
+        theirs.delete(second);
+
+        let merged = DbV3::merge(&base, &ours, theirs.clone()).unwrap();
+        assert_eq!(merged[first].description, "edited");
+        assert!(!merged.exists(second));
+        assert!(merged.tags_for_piece(first).eq([tag]));
+
+        // the other way around, our removal is made on top of their edit
+        let merged = DbV3::merge(&base, &theirs, ours).unwrap();
+        assert_eq!(merged[first].description, "edited");
+        assert!(!merged.exists(second));
+    }
+
+    #[test]
+    fn test_merge_same_row() {
+        let (base, first, _, _) = base();
+        let (mut ours, mut theirs) = (base.clone(), base.clone());
+        ours.pieces.get_mut(first).unwrap().description = "ours".to_string();
+        theirs.pieces.get_mut(first).unwrap().description = "theirs".to_string();
+        assert_eq!(DbV3::merge(&base, &ours, theirs), Err(MergeConflict));
+
+        let mut theirs = base.clone();
+        theirs.delete(first);
+        assert_eq!(DbV3::merge(&base, &ours, theirs), Err(MergeConflict));
+    }
+
+    #[test]
+    fn test_merge_reused_id() {
+        let (base, first, _, _) = base();
+        let (mut ours, mut theirs) = (base.clone(), base.clone());
+        // our new piece takes the ID of the one we deleted
+        ours.delete(first);
+        assert_eq!(ours.create_piece(piece("new")), first);
+        theirs.pieces.get_mut(first).unwrap().description = "edited".to_string();
+
+        assert_eq!(DbV3::merge(&base, &ours, theirs), Err(MergeConflict));
+    }
+
+    #[test]
+    fn test_merge_links() {
+        let (base, first, second, tag) = base();
+        let (mut ours, mut theirs) = (base.clone(), base.clone());
+        // tagging a piece they deleted
+        ours.attach_tag(AttachTag {
+            src: second,
+            dest: tag,
+        });
+        theirs.delete(second);
+        assert_eq!(DbV3::merge(&base, &ours, theirs), Err(MergeConflict));
+
+        // untagging a piece they edited
+        let mut ours = base.clone();
+        ours.remove_tag(AttachTag {
+            src: first,
+            dest: tag,
+        });
+        let mut theirs = base.clone();
+        theirs.pieces.get_mut(first).unwrap().description = "edited".to_string();
+        assert_eq!(DbV3::merge(&base, &ours, theirs), Err(MergeConflict));
+    }
+}
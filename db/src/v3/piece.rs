@@ -1,4 +1,4 @@
-use crate::table::TableId;
+use crate::{table::TableId, MediaType, SourceType};
 
 use chrono::{Local, NaiveDate};
 use serde::{Deserialize, Serialize};
@@ -9,6 +9,8 @@ pub type PieceId = TableId<Piece>;
 pub struct Piece {
     pub external_id: Option<String>,
     pub description: String,
+    pub source_type: SourceType,
+    pub media_type: MediaType,
     pub added: NaiveDate,
     pub base_price: Option<i64>,
     pub tip_price: Option<i64>,
@@ -19,6 +21,8 @@ impl Default for Piece {
         Self {
             external_id: None,
             description: String::new(),
+            source_type: SourceType::Commission,
+            media_type: MediaType::Image,
             added: Local::today().naive_local(),
             base_price: None,
             tip_price: None,
@@ -0,0 +1,66 @@
+use std::fmt::Display;
+
+use crate::PieceId;
+
+use serde::{Deserialize, Serialize};
+use strum::EnumIter;
+
+/// A typed link from `src` to `dest`.
+/// Relations are stored once, but apply in both directions.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Hash)]
+pub struct Relation {
+    pub src: PieceId,
+    pub kind: RelationType,
+    pub dest: PieceId,
+}
+
+impl Relation {
+    /// The piece on the other end from `piece_id`, if `piece_id` is part of this relation.
+    pub fn other(&self, piece_id: PieceId) -> Option<PieceId> {
+        if self.src == piece_id {
+            Some(self.dest)
+        } else if self.dest == piece_id {
+            Some(self.src)
+        } else {
+            None
+        }
+    }
+
+    /// Describes the other piece, as seen from `piece_id`.
+    pub fn describe(&self, piece_id: PieceId) -> &'static str {
+        match (self.kind, self.src == piece_id) {
+            (RelationType::Sequel, true) => "Sequel to",
+            (RelationType::Sequel, false) => "Followed by",
+            (RelationType::AltVersion, true) => "Alternate version of",
+            (RelationType::AltVersion, false) => "Alternate versions",
+            (RelationType::ReferenceFor, true) => "Reference for",
+            (RelationType::ReferenceFor, false) => "References",
+        }
+    }
+}
+
+#[derive(
+    Debug, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Hash, EnumIter,
+)]
+pub enum RelationType {
+    /// `src` follows on from `dest`.
+    Sequel,
+    /// `src` is a redraw or variant of `dest`.
+    AltVersion,
+    /// `src` was used as a reference when making `dest`.
+    ReferenceFor,
+}
+
+impl Display for RelationType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                RelationType::Sequel => "Sequel To",
+                RelationType::AltVersion => "Alternate Version Of",
+                RelationType::ReferenceFor => "Reference For",
+            }
+        )
+    }
+}
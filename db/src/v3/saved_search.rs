@@ -0,0 +1,25 @@
+use crate::table::TableId;
+
+use chrono::{Local, NaiveDate};
+use serde::{Deserialize, Serialize};
+
+pub type SavedSearchId = TableId<SavedSearch>;
+
+/// A named search, stored as the text the user typed so it can be edited
+/// and re-parsed as the syntax grows.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
+pub struct SavedSearch {
+    pub name: String,
+    pub query: String,
+    pub added: NaiveDate,
+}
+
+impl Default for SavedSearch {
+    fn default() -> Self {
+        Self {
+            name: "new_search".to_string(),
+            query: String::new(),
+            added: Local::today().naive_local(),
+        }
+    }
+}
@@ -0,0 +1,64 @@
+use std::{fmt::Display, path::PathBuf};
+
+use serde::{Deserialize, Serialize};
+use strum::{EnumIter, EnumString};
+
+use super::{Blob, BlobId, PieceId};
+
+/// Where new blobs' files are put, relative to the database root.
+#[derive(
+    Debug, Serialize, Deserialize, PartialEq, Eq, Clone, Copy, Default, EnumIter, EnumString,
+)]
+pub enum StorageLayout {
+    /// `[id] file_name`, all in the root. Every database used this before layouts existed.
+    #[default]
+    #[strum(serialize = "flat")]
+    Flat,
+    /// `blobs/ab/abcdef0123456789-id.ext`, grouped by the first byte of the content hash
+    /// so no one folder gets too big.
+    ///
+    /// The hash only spreads files evenly across the folders, it doesn't deduplicate them.
+    /// Every blob gets a file of its own, named after its ID as well, so blobs with the same
+    /// contents never share a file and deleting one never takes another's file with it.
+    #[strum(serialize = "sharded")]
+    Sharded,
+    /// `pieces/piece_id/[id] file_name`, a folder per piece.
+    #[strum(serialize = "by-piece")]
+    ByPiece,
+}
+
+impl StorageLayout {
+    /// Where the file of `blob` goes, given the piece it's attached to.
+    pub fn path_for(self, id: BlobId, blob: &Blob, piece: Option<PieceId>) -> PathBuf {
+        match self {
+            StorageLayout::Flat => blob.storage_name(id),
+            StorageLayout::Sharded => {
+                let hash = format!("{:016x}", blob.hash);
+                // the ID keeps blobs with the same contents apart, and the extension is kept so
+                // other programs know what the file is
+                let name = match blob.extension() {
+                    Some(extension) => format!("{}-{}.{}", hash, id, extension),
+                    None => format!("{}-{}", hash, id),
+                };
+                PathBuf::from("blobs").join(&hash[..2]).join(name)
+            }
+            StorageLayout::ByPiece => PathBuf::from("pieces")
+                .join(piece.map_or("unattached".to_string(), |piece_id| piece_id.to_string()))
+                .join(blob.storage_name(id)),
+        }
+    }
+}
+
+impl Display for StorageLayout {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                StorageLayout::Flat => "flat",
+                StorageLayout::Sharded => "sharded",
+                StorageLayout::ByPiece => "by-piece",
+            }
+        )
+    }
+}
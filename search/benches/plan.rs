@@ -0,0 +1,67 @@
+use chrono::{Local, NaiveDate};
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use db::{v3::commands::AttachTag, Db, Piece, Tag};
+use search::{parse_search, Plan, SearchIndex};
+
+const PIECES: usize = 2000;
+const TAGS: usize = 100;
+const TAGS_PER_PIECE: usize = 8;
+
+/// The same database every run, without pulling in a random number generator.
+fn database() -> Db {
+    let mut db = Db::default();
+    let tags = (0..TAGS)
+        .map(|tag| {
+            db.create_tag(Tag {
+                name: format!("tag_{}", tag),
+                ..Default::default()
+            })
+        })
+        .collect::<Vec<_>>();
+
+    let mut state = 0x2545_f491_u64;
+    for day in 0..PIECES {
+        let piece_id = db.create_piece(Piece {
+            added: NaiveDate::from_ymd(2020, 1, 1) + chrono::Duration::days(day as i64),
+            ..Default::default()
+        });
+        for _ in 0..TAGS_PER_PIECE {
+            state = state
+                .wrapping_mul(6_364_136_223_846_793_005)
+                .wrapping_add(1);
+            db.attach_tag(AttachTag {
+                src: piece_id,
+                dest: tags[(state >> 33) as usize % TAGS],
+            });
+        }
+    }
+    db
+}
+
+fn compare(c: &mut Criterion) {
+    let db = database();
+    let index = SearchIndex::build(&db);
+    let today = Local::today().naive_local();
+
+    let mut group = c.benchmark_group("search");
+    for input in &[
+        "tag_1",
+        "tag_1 tag_2|!tag_3",
+        "tag_1 tags:>8",
+        "tag_1* !tag_10",
+        "added:2021 (tag_5|tag_6)",
+    ] {
+        let (_, search) = parse_search(input).unwrap();
+
+        group.bench_with_input(BenchmarkId::new("naive", input), &search, |b, search| {
+            b.iter(|| search.execute_on(&db, &index, today).count())
+        });
+        group.bench_with_input(BenchmarkId::new("plan", input), &search, |b, search| {
+            b.iter(|| Plan::compile(search, &db).execute(&db, &index, today).len())
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, compare);
+criterion_main!(benches);
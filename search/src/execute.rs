@@ -1,60 +1,851 @@
-use db::{Db, Piece, PieceId};
+use std::{
+    cmp,
+    collections::hash_map::DefaultHasher,
+    convert::TryFrom,
+    hash::{Hash, Hasher},
+};
 
-use crate::model::{Condition, DateOp, Search};
+use chrono::{naive::MIN_DATE, Datelike, Duration, Local, NaiveDate};
+use db::{BlobId, Db, Piece, PieceId, TagId};
+
+use crate::{
+    index::{words, Document},
+    model::{
+        CompareOp, Condition, CountKey, DateOp, DateSpan, DateUnit, OrderKey, Ordering, PriceType,
+        Query, Search, Target,
+    },
+    Plan, SearchError, SearchIndex,
+};
+
+impl Query {
+    /// Returns the matching pieces, sorted according to the query's ordering,
+    /// or newest first if it doesn't have one.
+    pub fn execute(&self, db: &Db, index: &SearchIndex) -> Result<Vec<PieceId>, SearchError> {
+        self.execute_on(db, index, Local::today().naive_local())
+    }
+
+    /// Like [`Query::execute`], with relative dates counting back from `today`.
+    pub fn execute_on(
+        &self,
+        db: &Db,
+        index: &SearchIndex,
+        today: NaiveDate,
+    ) -> Result<Vec<PieceId>, SearchError> {
+        let mut pieces = match &self.prepare(db, Target::Pieces)? {
+            Some(search) => Plan::compile(search, db)
+                .execute(db, index, today)
+                .into_iter()
+                .collect::<Vec<_>>(),
+            None => db.pieces().map(|(id, _)| id).collect(),
+        };
+        self.sort(&mut pieces, db);
+        Ok(pieces)
+    }
+
+    /// Sorts `pieces` according to the query's ordering, or newest first if it doesn't have one.
+    pub fn sort(&self, pieces: &mut [PieceId], db: &Db) {
+        let default = [Ordering::default()];
+        let ordering = if self.ordering.is_empty() {
+            &default[..]
+        } else {
+            &self.ordering[..]
+        };
+
+        pieces.sort_by(|lhs, rhs| {
+            ordering
+                .iter()
+                .map(|order| order.compare(*lhs, *rhs, db, self.seed))
+                .find(|result| *result != cmp::Ordering::Equal)
+                .unwrap_or_else(|| lhs.cmp(rhs))
+        });
+    }
+
+    /// Returns the matching tags sorted by name, whatever the query's target.
+    pub fn execute_tags(&self, db: &Db, index: &SearchIndex) -> Result<Vec<TagId>, SearchError> {
+        self.execute_tags_on(db, index, Local::today().naive_local())
+    }
+
+    /// Like [`Query::execute_tags`], with relative dates counting back from `today`.
+    pub fn execute_tags_on(
+        &self,
+        db: &Db,
+        index: &SearchIndex,
+        today: NaiveDate,
+    ) -> Result<Vec<TagId>, SearchError> {
+        let search = self.prepare(db, Target::Tags)?;
+        let mut tags = db
+            .tags
+            .keys()
+            .filter(|tag_id| {
+                matches(&search, &|test| {
+                    evaluate_tag_test(test, *tag_id, db, index, today)
+                })
+            })
+            .collect::<Vec<_>>();
+        tags.sort_by(|lhs, rhs| db[*lhs].name.cmp(&db[*rhs].name).then(lhs.cmp(rhs)));
+        Ok(tags)
+    }
+
+    /// Returns the matching blobs newest first, whatever the query's target.
+    pub fn execute_blobs(&self, db: &Db, index: &SearchIndex) -> Result<Vec<BlobId>, SearchError> {
+        self.execute_blobs_on(db, index, Local::today().naive_local())
+    }
+
+    /// Like [`Query::execute_blobs`], with relative dates counting back from `today`.
+    pub fn execute_blobs_on(
+        &self,
+        db: &Db,
+        index: &SearchIndex,
+        today: NaiveDate,
+    ) -> Result<Vec<BlobId>, SearchError> {
+        let search = self.prepare(db, Target::Blobs)?;
+        let mut blobs = db
+            .blobs
+            .keys()
+            .filter(|blob_id| {
+                matches(&search, &|test| {
+                    evaluate_blob_test(test, *blob_id, db, index, today)
+                })
+            })
+            .collect::<Vec<_>>();
+        blobs.sort_by(|lhs, rhs| db[*rhs].added.cmp(&db[*lhs].added).then(lhs.cmp(rhs)));
+        Ok(blobs)
+    }
+
+    /// Expands saved searches, and checks for unknown terms if the query is strict.
+    pub(crate) fn prepare(&self, db: &Db, target: Target) -> Result<Option<Search>, SearchError> {
+        let search = self.clone().expand_saved(db)?.search;
+        if let Some(search) = search.as_ref().filter(|_| self.strict) {
+            let unknown = search.unknown_terms(db, target);
+            if !unknown.is_empty() {
+                return Err(SearchError::Unknown(unknown));
+            }
+        }
+        Ok(search)
+    }
+}
+
+/// Whether the search as a whole matches, with `test` evaluating each condition.
+fn matches(search: &Option<Search>, test: &dyn Fn(&Condition) -> Option<bool>) -> bool {
+    match search {
+        Some(search) => search.evaluate_internal(test).unwrap_or(true),
+        None => true,
+    }
+}
+
+impl Ordering {
+    fn compare(&self, lhs: PieceId, rhs: PieceId, db: &Db, seed: u64) -> cmp::Ordering {
+        let result = match self.key {
+            OrderKey::Added => db[lhs].added.cmp(&db[rhs].added),
+            OrderKey::Price => total_price(&db[lhs]).cmp(&total_price(&db[rhs])),
+            OrderKey::TagCount => db
+                .tags_for_piece(lhs)
+                .count()
+                .cmp(&db.tags_for_piece(rhs).count()),
+            OrderKey::Random => shuffle_key(lhs, seed).cmp(&shuffle_key(rhs, seed)),
+        };
+
+        if self.descending {
+            result.reverse()
+        } else {
+            result
+        }
+    }
+}
+
+fn total_price(piece: &Piece) -> Option<i64> {
+    match (piece.base_price, piece.tip_price) {
+        (None, None) => None,
+        (base, tip) => Some(base.unwrap_or(0) + tip.unwrap_or(0)),
+    }
+}
+
+fn shuffle_key(id: PieceId, seed: u64) -> u64 {
+    let mut hash = DefaultHasher::new();
+    (seed, id).hash(&mut hash);
+    hash.finish()
+}
 
 impl Search {
-    pub fn execute<'a>(&'a self, db: &'a Db) -> impl Iterator<Item = PieceId> + 'a {
+    /// Checks every piece against the whole search, one at a time.
+    /// [`Query::execute`] goes through a [`Plan`] instead, which gives the same pieces faster.
+    pub fn execute<'a>(
+        &'a self,
+        db: &'a Db,
+        index: &'a SearchIndex,
+    ) -> impl Iterator<Item = PieceId> + 'a {
+        self.execute_on(db, index, Local::today().naive_local())
+    }
+
+    /// Like [`Search::execute`], with relative dates counting back from `today`.
+    pub fn execute_on<'a>(
+        &'a self,
+        db: &'a Db,
+        index: &'a SearchIndex,
+        today: NaiveDate,
+    ) -> impl Iterator<Item = PieceId> + 'a {
         db.pieces()
-            .filter(move |item| self.evaluate_internal(item, db).unwrap_or(true))
+            .filter(move |item| {
+                self.evaluate_internal(&|test| evaluate_test(test, item, db, index, today))
+                    .unwrap_or(true)
+            })
             .map(|(id, _)| id)
     }
-    fn evaluate_internal(&self, value: &(PieceId, &Piece), db: &Db) -> Option<bool> {
+
+    /// None when the search is unknown, see [`Search`] for how unknown searches are combined.
+    /// `test` evaluates a single condition against whatever's being searched.
+    fn evaluate_internal(&self, test: &dyn Fn(&Condition) -> Option<bool>) -> Option<bool> {
         match self {
-            Search::Or(inner) => Some(
-                inner
-                    .iter()
-                    .any(|item| item.evaluate_internal(value, db).unwrap_or(true)),
-            ),
-            Search::And(inner) => Some(
-                inner
+            Search::Or(inner) | Search::And(inner) => {
+                let mut known = inner
                     .iter()
-                    .all(|item| item.evaluate_internal(value, db).unwrap_or(true)),
-            ),
-            Search::Negate(inner) => inner.evaluate_internal(value, db).map(|item| !item),
+                    .filter_map(|item| item.evaluate_internal(test))
+                    .peekable();
+                if !inner.is_empty() && known.peek().is_none() {
+                    return None;
+                }
+                Some(match self {
+                    Search::Or(_) => known.any(|item| item),
+                    _ => known.all(|item| item),
+                })
+            }
+            Search::Negate(inner) => inner.evaluate_internal(test).map(|item| !item),
+
+            Search::Test(condition) => test(condition),
+        }
+    }
+}
 
-            Search::Test(test) => evaluate_test(test, value, db),
+impl CompareOp {
+    fn matches<T: Ord>(self, lhs: T, rhs: T) -> bool {
+        match self {
+            CompareOp::Lesser => lhs < rhs,
+            CompareOp::LesserEqual => lhs <= rhs,
+            CompareOp::Equal => lhs == rhs,
+            CompareOp::GreaterEqual => lhs >= rhs,
+            CompareOp::Greater => lhs > rhs,
         }
     }
 }
 
+impl DateSpan {
+    /// The first and last day of the span.
+    fn bounds(self, today: NaiveDate) -> (NaiveDate, NaiveDate) {
+        match self {
+            DateSpan::Day(day) => (day, day),
+            DateSpan::Month(year, month) => {
+                let start = NaiveDate::from_ymd(year, month, 1);
+                let next = if month == 12 {
+                    NaiveDate::from_ymd(year + 1, 1, 1)
+                } else {
+                    NaiveDate::from_ymd(year, month + 1, 1)
+                };
+                (start, next.pred())
+            }
+            DateSpan::Year(year) => (
+                NaiveDate::from_ymd(year, 1, 1),
+                NaiveDate::from_ymd(year, 12, 31),
+            ),
+            DateSpan::Ago(amount, unit) => {
+                let day = match unit {
+                    DateUnit::Days => today.checked_sub_signed(Duration::days(amount.into())),
+                    DateUnit::Weeks => today.checked_sub_signed(Duration::weeks(amount.into())),
+                    DateUnit::Months => months_before(today, amount.into()),
+                    DateUnit::Years => months_before(today, i64::from(amount) * 12),
+                }
+                .unwrap_or(MIN_DATE);
+                (day, day)
+            }
+        }
+    }
+}
+
+/// Keeps the day of the month where possible, e.g. a month before March 31st is February 28th/29th.
+fn months_before(today: NaiveDate, months: i64) -> Option<NaiveDate> {
+    let months = i64::from(today.year()) * 12 + i64::from(today.month0()) - months;
+    let year = i32::try_from(months.div_euclid(12)).ok()?;
+    let month = months.rem_euclid(12) as u32 + 1;
+    (1..=today.day())
+        .rev()
+        .find_map(|day| NaiveDate::from_ymd_opt(year, month, day))
+}
+
 /// Returns Some(bool) evaluating the condition, returning None
 /// if the condition doesn't make sense (non-existent category, or tag for example)
-fn evaluate_test(test: &Condition, (id, piece): &(PieceId, &Piece), db: &Db) -> Option<bool> {
+pub(crate) fn evaluate_test(
+    test: &Condition,
+    (id, piece): &(PieceId, &Piece),
+    db: &Db,
+    index: &SearchIndex,
+    today: NaiveDate,
+) -> Option<bool> {
     match test {
+        // either the piece contains a tag of this name
+        // OR
+        // no tags of this name exist in the database
         Condition::Tag(tag_name) => {
-            let (searched, _) = db.tags().find(|(_, tag)| &tag.name == tag_name)?;
-            // either the piece contains the tag
-            // OR
-            // no tags of this name exist in the database
-            Some(db.tags_for_piece(*id).any(|tag| tag == searched))
+            evaluate_tag_match(*id, db, |tag_id| &db[tag_id].name == tag_name)
         }
         Condition::TagWithCategory(category_name, tag_name) => {
-            let (searched, _) =
-                db.tags()
-                    .filter(|(_, tag)| &tag.name == tag_name)
-                    .find(|(searched, _)| {
-                        db.category_for_tag(*searched)
-                            .map(|category_id| &db[category_id].name)
-                            == category_name.as_ref()
-                    })?;
-            // either the piece contains the category:tag
-            // OR
-            // no category:tag of this name exist in the database
-            Some(db.tags_for_piece(*id).any(|tag| tag == searched))
+            evaluate_tag_match(*id, db, |tag_id| {
+                &db[tag_id].name == tag_name && in_category(db, tag_id, category_name.as_ref())
+            })
+        }
+        Condition::TagMatch(pattern) => {
+            evaluate_tag_match(*id, db, |tag_id| pattern.matches(&db[tag_id].name))
+        }
+        Condition::TagMatchWithCategory(category_name, pattern) => {
+            evaluate_tag_match(*id, db, |tag_id| {
+                in_category(db, tag_id, category_name.as_ref()) && pattern.matches(&db[tag_id].name)
+            })
+        }
+        Condition::DateAdded(..) | Condition::Added(..) | Condition::AddedBetween(..) => {
+            evaluate_added(test, piece.added, today)
+        }
+        Condition::Count(key, op, count) => {
+            let actual = match key {
+                CountKey::Tags => db.tags_for_piece(*id).count(),
+                CountKey::Blobs => db.blobs_for_piece(*id).count(),
+                CountKey::Pieces => return None,
+            };
+            Some(op.matches(actual, *count))
         }
+        Condition::Source(source_type) => Some(&piece.source_type == source_type),
+        Condition::Media(media_type) => Some(&piece.media_type == media_type),
+        Condition::Price(price_type, op, amount) => {
+            let price = match price_type {
+                PriceType::Base => piece.base_price,
+                PriceType::Tip => piece.tip_price,
+                PriceType::Total => total_price(piece),
+            };
+            // pieces without the relevant price never match
+            Some(matches!(price, Some(price) if op.matches(price, *amount)))
+        }
+        Condition::Content(phrase) => {
+            let phrase = words(phrase).collect::<Vec<_>>();
+            Some(
+                db.blobs_for_piece(*id)
+                    .any(|blob_id| index.contains_phrase(Document::Blob(blob_id), &phrase)),
+            )
+        }
+        Condition::Description(phrase) => {
+            let phrase = words(phrase).collect::<Vec<_>>();
+            Some(
+                descriptions_for_piece(*id, db)
+                    .any(|document| index.contains_phrase(document, &phrase)),
+            )
+        }
+        Condition::Phrase(phrase) => {
+            let phrase = words(phrase).collect::<Vec<_>>();
+            Some(
+                descriptions_for_piece(*id, db)
+                    .chain(db.blobs_for_piece(*id).map(Document::Blob))
+                    .any(|document| index.contains_phrase(document, &phrase)),
+            )
+        }
+        // saved searches are expanded by `Query::execute` before anything is evaluated
+        Condition::Saved(_) => None,
+        Condition::Related(related) => {
+            if !db.exists(*related) {
+                return None;
+            }
+            Some(
+                db.relations_for_piece(*id)
+                    .any(|relation| relation.other(*id) == Some(*related)),
+            )
+        }
+        Condition::Category(category_name) => {
+            if !db
+                .categories()
+                .any(|(_, category)| &category.name == category_name)
+            {
+                return None;
+            }
+            Some(
+                db.tags_for_piece(*id)
+                    .any(|tag_id| in_category(db, tag_id, Some(category_name))),
+            )
+        }
+        Condition::Type(blob_type) => Some(
+            db.blobs_for_piece(*id)
+                .any(|blob_id| db[blob_id].blob_type == *blob_type),
+        ),
+    }
+}
+
+/// Date conditions, None for anything else.
+fn evaluate_added(test: &Condition, added: NaiveDate, today: NaiveDate) -> Option<bool> {
+    match test {
         Condition::DateAdded(op, date) => Some(match op {
-            DateOp::Before => &piece.added <= date,
-            DateOp::After => &piece.added >= date,
+            DateOp::Before => &added <= date,
+            DateOp::After => &added >= date,
         }),
+        Condition::Added(op, span) => {
+            let (start, end) = span.bounds(today);
+            Some(match op {
+                CompareOp::Lesser => added < start,
+                CompareOp::LesserEqual => added <= end,
+                CompareOp::Equal => start <= added && added <= end,
+                CompareOp::GreaterEqual => added >= start,
+                CompareOp::Greater => added > end,
+            })
+        }
+        Condition::AddedBetween(lhs, rhs) => {
+            // relative ranges read naturally either way around, e.g. -30d..-7d
+            let (lhs, rhs) = (lhs.bounds(today), rhs.bounds(today));
+            let (start, end) = (lhs.0.min(rhs.0), lhs.1.max(rhs.1));
+            Some(start <= added && added <= end)
+        }
+        _ => None,
+    }
+}
+
+/// Conditions on the tag's name, category, description and use count are checked against the tag,
+/// anything else against the pieces using it.
+fn evaluate_tag_test(
+    test: &Condition,
+    tag_id: TagId,
+    db: &Db,
+    index: &SearchIndex,
+    today: NaiveDate,
+) -> Option<bool> {
+    let name = &db[tag_id].name;
+    match test {
+        Condition::Tag(tag_name) => Some(name == tag_name),
+        Condition::TagWithCategory(category_name, tag_name) => {
+            Some(name == tag_name && in_category(db, tag_id, category_name.as_ref()))
+        }
+        Condition::TagMatch(pattern) => Some(pattern.matches(name)),
+        Condition::TagMatchWithCategory(category_name, pattern) => {
+            Some(pattern.matches(name) && in_category(db, tag_id, category_name.as_ref()))
+        }
+        Condition::Category(category_name) => Some(in_category(db, tag_id, Some(category_name))),
+        Condition::Count(CountKey::Pieces, op, count) => {
+            Some(op.matches(db.pieces_for_tag(tag_id).count(), *count))
+        }
+        Condition::Description(phrase) => {
+            let phrase = words(phrase).collect::<Vec<_>>();
+            Some(index.contains_phrase(Document::Tag(tag_id), &phrase))
+        }
+        _ => evaluate_through_pieces(test, db.pieces_for_tag(tag_id), db, index, today),
+    }
+}
+
+/// Conditions on the blob's type, date, media type, content and use count are checked against the blob,
+/// anything else against the pieces it belongs to.
+fn evaluate_blob_test(
+    test: &Condition,
+    blob_id: BlobId,
+    db: &Db,
+    index: &SearchIndex,
+    today: NaiveDate,
+) -> Option<bool> {
+    let blob = &db[blob_id];
+    match test {
+        Condition::Type(blob_type) => Some(blob.blob_type == *blob_type),
+        Condition::DateAdded(..) | Condition::Added(..) | Condition::AddedBetween(..) => {
+            evaluate_added(test, blob.added, today)
+        }
+        Condition::Media(media_type) => Some(blob.media_type() == *media_type),
+        Condition::Content(phrase) => {
+            let phrase = words(phrase).collect::<Vec<_>>();
+            Some(index.contains_phrase(Document::Blob(blob_id), &phrase))
+        }
+        Condition::Count(CountKey::Pieces, op, count) => {
+            Some(op.matches(db.pieces_for_blob(blob_id).count(), *count))
+        }
+        _ => evaluate_through_pieces(test, db.pieces_for_blob(blob_id), db, index, today),
+    }
+}
+
+/// Whether any of the pieces match, or None if the condition is unknown.
+fn evaluate_through_pieces(
+    test: &Condition,
+    mut pieces: impl Iterator<Item = PieceId>,
+    db: &Db,
+    index: &SearchIndex,
+    today: NaiveDate,
+) -> Option<bool> {
+    if !test.is_known(db, Target::Pieces) {
+        return None;
+    }
+    Some(pieces.any(|piece_id| {
+        evaluate_test(test, &(piece_id, &db[piece_id]), db, index, today) == Some(true)
+    }))
+}
+
+/// Whether the tag is in the named category, or has no category for None.
+pub(crate) fn in_category(db: &Db, tag_id: TagId, category_name: Option<&String>) -> bool {
+    db.category_for_tag(tag_id)
+        .map(|category_id| &db[category_id].name)
+        == category_name
+}
+
+/// Whether the piece has any of the tags picked out by `matches`,
+/// or None if no tags in the database match.
+fn evaluate_tag_match(id: PieceId, db: &Db, matches: impl Fn(TagId) -> bool) -> Option<bool> {
+    if !db.tags.keys().any(&matches) {
+        return None;
+    }
+    Some(db.tags_for_piece(id).any(matches))
+}
+
+/// The piece's own description, along with those of its tags and their categories.
+fn descriptions_for_piece(id: PieceId, db: &Db) -> impl Iterator<Item = Document> + '_ {
+    std::iter::once(Document::Piece(id))
+        .chain(db.tags_for_piece(id).map(Document::Tag))
+        .chain(
+            db.tags_for_piece(id)
+                .filter_map(move |tag_id| db.category_for_tag(tag_id))
+                .map(Document::Category),
+        )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::model::{BlobType, Target};
+    use crate::model::{Condition, Query};
+    use crate::parse_query;
+    use chrono::NaiveDate;
+    use db::{
+        v3::commands::{AttachBlob, AttachCategory, AttachTag},
+        Blob, Category, Relation, RelationType, Tag,
+    };
+    use proptest::prelude::*;
+
+    fn piece(day: u32, base_price: Option<i64>, tip_price: Option<i64>) -> Piece {
+        Piece {
+            added: NaiveDate::from_ymd(2021, 1, day),
+            base_price,
+            tip_price,
+            ..Default::default()
+        }
+    }
+
+    fn execute(db: &Db, input: &str) -> Vec<PieceId> {
+        let (_, query) = parse_query(input).unwrap();
+        query.execute(db, &SearchIndex::build(db)).unwrap()
+    }
+
+    #[test]
+    fn test_ordering() {
+        let mut db = Db::default();
+        let old = db.create_piece(piece(1, Some(50), None));
+        let new = db.create_piece(piece(3, None, None));
+        let middle = db.create_piece(piece(2, Some(20), Some(40)));
+
+        assert_eq!(execute(&db, ""), [new, middle, old]);
+        assert_eq!(execute(&db, "order:added"), [old, middle, new]);
+        assert_eq!(execute(&db, "order:-price"), [middle, old, new]);
+        assert_eq!(execute(&db, "order:price"), [new, old, middle]);
+
+        let random = execute(&db, "order:random");
+        assert_eq!(random, execute(&db, "order:random"));
+        assert_eq!(random.len(), 3);
+    }
+
+    #[test]
+    fn test_related() {
+        let mut db = Db::default();
+        let sketch = db.create_piece(piece(1, None, None));
+        let redraw = db.create_piece(piece(2, None, None));
+        let sequel = db.create_piece(piece(3, None, None));
+        let unrelated = db.create_piece(piece(4, None, None));
+
+        assert!(db.relate(Relation {
+            src: redraw,
+            kind: RelationType::AltVersion,
+            dest: sketch,
+        }));
+        assert!(db.relate(Relation {
+            src: sequel,
+            kind: RelationType::Sequel,
+            dest: redraw,
+        }));
+        assert!(!db.relate(Relation {
+            src: sketch,
+            kind: RelationType::Sequel,
+            dest: sketch,
+        }));
+
+        let related = |db: &Db, id: PieceId| execute(db, &format!("related:{} order:added", id));
+        assert_eq!(related(&db, sketch), [redraw]);
+        assert_eq!(related(&db, redraw), [sketch, sequel]);
+        assert_eq!(
+            execute(&db, &format!("!related:{} order:added", redraw)),
+            [redraw, unrelated]
+        );
+        // unknown pieces are ignored, like unknown tags
+        assert_eq!(execute(&db, "related:100").len(), 4);
+
+        db.delete(redraw);
+        assert!(db.relations_for_piece(sketch).next().is_none());
+        assert!(db.relations_for_piece(sequel).next().is_none());
+    }
+
+    #[test]
+    fn test_added() {
+        let today = NaiveDate::from_ymd(2023, 3, 31);
+        let mut db = Db::default();
+        let mut added = |date| {
+            db.create_piece(Piece {
+                added: date,
+                ..Default::default()
+            })
+        };
+        let old = added(NaiveDate::from_ymd(2022, 12, 31));
+        let january = added(NaiveDate::from_ymd(2023, 1, 15));
+        let february = added(NaiveDate::from_ymd(2023, 2, 28));
+        let recent = added(today);
+
+        let execute = |input: &str| {
+            let (_, query) = parse_query(&format!("{} order:added", input)).unwrap();
+            query
+                .execute_on(&db, &SearchIndex::build(&db), today)
+                .unwrap()
+        };
+
+        assert_eq!(execute("added:2023"), [january, february, recent]);
+        assert_eq!(execute("added:<2023"), [old]);
+        assert_eq!(execute("added:>2023-01"), [february, recent]);
+        assert_eq!(execute("added:<=2023-01"), [old, january]);
+        assert_eq!(execute("added:2023-02-28"), [february]);
+        assert_eq!(execute("added:2022-12..2023-01"), [old, january]);
+        // a month before March 31st is February 28th
+        assert_eq!(execute("added:-1m"), [february]);
+        assert_eq!(execute("added:>-30d"), [recent]);
+        assert_eq!(execute("added:-7d..-1y"), [old, january, february]);
+        assert_eq!(
+            execute("after:2023-01-15 before:02/28/2023"),
+            [january, february]
+        );
+    }
+
+    #[test]
+    fn test_count() {
+        let mut db = Db::default();
+        let untagged = db.create_piece(piece(1, None, None));
+        let tagged = db.create_piece(piece(2, None, None));
+        for name in ["red", "dress"] {
+            let tag_id = db.create_tag(Tag {
+                name: name.to_string(),
+                ..Default::default()
+            });
+            db.attach_tag(AttachTag {
+                src: tagged,
+                dest: tag_id,
+            });
+        }
+
+        assert_eq!(execute(&db, "tags:<2"), [untagged]);
+        assert_eq!(execute(&db, "tags:2"), [tagged]);
+        assert_eq!(execute(&db, "tags:>=1 blobs:0"), [tagged]);
+        assert!(execute(&db, "blobs:>0").is_empty());
+    }
+
+    fn tagged_db() -> Db {
+        let mut db = Db::default();
+        let tags = ["red", "dress", "hat"]
+            .iter()
+            .map(|name| {
+                db.create_tag(Tag {
+                    name: name.to_string(),
+                    ..Default::default()
+                })
+            })
+            .collect::<Vec<_>>();
+        let clothes = db.create_category(Category {
+            name: "clothes".to_string(),
+            ..Default::default()
+        });
+        db.attach_category(AttachCategory {
+            src: tags[2],
+            dest: Some(clothes),
+        });
+        // one piece for every combination of tags
+        for combination in 0..(1 << tags.len()) {
+            let piece_id = db.create_piece(piece(1, None, None));
+            for (bit, tag_id) in tags.iter().enumerate() {
+                if combination & (1 << bit) != 0 {
+                    db.attach_tag(AttachTag {
+                        src: piece_id,
+                        dest: *tag_id,
+                    });
+                }
+            }
+        }
+        db
+    }
+
+    #[test]
+    fn test_unknown() {
+        let db = tagged_db();
+        let all = execute(&db, "order:added");
+
+        assert_eq!(execute(&db, "missing"), all);
+        assert_eq!(execute(&db, "!missing"), all);
+        assert_eq!(execute(&db, "red missing"), execute(&db, "red"));
+        assert_eq!(execute(&db, "red|missing"), execute(&db, "red"));
+        assert_eq!(execute(&db, "!(red|missing)"), execute(&db, "!red"));
+        assert_eq!(execute(&db, "!(missing|typo)"), all);
+    }
+
+    #[test]
+    fn test_strict() {
+        let db = tagged_db();
+        let strict = |input: &str| {
+            let (_, query) = parse_query(input).unwrap();
+            Query {
+                strict: true,
+                ..query
+            }
+            .execute(&db, &SearchIndex::build(&db))
+        };
+
+        assert_eq!(strict("red").unwrap(), execute(&db, "red"));
+        assert_eq!(strict("").unwrap().len(), 8);
+        assert_eq!(
+            strict("red|!missing artist:red hat_* related:100"),
+            Err(SearchError::Unknown(vec![
+                "missing".to_owned(),
+                "artist:red".to_owned(),
+                "hat_*".to_owned(),
+                "related:100".to_owned(),
+            ]))
+        );
+    }
+
+    #[test]
+    fn test_targets() {
+        let today = NaiveDate::from_ymd(2023, 3, 31);
+        let mut db = tagged_db();
+        let tag = |db: &Db, name: &str| db.tags().find(|(_, tag)| tag.name == name).unwrap().0;
+        let (red, dress, hat) = (tag(&db, "red"), tag(&db, "dress"), tag(&db, "hat"));
+        let unused = db.create_tag(Tag {
+            name: "unused".to_string(),
+            ..Default::default()
+        });
+
+        let blob = |db: &mut Db, blob_type, day, piece_id| {
+            let blob_id = db.create_blob(Blob {
+                file_name: "blob.png".to_string(),
+                hash: 0,
+                blob_type,
+                added: NaiveDate::from_ymd(2023, 1, day),
+                content_type: None,
+                duration: None,
+                location: None,
+            });
+            db.attach_blob(AttachBlob {
+                src: piece_id,
+                dest: blob_id,
+            });
+            blob_id
+        };
+        let (red_piece, _) = db
+            .pieces()
+            .find(|(piece_id, _)| db.tags_for_piece(*piece_id).eq(vec![red]))
+            .unwrap();
+        let plain_piece = db.pieces.keys().next().unwrap();
+        let raw = blob(&mut db, BlobType::Raw, 1, red_piece);
+        let canon = blob(&mut db, BlobType::Canon, 2, plain_piece);
+
+        let index = SearchIndex::build(&db);
+        let tags = |input: &str| {
+            let (_, query) = parse_query(input).unwrap();
+            query.execute_tags_on(&db, &index, today).unwrap()
+        };
+        let blobs = |input: &str| {
+            let (_, query) = parse_query(input).unwrap();
+            query.execute_blobs_on(&db, &index, today).unwrap()
+        };
+
+        assert_eq!(
+            parse_query("tags where count>3").unwrap().1.target,
+            Target::Tags
+        );
+        assert_eq!(tags("tags where"), [dress, hat, red, unused]);
+        assert_eq!(tags("tags where count>3"), [dress, hat, red]);
+        assert_eq!(tags("tags where count:0"), [unused]);
+        assert_eq!(tags("tags where category:clothes"), [hat]);
+        assert_eq!(
+            tags("tags where !category:clothes *e*"),
+            [dress, red, unused]
+        );
+        // through the pieces using the tag
+        assert_eq!(tags("tags where tags:1"), [dress, hat, red]);
+        assert_eq!(tags("tags where blobs:>0"), [red]);
+
+        assert_eq!(blobs("blobs where"), [canon, raw]);
+        assert_eq!(blobs("blobs where type:raw"), [raw]);
+        assert_eq!(blobs("blobs where added:2023-01-02"), [canon]);
+        assert_eq!(blobs("blobs where red"), [raw]);
+        assert_eq!(blobs("blobs where !red missing"), [canon]);
+        assert_eq!(execute(&db, "type:canon"), [plain_piece]);
+        // pieces aren't used by pieces
+        assert_eq!(execute(&db, "count>0").len(), 8);
+    }
+
+    fn arb_search() -> impl Strategy<Value = Search> {
+        let leaf = prop_oneof![
+            prop::sample::select(vec!["red", "dress", "hat", "missing", "typo"])
+                .prop_map(|name| Condition::Tag(name.to_owned())),
+            (0..4usize).prop_map(|count| Condition::Count(
+                CountKey::Tags,
+                CompareOp::Greater,
+                count
+            )),
+            prop::sample::select(vec!["clothes", "missing"])
+                .prop_map(|name| Condition::Category(name.to_owned())),
+            Just(Condition::Count(CountKey::Pieces, CompareOp::Greater, 0)),
+        ]
+        .prop_map(Search::Test);
+        leaf.prop_recursive(4, 32, 4, |inner| {
+            prop_oneof![
+                prop::collection::vec(inner.clone(), 0..4).prop_map(Search::Or),
+                prop::collection::vec(inner.clone(), 0..4).prop_map(Search::And),
+                inner.prop_map(|search| Search::Negate(Box::new(search))),
+            ]
+        })
+    }
+
+    fn matches(db: &Db, search: Search) -> Vec<PieceId> {
+        search.execute(db, &SearchIndex::default()).collect()
+    }
+
+    fn not(search: &Search) -> Search {
+        Search::Negate(Box::new(search.clone()))
+    }
+
+    proptest! {
+        #[test]
+        fn test_de_morgan(lhs in arb_search(), rhs in arb_search()) {
+            let db = tagged_db();
+            let both = vec![lhs.clone(), rhs.clone()];
+
+            prop_assert_eq!(
+                matches(&db, not(&Search::And(both.clone()))),
+                matches(&db, Search::Or(vec![not(&lhs), not(&rhs)]))
+            );
+            prop_assert_eq!(
+                matches(&db, not(&Search::Or(both))),
+                matches(&db, Search::And(vec![not(&lhs), not(&rhs)]))
+            );
+        }
+
+        #[test]
+        fn test_plan(search in arb_search()) {
+            let db = tagged_db();
+            let planned = Plan::compile(&search, &db)
+                .execute(&db, &SearchIndex::build(&db), Local::today().naive_local());
+            prop_assert_eq!(planned.into_iter().collect::<Vec<_>>(), matches(&db, search));
+        }
+
+        #[test]
+        fn test_double_negation(search in arb_search()) {
+            let db = tagged_db();
+            prop_assert_eq!(matches(&db, not(&not(&search))), matches(&db, search));
+        }
     }
 }
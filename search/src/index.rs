@@ -0,0 +1,240 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use db::{v3::journal::Change, BlobId, CategoryId, Db, PieceId, TagId};
+
+/// Something with text that can be searched through.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Document {
+    Piece(PieceId),
+    Tag(TagId),
+    Category(CategoryId),
+    Blob(BlobId),
+}
+
+/// Inverted index over descriptions and the contents of text blobs,
+/// along with which pieces have each tag.
+///
+/// Descriptions can be refreshed straight from the database, but blob contents
+/// live on disk, so they have to be handed to [`SearchIndex::index_text`].
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct SearchIndex {
+    postings: BTreeMap<String, BTreeMap<Document, Vec<usize>>>,
+    words_for_document: BTreeMap<Document, BTreeSet<String>>,
+    pieces_for_tag: BTreeMap<TagId, BTreeSet<PieceId>>,
+}
+
+impl SearchIndex {
+    /// Indexes every piece, tag and category description in `db`, and every piece's tags.
+    pub fn build(db: &Db) -> Self {
+        let mut index = Self::default();
+        for (piece_id, tag_id) in &db.piece_tags {
+            index
+                .pieces_for_tag
+                .entry(*tag_id)
+                .or_default()
+                .insert(*piece_id);
+        }
+        for (piece_id, piece) in db.pieces() {
+            index.index_text(Document::Piece(piece_id), &piece.description);
+        }
+        for (tag_id, tag) in db.tags() {
+            index.index_text(Document::Tag(tag_id), &tag.description);
+        }
+        for (category_id, category) in db.categories() {
+            index.index_text(Document::Category(category_id), &category.description);
+        }
+        index
+    }
+
+    /// Brings `document` up to date after it was edited or deleted in `db`.
+    /// Blob contents aren't stored in the database, so blobs are only ever removed here.
+    pub fn refresh(&mut self, db: &Db, document: Document) {
+        let description = match document {
+            Document::Piece(id) => db.pieces.get(id).map(|piece| &piece.description),
+            Document::Tag(id) => db.tags.get(id).map(|tag| &tag.description),
+            Document::Category(id) => db.categories.get(id).map(|category| &category.description),
+            Document::Blob(id) => {
+                if !db.exists(id) {
+                    self.remove(document);
+                }
+                return;
+            }
+        };
+
+        match description {
+            Some(description) => self.index_text(document, description),
+            None => self.remove(document),
+        }
+    }
+
+    /// Brings the index up to date after `change` was made to `db`.
+    pub fn apply(&mut self, db: &Db, change: &Change) {
+        match change {
+            Change::Piece(piece_id, _) => self.refresh(db, Document::Piece(*piece_id)),
+            Change::Blob(blob_id, _) => self.refresh(db, Document::Blob(*blob_id)),
+            Change::Tag(tag_id, _) => self.refresh(db, Document::Tag(*tag_id)),
+            Change::Category(category_id, _) => self.refresh(db, Document::Category(*category_id)),
+            Change::PieceTag(piece_id, tag_id, true) => {
+                self.pieces_for_tag
+                    .entry(*tag_id)
+                    .or_default()
+                    .insert(*piece_id);
+            }
+            Change::PieceTag(piece_id, tag_id, false) => {
+                if let Some(pieces) = self.pieces_for_tag.get_mut(tag_id) {
+                    pieces.remove(piece_id);
+                    if pieces.is_empty() {
+                        self.pieces_for_tag.remove(tag_id);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// The pieces with `tag_id`, in order.
+    pub fn pieces_for_tag(&self, tag_id: TagId) -> impl Iterator<Item = PieceId> + '_ {
+        self.pieces_for_tag
+            .get(&tag_id)
+            .into_iter()
+            .flatten()
+            .copied()
+    }
+
+    pub fn has_tag(&self, piece_id: PieceId, tag_id: TagId) -> bool {
+        matches!(self.pieces_for_tag.get(&tag_id), Some(pieces) if pieces.contains(&piece_id))
+    }
+
+    /// Replaces whatever was previously indexed for `document` with the words in `text`.
+    pub fn index_text(&mut self, document: Document, text: &str) {
+        self.remove(document);
+
+        let mut indexed = BTreeSet::new();
+        for (position, word) in words(text).enumerate() {
+            self.postings
+                .entry(word.clone())
+                .or_default()
+                .entry(document)
+                .or_default()
+                .push(position);
+            indexed.insert(word);
+        }
+        self.words_for_document.insert(document, indexed);
+    }
+
+    pub fn remove(&mut self, document: Document) {
+        for word in self
+            .words_for_document
+            .remove(&document)
+            .into_iter()
+            .flatten()
+        {
+            if let Some(documents) = self.postings.get_mut(&word) {
+                documents.remove(&document);
+                if documents.is_empty() {
+                    self.postings.remove(&word);
+                }
+            }
+        }
+    }
+
+    /// Whether `phrase` occurs in `document`, as consecutive words.
+    /// `phrase` is expected to come from [`words`], so it's already lowercased.
+    pub fn contains_phrase(&self, document: Document, phrase: &[String]) -> bool {
+        let positions = phrase
+            .iter()
+            .map(|word| self.postings.get(word)?.get(&document))
+            .collect::<Option<Vec<_>>>();
+
+        match positions.as_deref() {
+            Some([first, rest @ ..]) => first.iter().any(|start| {
+                rest.iter()
+                    .zip(1..)
+                    .all(|(positions, offset)| positions.binary_search(&(start + offset)).is_ok())
+            }),
+            _ => false,
+        }
+    }
+}
+
+/// Splits `text` into lowercased words, treating anything that isn't
+/// alphanumeric (or an underscore) as a separator.
+pub fn words(text: &str) -> impl Iterator<Item = String> + '_ {
+    text.split(|c: char| !(c.is_alphanumeric() || c == '_'))
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn phrase(text: &str) -> Vec<String> {
+        words(text).collect()
+    }
+
+    #[test]
+    fn test_words() {
+        assert_eq!(
+            words("A red dress, (yumi_lovelace)!").collect::<Vec<_>>(),
+            vec!["a", "red", "dress", "yumi_lovelace"]
+        );
+    }
+
+    #[test]
+    fn test_phrase() {
+        let document = Document::Blob(BlobId::from(0));
+        let mut index = SearchIndex::default();
+
+        index.index_text(document, "She wore a Red Dress, then a red hat.");
+        assert!(index.contains_phrase(document, &phrase("red")));
+        assert!(index.contains_phrase(document, &phrase("RED DRESS")));
+        assert!(index.contains_phrase(document, &phrase("a red hat")));
+        assert!(!index.contains_phrase(document, &phrase("red wore")));
+        assert!(!index.contains_phrase(document, &phrase("dress hat")));
+        assert!(!index.contains_phrase(document, &phrase("blue")));
+        assert!(!index.contains_phrase(document, &[]));
+    }
+
+    #[test]
+    fn test_reindex() {
+        let document = Document::Blob(BlobId::from(0));
+        let mut index = SearchIndex::default();
+
+        index.index_text(document, "Red Dress");
+        index.index_text(document, "blue dress");
+        assert!(!index.contains_phrase(document, &phrase("red")));
+        assert!(index.contains_phrase(document, &phrase("blue dress")));
+
+        index.remove(document);
+        assert_eq!(index, SearchIndex::default());
+    }
+
+    #[test]
+    fn test_apply() {
+        let mut db = Db::default();
+        let piece = db.create_piece(Default::default());
+        let tag = db.create_tag(Default::default());
+        let mut index = SearchIndex::build(&db);
+
+        let before = db.clone();
+        db.attach_tag(db::v3::commands::AttachTag {
+            src: piece,
+            dest: tag,
+        });
+        db.pieces.get_mut(piece).unwrap().description = "Red Dress".to_string();
+        for change in db.changes_since(&before) {
+            index.apply(&db, &change);
+        }
+        assert_eq!(index, SearchIndex::build(&db));
+        assert_eq!(index.pieces_for_tag(tag).collect::<Vec<_>>(), vec![piece]);
+
+        let before = db.clone();
+        db.delete(piece);
+        for change in db.changes_since(&before) {
+            index.apply(&db, &change);
+        }
+        assert_eq!(index, SearchIndex::build(&db));
+        assert_eq!(index.pieces_for_tag(tag).count(), 0);
+    }
+}
@@ -1,5 +1,13 @@
 mod execute;
+pub mod index;
 pub mod model;
 mod parse;
+mod plan;
+mod saved;
+mod strict;
 
-pub use parse::parse_search;
+pub use index::SearchIndex;
+pub use parse::{parse_query, parse_search};
+pub use plan::{IncrementalSearch, Plan};
+pub use saved::SavedSearchError;
+pub use strict::SearchError;
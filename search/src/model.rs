@@ -1,4 +1,19 @@
+use std::fmt;
+
 use chrono::NaiveDate;
+pub use db::{BlobType, MediaType, PieceId, SourceType};
+use regex::Regex;
+
+/// Each search evaluates to true, false, or unknown when it only refers to things that aren't
+/// in the database (e.g. a misspelled tag). Unknown searches are left out of whatever they're in,
+/// as if they hadn't been typed at all:
+/// * `Negate` of an unknown search is unknown.
+/// * `And` and `Or` only look at their known parts, and are unknown if none of them are known.
+///   Without any parts, `And` matches everything and `Or` matches nothing.
+/// * A search that's unknown as a whole matches every piece, like an empty one.
+///
+/// So `!(a b)` matches the same pieces as `!a|!b`, whether or not `a` and `b` exist.
+/// See [`crate::model::Query::strict`] for reporting unknown terms instead.
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub enum Search {
     Or(Vec<Search>),
@@ -11,11 +26,188 @@ pub enum Search {
 pub enum Condition {
     Tag(String),
     TagWithCategory(Option<String>, String),
+    /// Pieces with any tag matching the pattern.
+    TagMatch(TagPattern),
+    /// Like [`Condition::TagMatch`], only looking at tags in the category.
+    TagMatchWithCategory(Option<String>, TagPattern),
     DateAdded(DateOp, NaiveDate),
+    /// Compared against the whole span, e.g. `added:>2023` only matches from 2024 on.
+    Added(CompareOp, DateSpan),
+    /// Inclusive of both spans.
+    AddedBetween(DateSpan, DateSpan),
+    Count(CountKey, CompareOp, usize),
+    Source(SourceType),
+    Media(MediaType),
+    Price(PriceType, CompareOp, i64),
+    Content(String),
+    Description(String),
+    Phrase(String),
+    /// The search saved under this name, see [`Query::expand_saved`].
+    Saved(String),
+    /// Pieces related to this one, in either direction.
+    Related(PieceId),
+    /// Pieces with any tag in the category, or tags in the category.
+    Category(String),
+    /// Pieces with any blob of this type, or blobs of this type.
+    Type(BlobType),
 }
 
+/// A pattern for tag names, either a glob (`yumi_*`) or a regex (`/^yumi/`).
+#[derive(Clone, Debug)]
+pub struct TagPattern {
+    source: String,
+    regex: Regex,
+}
+
+impl TagPattern {
+    /// `*` matches any run of characters, everything else has to match exactly.
+    pub fn glob(glob: &str) -> Self {
+        let regex = glob
+            .split('*')
+            .map(regex::escape)
+            .collect::<Vec<_>>()
+            .join(".*");
+        Self {
+            source: glob.to_string(),
+            regex: Regex::new(&format!("^{}$", regex)).unwrap(),
+        }
+    }
+
+    /// Unlike globs, regexes aren't anchored, so `/yumi/` matches any tag containing "yumi".
+    pub fn regex(regex: &str) -> Result<Self, regex::Error> {
+        Ok(Self {
+            source: format!("/{}/", regex),
+            regex: Regex::new(regex)?,
+        })
+    }
+
+    pub fn matches(&self, name: &str) -> bool {
+        self.regex.is_match(name)
+    }
+}
+
+impl fmt::Display for TagPattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+impl PartialEq for TagPattern {
+    fn eq(&self, other: &Self) -> bool {
+        self.source == other.source
+    }
+}
+impl Eq for TagPattern {}
+
 #[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
 pub enum DateOp {
     Before,
     After,
 }
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum PriceType {
+    Base,
+    Tip,
+    Total,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum CompareOp {
+    Lesser,
+    LesserEqual,
+    Equal,
+    GreaterEqual,
+    Greater,
+}
+
+impl fmt::Display for CompareOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            CompareOp::Lesser => "<",
+            CompareOp::LesserEqual => "<=",
+            CompareOp::Equal => "=",
+            CompareOp::GreaterEqual => ">=",
+            CompareOp::Greater => ">",
+        })
+    }
+}
+
+/// A day, month or year, either as a date or relative to today.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DateSpan {
+    Day(NaiveDate),
+    Month(i32, u32),
+    Year(i32),
+    /// The single day this long before today.
+    Ago(u32, DateUnit),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum DateUnit {
+    Days,
+    Weeks,
+    Months,
+    Years,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum CountKey {
+    Tags,
+    Blobs,
+    /// How many pieces a tag or blob is used by, unknown when searching pieces.
+    Pieces,
+}
+
+/// What a query searches through.
+///
+/// Conditions about the target itself (e.g. `category:` for tags, or `type:` for blobs) are checked
+/// directly, anything else matches if it holds for any of the pieces the tag or blob is used by.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub enum Target {
+    #[default]
+    Pieces,
+    Tags,
+    Blobs,
+}
+
+/// A search, along with how to order its results.
+#[derive(Clone, Debug, PartialEq, Eq, Default)]
+pub struct Query {
+    pub target: Target,
+    /// `None` matches everything.
+    pub search: Option<Search>,
+    /// Applied in order, with later orderings breaking ties in earlier ones.
+    /// Only pieces can be ordered, tags are sorted by name and blobs newest first.
+    pub ordering: Vec<Ordering>,
+    /// Used to shuffle pieces for `order:random`, so the same seed gives the same order.
+    pub seed: u64,
+    /// Terms that don't refer to anything in the database are normally ignored,
+    /// strict queries report them as [`crate::SearchError::Unknown`] instead.
+    pub strict: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Ordering {
+    pub key: OrderKey,
+    pub descending: bool,
+}
+
+impl Default for Ordering {
+    /// Newest pieces first.
+    fn default() -> Self {
+        Self {
+            key: OrderKey::Added,
+            descending: true,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum OrderKey {
+    Added,
+    /// Base price plus tip.
+    Price,
+    TagCount,
+    Random,
+}
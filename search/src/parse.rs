@@ -1,12 +1,18 @@
-use crate::model::{Condition, DateOp, Search};
+use crate::{
+    index::words,
+    model::{
+        BlobType, CompareOp, Condition, CountKey, DateOp, DateSpan, DateUnit, MediaType, OrderKey,
+        Ordering, PieceId, PriceType, Query, Search, SourceType, TagPattern, Target,
+    },
+};
 use chrono::NaiveDate;
 use nom::{
     branch::alt,
     bytes::complete::{self, tag},
-    character::complete::space1,
-    combinator::{map, map_opt, verify},
-    multi::separated_list1,
-    sequence::{preceded, separated_pair, terminated},
+    character::complete::{digit1, space1},
+    combinator::{eof, map, map_opt, map_res, opt, value, verify},
+    multi::{separated_list0, separated_list1},
+    sequence::{delimited, pair, preceded, separated_pair, terminated, tuple},
     IResult,
 };
 
@@ -37,6 +43,93 @@ pub fn parse_search(input: &str) -> IResult<&str, Search> {
     alt((parse_and, parse_or, parse_paren, parse_negate, parse_test))(input)
 }
 
+/// Parses a search where `order:` directives can be mixed in with the top level terms,
+/// optionally starting with what to search through, e.g. `tags where category:artist`.
+/// The `where` is required, so `tags` on its own still searches for the tag named "tags".
+pub fn parse_query(input: &str) -> IResult<&str, Query> {
+    enum Term {
+        Search(Search),
+        Ordering(Ordering),
+    }
+
+    let (input, target) = opt(parse_target)(input)?;
+
+    let (input, query) = map(
+        separated_list0(
+            space1,
+            alt((
+                map(parse_ordering, Term::Ordering),
+                map(
+                    alt((parse_or, parse_paren, parse_negate, parse_test)),
+                    Term::Search,
+                ),
+            )),
+        ),
+        |terms| {
+            let mut searches = Vec::new();
+            let mut ordering = Vec::new();
+            for term in terms {
+                match term {
+                    Term::Search(search) => searches.push(search),
+                    Term::Ordering(order) => ordering.push(order),
+                }
+            }
+
+            Query {
+                search: match searches.len() {
+                    0 => None,
+                    1 => searches.pop(),
+                    _ => Some(Search::And(searches)),
+                },
+                ordering,
+                ..Query::default()
+            }
+        },
+    )(input)?;
+
+    Ok((
+        input,
+        Query {
+            target: target.unwrap_or_default(),
+            ..query
+        },
+    ))
+}
+
+fn parse_target(input: &str) -> IResult<&str, Target> {
+    let end = || alt((space1, eof));
+    terminated(
+        alt((
+            value(Target::Pieces, tag("pieces")),
+            value(Target::Tags, tag("tags")),
+            value(Target::Blobs, tag("blobs")),
+        )),
+        tuple((space1, tag("where"), end())),
+    )(input)
+}
+
+fn parse_ordering(input: &str) -> IResult<&str, Ordering> {
+    map_opt(parse_whole, |(lhs, rhs)| {
+        if lhs != "order" {
+            return None;
+        }
+        let (descending, rhs) = match rhs.strip_prefix('-') {
+            Some(rhs) => (true, rhs),
+            None => (false, rhs),
+        };
+        Some(Ordering {
+            key: match rhs {
+                "added" => OrderKey::Added,
+                "price" => OrderKey::Price,
+                "tagcount" => OrderKey::TagCount,
+                "random" => OrderKey::Random,
+                _ => return None,
+            },
+            descending,
+        })
+    })(input)
+}
+
 fn parse_paren(input: &str) -> IResult<&str, Search> {
     terminated(preceded(tag("("), parse_search), tag(")"))(input)
 }
@@ -58,12 +151,128 @@ fn parse_whole(input: &str) -> IResult<&str, (&str, &str)> {
 
 fn parse_item(input: &str) -> IResult<&str, &str> {
     complete::take_while(|c: char| {
-        !c.is_whitespace() && !matches!(c, ':' | '(' | ')' | '!' | '|' | '>' | '=' | '<')
+        !c.is_whitespace() && !matches!(c, ':' | '(' | ')' | '!' | '|' | '>' | '=' | '<' | '"')
     })(input)
 }
 
 fn parse_condition(input: &str) -> IResult<&str, Condition> {
-    alt((parse_added, parse_tag_with_category, parse_tag))(input)
+    alt((
+        parse_price,
+        parse_added,
+        parse_added_span,
+        parse_count,
+        parse_source,
+        parse_media,
+        parse_type,
+        parse_content,
+        parse_description,
+        parse_phrase,
+        parse_saved,
+        parse_related,
+        parse_tag_match_with_category,
+        parse_tag_match,
+        parse_category,
+        parse_tag_with_category,
+        parse_tag,
+    ))(input)
+}
+
+fn parse_price(input: &str) -> IResult<&str, Condition> {
+    map(
+        tuple((
+            alt((
+                value(PriceType::Base, tag("base")),
+                value(PriceType::Tip, tag("tip")),
+                value(PriceType::Total, tag("total")),
+            )),
+            parse_compare_op,
+            map_res(digit1, str::parse),
+        )),
+        |(price_type, op, amount)| Condition::Price(price_type, op, amount),
+    )(input)
+}
+
+fn parse_compare_op(input: &str) -> IResult<&str, CompareOp> {
+    alt((
+        value(CompareOp::GreaterEqual, tag(">=")),
+        value(CompareOp::LesserEqual, tag("<=")),
+        value(CompareOp::Greater, tag(">")),
+        value(CompareOp::Lesser, tag("<")),
+        value(CompareOp::Equal, tag("=")),
+    ))(input)
+}
+
+/// `tags:3`, `tags:>3` or `tags>3`, with `count` for how many pieces a tag or blob is used by.
+fn parse_count(input: &str) -> IResult<&str, Condition> {
+    map(
+        tuple((
+            alt((
+                value(CountKey::Tags, tag("tags")),
+                value(CountKey::Blobs, tag("blobs")),
+                value(CountKey::Pieces, tag("count")),
+            )),
+            alt((
+                preceded(tag(":"), opt(parse_compare_op)),
+                map(parse_compare_op, Some),
+            )),
+            map_res(digit1, str::parse),
+        )),
+        |(key, op, count)| Condition::Count(key, op.unwrap_or(CompareOp::Equal), count),
+    )(input)
+}
+
+fn parse_category(input: &str) -> IResult<&str, Condition> {
+    map(
+        preceded(
+            tag("category:"),
+            verify(parse_item, |name: &str| !name.is_empty()),
+        ),
+        |name| Condition::Category(name.to_string()),
+    )(input)
+}
+
+fn parse_type(input: &str) -> IResult<&str, Condition> {
+    map_opt(parse_whole, |(lhs, rhs)| {
+        if lhs != "type" {
+            return None;
+        }
+        Some(Condition::Type(match rhs {
+            "canon" => BlobType::Canon,
+            "variant" => BlobType::Variant,
+            "raw" => BlobType::Raw,
+            "draft" => BlobType::Draft,
+            _ => return None,
+        }))
+    })(input)
+}
+
+fn parse_source(input: &str) -> IResult<&str, Condition> {
+    map_opt(parse_whole, |(lhs, rhs)| {
+        if lhs != "source" {
+            return None;
+        }
+        Some(Condition::Source(match rhs {
+            "fan" => SourceType::FanCreation,
+            "official" => SourceType::Official,
+            "commission" => SourceType::Commission,
+            _ => return None,
+        }))
+    })(input)
+}
+
+fn parse_media(input: &str) -> IResult<&str, Condition> {
+    map_opt(parse_whole, |(lhs, rhs)| {
+        if lhs != "media" {
+            return None;
+        }
+        Some(Condition::Media(match rhs {
+            "image" => MediaType::Image,
+            "text" => MediaType::Text,
+            "video" => MediaType::Video,
+            "audio" => MediaType::Audio,
+            _ => return None,
+        }))
+    })(input)
 }
 
 fn parse_added(input: &str) -> IResult<&str, Condition> {
@@ -74,11 +283,162 @@ fn parse_added(input: &str) -> IResult<&str, Condition> {
                 "before" => DateOp::Before,
                 _ => return None,
             },
-            NaiveDate::parse_from_str(rhs, "%m/%d/%Y").ok()?,
+            NaiveDate::parse_from_str(rhs, "%m/%d/%Y")
+                .or_else(|_| NaiveDate::parse_from_str(rhs, "%Y-%m-%d"))
+                .ok()?,
         ))
     })(input)
 }
 
+fn parse_added_span(input: &str) -> IResult<&str, Condition> {
+    preceded(
+        tag("added:"),
+        alt((
+            map(
+                separated_pair(parse_date_span, tag(".."), parse_date_span),
+                |(start, end)| Condition::AddedBetween(start, end),
+            ),
+            map(
+                pair(opt(parse_compare_op), parse_date_span),
+                |(op, span)| Condition::Added(op.unwrap_or(CompareOp::Equal), span),
+            ),
+        )),
+    )(input)
+}
+
+/// Either `-30d` style relative dates (with `d`, `w`, `m` or `y`),
+/// or ISO dates where the month and day can be left off.
+fn parse_date_span(input: &str) -> IResult<&str, DateSpan> {
+    alt((
+        map(
+            preceded(
+                tag("-"),
+                pair(
+                    map_res(digit1, str::parse),
+                    alt((
+                        value(DateUnit::Days, tag("d")),
+                        value(DateUnit::Weeks, tag("w")),
+                        value(DateUnit::Months, tag("m")),
+                        value(DateUnit::Years, tag("y")),
+                    )),
+                ),
+            ),
+            |(amount, unit)| DateSpan::Ago(amount, unit),
+        ),
+        map_opt(
+            tuple((
+                map_res(
+                    complete::take_while_m_n(4, 4, |c: char| c.is_ascii_digit()),
+                    str::parse,
+                ),
+                opt(preceded(
+                    tag("-"),
+                    map_res(
+                        complete::take_while_m_n(1, 2, |c: char| c.is_ascii_digit()),
+                        str::parse,
+                    ),
+                )),
+                opt(preceded(
+                    tag("-"),
+                    map_res(
+                        complete::take_while_m_n(1, 2, |c: char| c.is_ascii_digit()),
+                        str::parse,
+                    ),
+                )),
+            )),
+            |(year, month, day)| match (month, day) {
+                (None, _) => Some(DateSpan::Year(year)),
+                (Some(month), None) if (1..=12).contains(&month) => {
+                    Some(DateSpan::Month(year, month))
+                }
+                (Some(_), None) => None,
+                (Some(month), Some(day)) => {
+                    NaiveDate::from_ymd_opt(year, month, day).map(DateSpan::Day)
+                }
+            },
+        ),
+    ))(input)
+}
+
+fn parse_quoted(input: &str) -> IResult<&str, &str> {
+    delimited(tag("\""), complete::take_till(|c| c == '"'), tag("\""))(input)
+}
+
+fn has_words(value: &str) -> bool {
+    words(value).next().is_some()
+}
+
+/// Either a single word, or a quoted phrase, as long as it has some words to search for.
+fn parse_text(input: &str) -> IResult<&str, String> {
+    map(
+        verify(alt((parse_quoted, parse_item)), has_words),
+        str::to_lowercase,
+    )(input)
+}
+
+fn parse_phrase(input: &str) -> IResult<&str, Condition> {
+    map(verify(parse_quoted, has_words), |value| {
+        Condition::Phrase(value.to_lowercase())
+    })(input)
+}
+
+fn parse_content(input: &str) -> IResult<&str, Condition> {
+    map(preceded(tag("content:"), parse_text), Condition::Content)(input)
+}
+
+fn parse_description(input: &str) -> IResult<&str, Condition> {
+    map(preceded(tag("desc:"), parse_text), Condition::Description)(input)
+}
+
+fn parse_saved(input: &str) -> IResult<&str, Condition> {
+    map(
+        preceded(
+            tag("saved:"),
+            verify(alt((parse_quoted, parse_item)), |name: &str| {
+                !name.trim().is_empty()
+            }),
+        ),
+        |name| Condition::Saved(name.to_string()),
+    )(input)
+}
+
+fn parse_related(input: &str) -> IResult<&str, Condition> {
+    map(
+        preceded(tag("related:"), map_res(digit1, str::parse::<usize>)),
+        |id| Condition::Related(PieceId::from(id)),
+    )(input)
+}
+
+/// Either `/regex/`, or a glob like `yumi_*`, which has to have a `*` somewhere.
+fn parse_tag_pattern(input: &str) -> IResult<&str, TagPattern> {
+    alt((
+        map_res(
+            delimited(tag("/"), complete::take_till1(|c| c == '/'), tag("/")),
+            TagPattern::regex,
+        ),
+        map(
+            verify(parse_item, |glob: &str| glob.contains('*')),
+            TagPattern::glob,
+        ),
+    ))(input)
+}
+
+fn parse_tag_match(input: &str) -> IResult<&str, Condition> {
+    map(parse_tag_pattern, Condition::TagMatch)(input)
+}
+
+fn parse_tag_match_with_category(input: &str) -> IResult<&str, Condition> {
+    map(
+        separated_pair(parse_item, tag(":"), parse_tag_pattern),
+        |(category, pattern)| {
+            Condition::TagMatchWithCategory(
+                (!category.trim().is_empty()).then(|| category.to_string()),
+                pattern,
+            )
+        },
+    )(input)
+}
+
 fn parse_tag(input: &str) -> IResult<&str, Condition> {
     map(verify(parse_item, |x: &str| !x.is_empty()), |value| {
         Condition::Tag(value.to_string())
@@ -121,29 +481,222 @@ mod test {
         assert!(parse_tag_with_category("input").is_err())
     }
 
+    #[test]
+    fn test_tag_match() {
+        assert_eq!(
+            parse_tag_match("yumi_*"),
+            Ok(("", TagMatch(TagPattern::glob("yumi_*"))))
+        );
+        assert_eq!(
+            parse_tag_match("/^yumi_(lovelace|kou)$/"),
+            Ok((
+                "",
+                TagMatch(TagPattern::regex("^yumi_(lovelace|kou)$").unwrap())
+            ))
+        );
+        assert_eq!(
+            parse_condition("*_outfit"),
+            Ok(("", TagMatch(TagPattern::glob("*_outfit"))))
+        );
+        assert_eq!(parse_condition("yumi"), Ok(("", Tag("yumi".to_owned()))));
+
+        assert!(parse_tag_match("yumi").is_err());
+        assert!(parse_tag_match("//").is_err());
+        assert!(parse_tag_match("/(yumi/").is_err());
+    }
+
+    #[test]
+    fn test_tag_match_with_category() {
+        assert_eq!(
+            parse_condition("artist:*"),
+            Ok((
+                "",
+                TagMatchWithCategory(Some("artist".to_owned()), TagPattern::glob("*"))
+            ))
+        );
+        assert_eq!(
+            parse_condition(":yumi_*"),
+            Ok(("", TagMatchWithCategory(None, TagPattern::glob("yumi_*"))))
+        );
+        assert_eq!(
+            parse_condition("artist:/yumi/"),
+            Ok((
+                "",
+                TagMatchWithCategory(
+                    Some("artist".to_owned()),
+                    TagPattern::regex("yumi").unwrap()
+                )
+            ))
+        );
+        assert_eq!(
+            parse_condition("artist:yumi"),
+            Ok((
+                "",
+                TagWithCategory(Some("artist".to_owned()), "yumi".to_owned())
+            ))
+        );
+    }
+
+    #[test]
+    fn test_pattern() {
+        assert!(TagPattern::glob("yumi_*").matches("yumi_lovelace"));
+        assert!(TagPattern::glob("*_outfit").matches("school_outfit"));
+        assert!(TagPattern::glob("*").matches("anything"));
+        assert!(TagPattern::glob("a.b*").matches("a.bc"));
+        assert!(!TagPattern::glob("a.b*").matches("axbc"));
+        assert!(!TagPattern::glob("yumi_*").matches("not_yumi_lovelace"));
+        assert!(TagPattern::regex("yumi")
+            .unwrap()
+            .matches("not_yumi_lovelace"));
+    }
+
     #[test]
     fn test_price() {
         assert_eq!(
             parse_price("base>=20"),
-            Ok(("", Price(PriceType::Base, PriceOp::GreaterEqual, 20)))
+            Ok(("", Price(PriceType::Base, CompareOp::GreaterEqual, 20)))
         );
         assert_eq!(
             parse_price("base<=20"),
-            Ok(("", Price(PriceType::Base, PriceOp::LesserEqual, 20)))
+            Ok(("", Price(PriceType::Base, CompareOp::LesserEqual, 20)))
         );
         assert_eq!(
             parse_price("tip<=20"),
-            Ok(("", Price(PriceType::Tip, PriceOp::LesserEqual, 20)))
+            Ok(("", Price(PriceType::Tip, CompareOp::LesserEqual, 20)))
         );
         assert_eq!(
             parse_price("total<=20"),
-            Ok(("", Price(PriceType::Total, PriceOp::LesserEqual, 20)))
+            Ok(("", Price(PriceType::Total, CompareOp::LesserEqual, 20)))
         );
         assert!(parse_price("awa>=20").is_err());
         assert!(parse_price("total20").is_err());
         assert!(parse_price("total<=").is_err());
     }
 
+    #[test]
+    fn test_count() {
+        assert_eq!(
+            parse_count("tags:<3"),
+            Ok(("", Count(CountKey::Tags, CompareOp::Lesser, 3)))
+        );
+        assert_eq!(
+            parse_count("blobs:>=5"),
+            Ok(("", Count(CountKey::Blobs, CompareOp::GreaterEqual, 5)))
+        );
+        assert_eq!(
+            parse_count("tags:0"),
+            Ok(("", Count(CountKey::Tags, CompareOp::Equal, 0)))
+        );
+        assert_eq!(
+            parse_condition("tags:=2"),
+            Ok(("", Count(CountKey::Tags, CompareOp::Equal, 2)))
+        );
+
+        assert_eq!(
+            parse_condition("count>10"),
+            Ok(("", Count(CountKey::Pieces, CompareOp::Greater, 10)))
+        );
+        assert_eq!(
+            parse_condition("count:10"),
+            Ok(("", Count(CountKey::Pieces, CompareOp::Equal, 10)))
+        );
+
+        assert!(parse_count("tags:<").is_err());
+        assert!(parse_count("pieces:<3").is_err());
+        assert_eq!(parse_condition("tags3"), Ok(("", Tag("tags3".to_owned()))));
+    }
+
+    #[test]
+    fn test_category_and_type() {
+        assert_eq!(
+            parse_condition("category:artist"),
+            Ok(("", Category("artist".to_owned())))
+        );
+        assert_eq!(parse_condition("type:raw"), Ok(("", Type(BlobType::Raw))));
+        assert_eq!(
+            parse_condition("type:comic"),
+            Ok((
+                "",
+                TagWithCategory(Some("type".to_owned()), "comic".to_owned())
+            ))
+        );
+    }
+
+    #[test]
+    fn test_target() {
+        assert_eq!(
+            parse_query("tags where count>10 category:artist"),
+            Ok((
+                "",
+                Query {
+                    target: Target::Tags,
+                    search: Some(And(vec![
+                        Test(Count(CountKey::Pieces, CompareOp::Greater, 10)),
+                        Test(Category("artist".to_owned())),
+                    ])),
+                    ..Query::default()
+                }
+            ))
+        );
+        assert_eq!(
+            parse_query("blobs where type:raw"),
+            Ok((
+                "",
+                Query {
+                    target: Target::Blobs,
+                    search: Some(Test(Type(BlobType::Raw))),
+                    ..Query::default()
+                }
+            ))
+        );
+        assert_eq!(
+            parse_query("blobs where"),
+            Ok((
+                "",
+                Query {
+                    target: Target::Blobs,
+                    ..Query::default()
+                }
+            ))
+        );
+        // without `where` they're tag names like any other
+        for target in ["tags", "blobs", "pieces"] {
+            assert_eq!(
+                parse_query(target),
+                Ok((
+                    "",
+                    Query {
+                        search: Some(Test(Tag(target.to_owned()))),
+                        ..Query::default()
+                    }
+                ))
+            );
+        }
+        assert_eq!(
+            parse_query("blobs type:raw"),
+            Ok((
+                "",
+                Query {
+                    search: Some(And(vec![
+                        Test(Tag("blobs".to_owned())),
+                        Test(Type(BlobType::Raw)),
+                    ])),
+                    ..Query::default()
+                }
+            ))
+        );
+        assert_eq!(
+            parse_query("tags_outfit"),
+            Ok((
+                "",
+                Query {
+                    search: Some(Test(Tag("tags_outfit".to_owned()))),
+                    ..Query::default()
+                }
+            ))
+        );
+    }
+
     #[test]
     fn test_added() {
         assert_eq!(
@@ -161,10 +714,69 @@ mod test {
             ))
         );
 
+        assert_eq!(
+            parse_added("after:2011-10-25"),
+            Ok((
+                "",
+                DateAdded(DateOp::After, NaiveDate::from_ymd(2011, 10, 25))
+            ))
+        );
+
         assert!(parse_added("wafter:10/25/2011").is_err());
         assert!(parse_added("after:100/24/2011").is_err());
     }
 
+    #[test]
+    fn test_added_span() {
+        assert_eq!(
+            parse_added_span("added:2023"),
+            Ok(("", Added(CompareOp::Equal, DateSpan::Year(2023))))
+        );
+        assert_eq!(
+            parse_added_span("added:<=2023-02"),
+            Ok(("", Added(CompareOp::LesserEqual, DateSpan::Month(2023, 2))))
+        );
+        assert_eq!(
+            parse_added_span("added:>2023-02-14"),
+            Ok((
+                "",
+                Added(
+                    CompareOp::Greater,
+                    DateSpan::Day(NaiveDate::from_ymd(2023, 2, 14))
+                )
+            ))
+        );
+        assert_eq!(
+            parse_added_span("added:>-30d"),
+            Ok((
+                "",
+                Added(CompareOp::Greater, DateSpan::Ago(30, DateUnit::Days))
+            ))
+        );
+        assert_eq!(
+            parse_condition("added:2023-01..2023-06"),
+            Ok((
+                "",
+                AddedBetween(DateSpan::Month(2023, 1), DateSpan::Month(2023, 6))
+            ))
+        );
+        assert_eq!(
+            parse_condition("added:-2y..-6m"),
+            Ok((
+                "",
+                AddedBetween(
+                    DateSpan::Ago(2, DateUnit::Years),
+                    DateSpan::Ago(6, DateUnit::Months)
+                )
+            ))
+        );
+
+        assert!(parse_added_span("added:2023-13").is_err());
+        assert!(parse_added_span("added:2023-02-30").is_err());
+        assert!(parse_added_span("added:-30").is_err());
+        assert!(parse_added_span("added:23").is_err());
+    }
+
     #[test]
     fn test_media() {
         assert_eq!(
@@ -172,6 +784,14 @@ mod test {
             Ok(("", Media(MediaType::Image)))
         );
         assert_eq!(parse_media("media:text"), Ok(("", Media(MediaType::Text))));
+        assert_eq!(
+            parse_media("media:video"),
+            Ok(("", Media(MediaType::Video)))
+        );
+        assert_eq!(
+            parse_media("media:audio"),
+            Ok(("", Media(MediaType::Audio)))
+        );
 
         assert!(parse_media("media:logs").is_err());
         assert!(parse_media("wmedia:text").is_err());
@@ -195,11 +815,89 @@ mod test {
         assert!(parse_source("wsource:commission").is_err());
     }
 
+    #[test]
+    fn test_content() {
+        assert_eq!(
+            parse_content("content:Dress"),
+            Ok(("", Content("dress".to_owned())))
+        );
+
+        assert_eq!(
+            parse_content("content:\"Red Dress\""),
+            Ok(("", Content("red dress".to_owned())))
+        );
+
+        assert!(parse_content("content:").is_err());
+        assert!(parse_content("content:\"\"").is_err());
+        assert!(parse_content("wcontent:dress").is_err());
+    }
+
+    #[test]
+    fn test_description() {
+        assert_eq!(
+            parse_description("desc:Dress"),
+            Ok(("", Description("dress".to_owned())))
+        );
+        assert_eq!(
+            parse_description("desc:\"red dress\""),
+            Ok(("", Description("red dress".to_owned())))
+        );
+
+        assert!(parse_description("desc:").is_err());
+        assert!(parse_description("desc:\"red dress").is_err());
+    }
+
+    #[test]
+    fn test_phrase() {
+        assert_eq!(
+            parse_phrase("\"Red Dress\""),
+            Ok(("", Phrase("red dress".to_owned())))
+        );
+
+        assert!(parse_phrase("\"red dress").is_err());
+        assert!(parse_phrase("\"  \"").is_err());
+        assert!(parse_phrase("red").is_err());
+    }
+
+    #[test]
+    fn test_saved() {
+        assert_eq!(
+            parse_saved("saved:daily"),
+            Ok(("", Saved("daily".to_owned())))
+        );
+        assert_eq!(
+            parse_saved("saved:\"Daily Art\""),
+            Ok(("", Saved("Daily Art".to_owned())))
+        );
+        assert_eq!(
+            parse_condition("saved:daily"),
+            Ok(("", Saved("daily".to_owned())))
+        );
+
+        assert!(parse_saved("saved:").is_err());
+        assert!(parse_saved("saved:\" \"").is_err());
+    }
+
+    #[test]
+    fn test_related() {
+        assert_eq!(
+            parse_related("related:12"),
+            Ok(("", Related(PieceId::from(12))))
+        );
+        assert_eq!(
+            parse_condition("related:12"),
+            Ok(("", Related(PieceId::from(12))))
+        );
+
+        assert!(parse_related("related:").is_err());
+        assert!(parse_related("related:abc").is_err());
+    }
+
     #[test]
     fn test_condition() {
         assert_eq!(
             parse_condition("base>=20"),
-            Ok(("", Price(PriceType::Base, PriceOp::GreaterEqual, 20)))
+            Ok(("", Price(PriceType::Base, CompareOp::GreaterEqual, 20)))
         );
         assert_eq!(
             parse_condition("after:10/25/2011"),
@@ -216,16 +914,20 @@ mod test {
             parse_condition("source:fan"),
             Ok(("", Source(SourceType::FanCreation)))
         );
+        assert_eq!(
+            parse_condition("content:dress"),
+            Ok(("", Content("dress".to_owned())))
+        );
         assert_eq!(parse_condition("input"), Ok(("", Tag("input".to_owned()))));
         assert_eq!(
             parse_condition(":input"),
             Ok(("", TagWithCategory(None, "input".to_owned())))
         );
         assert_eq!(
-            parse_condition("category:input"),
+            parse_condition("artist:input"),
             Ok((
                 "",
-                TagWithCategory(Some("category".to_owned()), "input".to_owned())
+                TagWithCategory(Some("artist".to_owned()), "input".to_owned())
             ))
         );
 
@@ -237,7 +939,10 @@ mod test {
     fn test_parse_test() {
         assert_eq!(
             parse_test("base>=20"),
-            Ok(("", Test(Price(PriceType::Base, PriceOp::GreaterEqual, 20))))
+            Ok((
+                "",
+                Test(Price(PriceType::Base, CompareOp::GreaterEqual, 20))
+            ))
         );
         assert_eq!(
             parse_search("after:10/25/2011"),
@@ -260,11 +965,11 @@ mod test {
             Ok(("", Test(TagWithCategory(None, "input".to_owned()))))
         );
         assert_eq!(
-            parse_test("category:input"),
+            parse_test("artist:input"),
             Ok((
                 "",
                 Test(TagWithCategory(
-                    Some("category".to_owned()),
+                    Some("artist".to_owned()),
                     "input".to_owned()
                 ))
             ))
@@ -282,7 +987,7 @@ mod test {
                 "",
                 Negate(Box::new(Test(Price(
                     PriceType::Base,
-                    PriceOp::GreaterEqual,
+                    CompareOp::GreaterEqual,
                     20
                 ))))
             ))
@@ -293,7 +998,7 @@ mod test {
                 "",
                 Negate(Box::new(Negate(Box::new(Test(Price(
                     PriceType::Base,
-                    PriceOp::GreaterEqual,
+                    CompareOp::GreaterEqual,
                     20
                 ))))))
             ))
@@ -303,7 +1008,10 @@ mod test {
     fn test_parens() {
         assert_eq!(
             parse_paren("(base>=20)"),
-            Ok(("", Test(Price(PriceType::Base, PriceOp::GreaterEqual, 20))))
+            Ok((
+                "",
+                Test(Price(PriceType::Base, CompareOp::GreaterEqual, 20))
+            ))
         );
     }
     #[test]
@@ -313,8 +1021,8 @@ mod test {
             Ok((
                 "",
                 And(vec![
-                    Test(Price(PriceType::Base, PriceOp::GreaterEqual, 20)),
-                    Test(Price(PriceType::Base, PriceOp::LesserEqual, 30)),
+                    Test(Price(PriceType::Base, CompareOp::GreaterEqual, 20)),
+                    Test(Price(PriceType::Base, CompareOp::LesserEqual, 30)),
                 ])
             ))
         );
@@ -326,8 +1034,8 @@ mod test {
             Ok((
                 "",
                 Or(vec![
-                    Test(Price(PriceType::Base, PriceOp::LesserEqual, 20)),
-                    Test(Price(PriceType::Base, PriceOp::GreaterEqual, 30)),
+                    Test(Price(PriceType::Base, CompareOp::LesserEqual, 20)),
+                    Test(Price(PriceType::Base, CompareOp::GreaterEqual, 30)),
                 ])
             ))
         );
@@ -341,7 +1049,7 @@ mod test {
                 "",
                 Negate(Box::new(Test(Price(
                     PriceType::Base,
-                    PriceOp::GreaterEqual,
+                    CompareOp::GreaterEqual,
                     20
                 ))))
             ))
@@ -349,7 +1057,10 @@ mod test {
 
         assert_eq!(
             parse_search("(base>=20)"),
-            Ok(("", Test(Price(PriceType::Base, PriceOp::GreaterEqual, 20))))
+            Ok((
+                "",
+                Test(Price(PriceType::Base, CompareOp::GreaterEqual, 20))
+            ))
         );
 
         assert_eq!(
@@ -357,8 +1068,8 @@ mod test {
             Ok((
                 "",
                 And(vec![
-                    Test(Price(PriceType::Base, PriceOp::GreaterEqual, 20)),
-                    Test(Price(PriceType::Base, PriceOp::LesserEqual, 30)),
+                    Test(Price(PriceType::Base, CompareOp::GreaterEqual, 20)),
+                    Test(Price(PriceType::Base, CompareOp::LesserEqual, 30)),
                 ])
             ))
         );
@@ -368,8 +1079,8 @@ mod test {
             Ok((
                 "",
                 Or(vec![
-                    Test(Price(PriceType::Base, PriceOp::LesserEqual, 20)),
-                    Test(Price(PriceType::Base, PriceOp::GreaterEqual, 30)),
+                    Test(Price(PriceType::Base, CompareOp::LesserEqual, 20)),
+                    Test(Price(PriceType::Base, CompareOp::GreaterEqual, 30)),
                 ])
             ))
         );
@@ -463,6 +1174,123 @@ mod test {
         );
     }
 
+    #[test]
+    fn test_phrase_grouped() {
+        assert_eq!(
+            parse_search("yumi_lovelace \"red dress\"|desc:hat"),
+            Ok((
+                "",
+                And(vec![
+                    Test(Tag("yumi_lovelace".to_owned())),
+                    Or(vec![
+                        Test(Phrase("red dress".to_owned())),
+                        Test(Description("hat".to_owned())),
+                    ])
+                ])
+            ))
+        );
+    }
+
+    #[test]
+    fn test_ordering() {
+        assert_eq!(
+            parse_ordering("order:added"),
+            Ok((
+                "",
+                Ordering {
+                    key: OrderKey::Added,
+                    descending: false
+                }
+            ))
+        );
+        assert_eq!(
+            parse_ordering("order:-price"),
+            Ok((
+                "",
+                Ordering {
+                    key: OrderKey::Price,
+                    descending: true
+                }
+            ))
+        );
+        assert_eq!(
+            parse_ordering("order:tagcount"),
+            Ok((
+                "",
+                Ordering {
+                    key: OrderKey::TagCount,
+                    descending: false
+                }
+            ))
+        );
+
+        assert!(parse_ordering("order:awa").is_err());
+        assert!(parse_ordering("order:--price").is_err());
+        assert!(parse_ordering("worder:price").is_err());
+    }
+
+    #[test]
+    fn test_query() {
+        assert_eq!(parse_query(""), Ok(("", Query::default())));
+        assert_eq!(
+            parse_query("order:random"),
+            Ok((
+                "",
+                Query {
+                    search: None,
+                    ordering: vec![Ordering {
+                        key: OrderKey::Random,
+                        descending: false
+                    }],
+                    seed: 0,
+                    strict: false,
+                    target: Target::Pieces,
+                }
+            ))
+        );
+        assert_eq!(
+            parse_query("yumi_lovelace order:-price casual_outfit|teaching_outfit order:added"),
+            Ok((
+                "",
+                Query {
+                    search: Some(And(vec![
+                        Test(Tag("yumi_lovelace".to_owned())),
+                        Or(vec![
+                            Test(Tag("casual_outfit".to_owned())),
+                            Test(Tag("teaching_outfit".to_owned())),
+                        ])
+                    ])),
+                    ordering: vec![
+                        Ordering {
+                            key: OrderKey::Price,
+                            descending: true
+                        },
+                        Ordering {
+                            key: OrderKey::Added,
+                            descending: false
+                        }
+                    ],
+                    seed: 0,
+                    strict: false,
+                    target: Target::Pieces,
+                }
+            ))
+        );
+        assert_eq!(
+            parse_query("(yumi_lovelace)"),
+            Ok((
+                "",
+                Query {
+                    search: Some(Test(Tag("yumi_lovelace".to_owned()))),
+                    ordering: vec![],
+                    seed: 0,
+                    strict: false,
+                    target: Target::Pieces,
+                }
+            ))
+        );
+    }
+
     #[test]
     fn test_complex() {
         assert_eq!(
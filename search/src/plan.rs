@@ -0,0 +1,463 @@
+use std::collections::BTreeSet;
+
+use chrono::NaiveDate;
+use db::{v3::journal::Change, Db, PieceId, Tag, TagId};
+
+use crate::{
+    execute::{evaluate_test, in_category},
+    model::{Condition, Query, Search, Target},
+    SearchError, SearchIndex,
+};
+
+/// A [`Search`] compiled against a database.
+///
+/// Names are resolved to tags once, up front, and tag conditions are answered from the
+/// [`SearchIndex`]'s lists of pieces for each tag, merging them for unions and intersecting
+/// them with what's left for `and`s, rather than looking at pieces one by one.
+/// Unknown terms are dropped while compiling, following the rules on [`Search`],
+/// so a plan always gives the same pieces as [`Search::execute`].
+///
+/// The index has to be up to date with the database, see [`SearchIndex::apply`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Plan {
+    /// None if the search is unknown as a whole, matching every piece.
+    root: Option<Node>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Node {
+    /// Pieces with any of these tags.
+    Tags(BTreeSet<TagId>),
+    /// Checked piece by piece.
+    Filter(Condition),
+    Or(Vec<Node>),
+    And(Vec<Node>),
+    Not(Box<Node>),
+}
+
+impl Plan {
+    /// Saved searches are expected to be expanded already, see [`crate::model::Query::expand_saved`].
+    pub fn compile(search: &Search, db: &Db) -> Self {
+        Self {
+            root: Node::compile(search, db),
+        }
+    }
+
+    /// The matching pieces, with relative dates counting back from `today`.
+    pub fn execute(&self, db: &Db, index: &SearchIndex, today: NaiveDate) -> BTreeSet<PieceId> {
+        let pieces = db.pieces.keys().collect();
+        match &self.root {
+            Some(root) => root.filter(pieces, db, index, today),
+            None => pieces,
+        }
+    }
+
+    /// Whether a single piece matches.
+    pub fn matches(
+        &self,
+        piece_id: PieceId,
+        db: &Db,
+        index: &SearchIndex,
+        today: NaiveDate,
+    ) -> bool {
+        match &self.root {
+            Some(root) => root.matches(piece_id, db, index, today),
+            None => true,
+        }
+    }
+}
+
+impl Node {
+    /// None if the search is unknown.
+    fn compile(search: &Search, db: &Db) -> Option<Self> {
+        match search {
+            Search::Or(inner) | Search::And(inner) => {
+                let mut known = inner
+                    .iter()
+                    .filter_map(|search| Node::compile(search, db))
+                    .collect::<Vec<_>>();
+                if !inner.is_empty() && known.is_empty() {
+                    return None;
+                }
+                if known.len() == 1 {
+                    return known.pop();
+                }
+                Some(match search {
+                    Search::Or(_) => Node::Or(known),
+                    _ => {
+                        // cheap nodes first, so filters only see what the tags let through
+                        known.sort_by_key(Node::cost);
+                        Node::And(known)
+                    }
+                })
+            }
+            Search::Negate(inner) => Some(Node::Not(Box::new(Node::compile(inner, db)?))),
+            Search::Test(test) => Node::compile_test(test, db),
+        }
+    }
+
+    fn compile_test(test: &Condition, db: &Db) -> Option<Self> {
+        let tags_where = |predicate: &dyn Fn(TagId, &Tag) -> bool| {
+            let tags = db
+                .tags()
+                .filter(|(tag_id, tag)| predicate(*tag_id, tag))
+                .map(|(tag_id, _)| tag_id)
+                .collect::<BTreeSet<_>>();
+            // no tags means the condition is unknown
+            (!tags.is_empty()).then(|| Node::Tags(tags))
+        };
+
+        match test {
+            Condition::Tag(tag_name) => tags_where(&|_, tag| &tag.name == tag_name),
+            Condition::TagWithCategory(category_name, tag_name) => tags_where(&|tag_id, tag| {
+                &tag.name == tag_name && in_category(db, tag_id, category_name.as_ref())
+            }),
+            Condition::TagMatch(pattern) => tags_where(&|_, tag| pattern.matches(&tag.name)),
+            Condition::TagMatchWithCategory(category_name, pattern) => {
+                tags_where(&|tag_id, tag| {
+                    pattern.matches(&tag.name) && in_category(db, tag_id, category_name.as_ref())
+                })
+            }
+            _ if !test.is_known(db, Target::Pieces) => None,
+            // unlike tag names, a category without any tags is still known
+            Condition::Category(category_name) => Some(Node::Tags(
+                db.tags
+                    .keys()
+                    .filter(|tag_id| in_category(db, *tag_id, Some(category_name)))
+                    .collect(),
+            )),
+            _ => Some(Node::Filter(test.clone())),
+        }
+    }
+
+    /// Whether this refers to any piece by ID.
+    fn related(&self) -> bool {
+        match self {
+            Node::Tags(_) => false,
+            Node::Filter(test) => matches!(test, Condition::Related(_)),
+            Node::Or(inner) | Node::And(inner) => inner.iter().any(Node::related),
+            Node::Not(inner) => inner.related(),
+        }
+    }
+
+    fn cost(&self) -> usize {
+        match self {
+            Node::Tags(_) => 0,
+            Node::Filter(_) => 2,
+            Node::Or(_) | Node::And(_) | Node::Not(_) => 1,
+        }
+    }
+
+    /// The candidates matching this node.
+    fn filter(
+        &self,
+        mut candidates: BTreeSet<PieceId>,
+        db: &Db,
+        index: &SearchIndex,
+        today: NaiveDate,
+    ) -> BTreeSet<PieceId> {
+        match self {
+            Node::Tags(tags) => {
+                let tagged = tags
+                    .iter()
+                    .flat_map(|tag_id| index.pieces_for_tag(*tag_id))
+                    .collect::<BTreeSet<_>>();
+                tagged.intersection(&candidates).copied().collect()
+            }
+            Node::Filter(test) => candidates
+                .into_iter()
+                .filter(|piece_id| {
+                    evaluate_test(test, &(*piece_id, &db[*piece_id]), db, index, today)
+                        .unwrap_or(true)
+                })
+                .collect(),
+            Node::Or(inner) => {
+                // later alternatives only need to look at pieces the earlier ones didn't match
+                let mut matched = BTreeSet::new();
+                for node in inner {
+                    let found = node.filter(candidates.clone(), db, index, today);
+                    candidates.retain(|piece_id| !found.contains(piece_id));
+                    matched.extend(found);
+                }
+                matched
+            }
+            Node::And(inner) => inner.iter().fold(candidates, |candidates, node| {
+                node.filter(candidates, db, index, today)
+            }),
+            Node::Not(inner) => {
+                let excluded = inner.filter(candidates.clone(), db, index, today);
+                candidates.retain(|piece_id| !excluded.contains(piece_id));
+                candidates
+            }
+        }
+    }
+
+    fn matches(&self, piece_id: PieceId, db: &Db, index: &SearchIndex, today: NaiveDate) -> bool {
+        match self {
+            Node::Tags(tags) => tags.iter().any(|tag_id| index.has_tag(piece_id, *tag_id)),
+            Node::Filter(test) => {
+                evaluate_test(test, &(piece_id, &db[piece_id]), db, index, today).unwrap_or(true)
+            }
+            Node::Or(inner) => inner
+                .iter()
+                .any(|node| node.matches(piece_id, db, index, today)),
+            Node::And(inner) => inner
+                .iter()
+                .all(|node| node.matches(piece_id, db, index, today)),
+            Node::Not(inner) => !inner.matches(piece_id, db, index, today),
+        }
+    }
+}
+
+/// The pieces a query matches, kept up to date as the database changes
+/// without running the whole query again.
+#[derive(Debug, Clone)]
+pub struct IncrementalSearch {
+    query: Query,
+    /// None if the query matches every piece.
+    plan: Option<Plan>,
+    today: NaiveDate,
+    results: BTreeSet<PieceId>,
+}
+
+impl IncrementalSearch {
+    /// Runs `query` for pieces, whatever its target, failing as [`Query::execute`] does.
+    pub fn new(
+        query: Query,
+        db: &Db,
+        index: &SearchIndex,
+        today: NaiveDate,
+    ) -> Result<Self, SearchError> {
+        let plan = query
+            .prepare(db, Target::Pieces)?
+            .map(|search| Plan::compile(&search, db));
+        let results = match &plan {
+            Some(plan) => plan.execute(db, index, today),
+            None => db.pieces.keys().collect(),
+        };
+        Ok(Self {
+            query,
+            plan,
+            today,
+            results,
+        })
+    }
+
+    pub fn query(&self) -> &Query {
+        &self.query
+    }
+
+    /// The matching pieces, by ID rather than in the query's order.
+    pub fn results(&self) -> &BTreeSet<PieceId> {
+        &self.results
+    }
+
+    /// Brings the results up to date after `changes` were made to `db`, and `index` was
+    /// brought up to date with them. Only the pieces the changes touched are checked again,
+    /// except after tags, categories, collections or saved searches changed, which can
+    /// change what the query's names refer to, or on a new day, since relative dates
+    /// count back from today. Those run the query again from scratch.
+    pub fn apply(
+        &mut self,
+        changes: &[Change],
+        db: &Db,
+        index: &SearchIndex,
+        today: NaiveDate,
+    ) -> Result<(), SearchError> {
+        let related = matches!(&self.plan, Some(Plan { root: Some(root) }) if root.related());
+        let rerun = today != self.today
+            || changes.iter().any(|change| match change {
+                Change::Tag(..)
+                | Change::Category(..)
+                | Change::TagCategory(..)
+                | Change::SavedSearch(..)
+                | Change::Collection(..) => true,
+                // `related:` is unknown for pieces that don't exist
+                Change::Piece(..) => related,
+                _ => false,
+            });
+        if rerun {
+            *self = Self::new(self.query.clone(), db, index, today)?;
+            return Ok(());
+        }
+
+        let mut touched = BTreeSet::new();
+        for change in changes {
+            match change {
+                Change::Piece(piece_id, _)
+                | Change::Media(piece_id, _, _)
+                | Change::PieceTag(piece_id, _, _)
+                | Change::Review(piece_id, _) => {
+                    touched.insert(*piece_id);
+                }
+                Change::Relation(relation, _) => {
+                    touched.insert(relation.src);
+                    touched.insert(relation.dest);
+                }
+                Change::Blob(blob_id, _) => touched.extend(db.pieces_for_blob(*blob_id)),
+                _ => {}
+            }
+        }
+        for piece_id in touched {
+            let matches = db.exists(piece_id)
+                && match &self.plan {
+                    Some(plan) => plan.matches(piece_id, db, index, today),
+                    None => true,
+                };
+            if matches {
+                self.results.insert(piece_id);
+            } else {
+                self.results.remove(&piece_id);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::model::{CompareOp, CountKey};
+    use crate::parse_search;
+    use db::{
+        v3::commands::{AttachCategory, AttachTag},
+        Category, Piece,
+    };
+
+    fn compile(db: &Db, input: &str) -> Plan {
+        let (_, search) = parse_search(input).unwrap();
+        Plan::compile(&search, db)
+    }
+
+    fn tag(db: &mut Db, name: &str) -> TagId {
+        db.create_tag(Tag {
+            name: name.to_string(),
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn test_compile() {
+        let mut db = Db::default();
+        let red = tag(&mut db, "red");
+        let artist_red = tag(&mut db, "red");
+        let artist = db.create_category(Category {
+            name: "artist".to_string(),
+            ..Default::default()
+        });
+        db.attach_category(AttachCategory {
+            src: artist_red,
+            dest: Some(artist),
+        });
+
+        assert_eq!(compile(&db, "red missing"), compile(&db, "red"));
+        assert_eq!(compile(&db, "!(missing|typo)"), Plan { root: None });
+        assert_eq!(
+            compile(&db, "red"),
+            Plan {
+                root: Some(Node::Tags(vec![red, artist_red].into_iter().collect()))
+            }
+        );
+        assert_eq!(
+            compile(&db, "artist:red"),
+            Plan {
+                root: Some(Node::Tags(vec![artist_red].into_iter().collect()))
+            }
+        );
+        // tags go before filters
+        assert_eq!(
+            compile(&db, "tags:>1 :red"),
+            Plan {
+                root: Some(Node::And(vec![
+                    Node::Tags(vec![red].into_iter().collect()),
+                    Node::Filter(Condition::Count(CountKey::Tags, CompareOp::Greater, 1)),
+                ]))
+            }
+        );
+    }
+
+    #[test]
+    fn test_execute() {
+        let today = NaiveDate::from_ymd(2023, 3, 31);
+        let mut db = Db::default();
+        let red = tag(&mut db, "red");
+        let dress = tag(&mut db, "dress");
+        let pieces = (0..3)
+            .map(|_| db.create_piece(Piece::default()))
+            .collect::<Vec<_>>();
+        for (piece, tag) in [(0, red), (1, red), (1, dress), (2, dress)] {
+            db.attach_tag(AttachTag {
+                src: pieces[piece],
+                dest: tag,
+            });
+        }
+        let index = SearchIndex::build(&db);
+
+        let run = |input| {
+            let plan = compile(&db, input);
+            let found = plan.execute(&db, &index, today);
+            for piece_id in &pieces {
+                assert_eq!(
+                    plan.matches(*piece_id, &db, &index, today),
+                    found.contains(piece_id)
+                );
+            }
+            found.into_iter().collect::<Vec<_>>()
+        };
+        assert_eq!(run("red"), vec![pieces[0], pieces[1]]);
+        assert_eq!(run("red dress"), vec![pieces[1]]);
+        assert_eq!(run("red|dress !(red dress)"), vec![pieces[0], pieces[2]]);
+        assert_eq!(run("missing"), pieces);
+    }
+
+    #[test]
+    fn test_incremental() {
+        let today = NaiveDate::from_ymd(2023, 3, 31);
+        let mut db = Db::default();
+        let red = tag(&mut db, "red");
+        let pieces = (0..3)
+            .map(|_| db.create_piece(Piece::default()))
+            .collect::<Vec<_>>();
+        let mut index = SearchIndex::build(&db);
+        let (_, query) = crate::parse_query("red").unwrap();
+        let mut search = IncrementalSearch::new(query.clone(), &db, &index, today).unwrap();
+        assert!(search.results().is_empty());
+
+        let mut step = |db: &mut Db, edit: &dyn Fn(&mut Db)| {
+            let before = db.clone();
+            edit(db);
+            let changes = db.changes_since(&before);
+            for change in &changes {
+                index.apply(db, change);
+            }
+            search.apply(&changes, db, &index, today).unwrap();
+            let fresh = IncrementalSearch::new(query.clone(), db, &index, today).unwrap();
+            assert_eq!(search.results(), fresh.results());
+            search.results().iter().copied().collect::<Vec<_>>()
+        };
+        let attach = |piece_id| {
+            move |db: &mut Db| {
+                db.attach_tag(AttachTag {
+                    src: piece_id,
+                    dest: red,
+                });
+            }
+        };
+        assert_eq!(step(&mut db, &attach(pieces[0])), vec![pieces[0]]);
+        assert_eq!(
+            step(&mut db, &attach(pieces[2])),
+            vec![pieces[0], pieces[2]]
+        );
+        assert_eq!(
+            step(&mut db, &|db| {
+                db.delete(pieces[0]);
+            }),
+            vec![pieces[2]]
+        );
+        assert_eq!(
+            step(&mut db, &|db| db.tags.get_mut(red).unwrap().name =
+                "blue".into()),
+            vec![pieces[1], pieces[2]]
+        );
+    }
+}
@@ -0,0 +1,175 @@
+use std::fmt;
+
+use db::Db;
+
+use crate::{
+    model::{Condition, Query, Search},
+    parse_query,
+};
+
+/// Why the `saved:` terms in a query couldn't be expanded.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SavedSearchError {
+    /// No search is saved under this name.
+    Unknown(String),
+    /// The search saved under this name doesn't parse.
+    Invalid(String),
+    /// The saved searches refer back to themselves, in this order.
+    Recursive(Vec<String>),
+}
+
+impl fmt::Display for SavedSearchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SavedSearchError::Unknown(name) => write!(f, "No search is saved as \"{}\".", name),
+            SavedSearchError::Invalid(name) => {
+                write!(f, "The search saved as \"{}\" is invalid.", name)
+            }
+            SavedSearchError::Recursive(names) => {
+                write!(
+                    f,
+                    "Saved searches refer to themselves: {}.",
+                    names.join(" -> ")
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for SavedSearchError {}
+
+impl Query {
+    /// Replaces every `saved:name` term with the terms of the search saved under that name.
+    /// The ordering of a saved search is ignored when it's used inside another query.
+    pub fn expand_saved(self, db: &Db) -> Result<Self, SavedSearchError> {
+        Ok(Self {
+            search: self
+                .search
+                .map(|search| search.expand_saved(db, &mut Vec::new()))
+                .transpose()?,
+            ..self
+        })
+    }
+}
+
+impl Search {
+    /// `expanding` holds the names of the saved searches currently being expanded,
+    /// so a search that (eventually) refers to itself is caught instead of looping forever.
+    fn expand_saved(self, db: &Db, expanding: &mut Vec<String>) -> Result<Self, SavedSearchError> {
+        Ok(match self {
+            Search::Or(inner) => Search::Or(
+                inner
+                    .into_iter()
+                    .map(|search| search.expand_saved(db, expanding))
+                    .collect::<Result<_, _>>()?,
+            ),
+            Search::And(inner) => Search::And(
+                inner
+                    .into_iter()
+                    .map(|search| search.expand_saved(db, expanding))
+                    .collect::<Result<_, _>>()?,
+            ),
+            Search::Negate(inner) => Search::Negate(Box::new(inner.expand_saved(db, expanding)?)),
+            Search::Test(Condition::Saved(name)) => {
+                if let Some(start) = expanding.iter().position(|other| other == &name) {
+                    let mut cycle = expanding[start..].to_vec();
+                    cycle.push(name);
+                    return Err(SavedSearchError::Recursive(cycle));
+                }
+
+                let saved = db
+                    .find_saved_search(&name)
+                    .ok_or_else(|| SavedSearchError::Unknown(name.clone()))?;
+                let query = match parse_query(db[saved].query.trim()) {
+                    Ok(("", query)) => query,
+                    _ => return Err(SavedSearchError::Invalid(name)),
+                };
+
+                expanding.push(name);
+                let search = match query.search {
+                    Some(search) => search.expand_saved(db, expanding)?,
+                    // a saved search without any terms matches everything
+                    None => Search::And(Vec::new()),
+                };
+                expanding.pop();
+                search
+            }
+            test @ Search::Test(_) => test,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use db::SavedSearch;
+
+    fn save(db: &mut Db, name: &str, query: &str) {
+        db.create_saved_search(SavedSearch {
+            name: name.to_string(),
+            query: query.to_string(),
+            ..Default::default()
+        });
+    }
+
+    fn expand(db: &Db, input: &str) -> Result<Option<Search>, SavedSearchError> {
+        let (_, query) = parse_query(input).unwrap();
+        query.expand_saved(db).map(|query| query.search)
+    }
+
+    #[test]
+    fn test_expand() {
+        let mut db = Db::default();
+        save(&mut db, "dresses", "dress order:random");
+        save(&mut db, "red_dresses", "saved:dresses red");
+        save(&mut db, "everything", "order:added");
+
+        assert_eq!(
+            expand(&db, "!saved:red_dresses"),
+            Ok(Some(Search::Negate(Box::new(Search::And(vec![
+                Search::Test(Condition::Tag("dress".to_owned())),
+                Search::Test(Condition::Tag("red".to_owned())),
+            ])))))
+        );
+        assert_eq!(
+            expand(&db, "saved:everything"),
+            Ok(Some(Search::And(Vec::new())))
+        );
+        assert_eq!(
+            expand(&db, "saved:missing"),
+            Err(SavedSearchError::Unknown("missing".to_owned()))
+        );
+    }
+
+    #[test]
+    fn test_recursive() {
+        let mut db = Db::default();
+        save(&mut db, "a", "saved:b");
+        save(&mut db, "b", "dress|saved:a");
+        save(&mut db, "c", "saved:c");
+        save(&mut db, "twice", "saved:c2 saved:c2");
+        save(&mut db, "c2", "dress");
+        save(&mut db, "broken", "(dress");
+
+        assert_eq!(
+            expand(&db, "saved:a"),
+            Err(SavedSearchError::Recursive(vec![
+                "a".to_owned(),
+                "b".to_owned(),
+                "a".to_owned()
+            ]))
+        );
+        assert_eq!(
+            expand(&db, "red saved:c"),
+            Err(SavedSearchError::Recursive(vec![
+                "c".to_owned(),
+                "c".to_owned()
+            ]))
+        );
+        assert!(expand(&db, "saved:twice").is_ok());
+        assert_eq!(
+            expand(&db, "saved:broken"),
+            Err(SavedSearchError::Invalid("broken".to_owned()))
+        );
+    }
+}
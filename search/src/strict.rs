@@ -0,0 +1,117 @@
+use std::fmt;
+
+use db::Db;
+
+use crate::{
+    execute::in_category,
+    model::{Condition, CountKey, Search, Target},
+    SavedSearchError,
+};
+
+/// Why a query couldn't be executed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SearchError {
+    Saved(SavedSearchError),
+    /// Terms which don't refer to anything in the database, as they were written.
+    /// Only reported by strict queries, see [`crate::model::Query::strict`].
+    Unknown(Vec<String>),
+}
+
+impl fmt::Display for SearchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SearchError::Saved(err) => err.fmt(f),
+            SearchError::Unknown(terms) => write!(f, "Unknown terms: {}.", terms.join(", ")),
+        }
+    }
+}
+
+impl std::error::Error for SearchError {}
+
+impl From<SavedSearchError> for SearchError {
+    fn from(err: SavedSearchError) -> Self {
+        SearchError::Saved(err)
+    }
+}
+
+impl Search {
+    /// The terms that are ignored when searching `db`, because they don't refer to anything in it.
+    /// Saved searches are expected to be expanded already.
+    pub fn unknown_terms(&self, db: &Db, target: Target) -> Vec<String> {
+        let mut terms = Vec::new();
+        self.collect_unknown(db, target, &mut terms);
+        terms
+    }
+
+    fn collect_unknown(&self, db: &Db, target: Target, terms: &mut Vec<String>) {
+        match self {
+            Search::Or(inner) | Search::And(inner) => {
+                for search in inner {
+                    search.collect_unknown(db, target, terms);
+                }
+            }
+            Search::Negate(inner) => inner.collect_unknown(db, target, terms),
+            Search::Test(test) => {
+                if !test.is_known(db, target) {
+                    terms.push(test.term());
+                }
+            }
+        }
+    }
+}
+
+impl Condition {
+    /// Whether the condition refers to things that exist in `db`, and makes sense for the target.
+    /// Conditions on a piece's own fields are always known.
+    pub(crate) fn is_known(&self, db: &Db, target: Target) -> bool {
+        match self {
+            Condition::Tag(tag_name) => db.tags().any(|(_, tag)| &tag.name == tag_name),
+            Condition::TagWithCategory(category_name, tag_name) => {
+                db.tags().any(|(tag_id, tag)| {
+                    &tag.name == tag_name && in_category(db, tag_id, category_name.as_ref())
+                })
+            }
+            Condition::TagMatch(pattern) => db.tags().any(|(_, tag)| pattern.matches(&tag.name)),
+            Condition::TagMatchWithCategory(category_name, pattern) => {
+                db.tags().any(|(tag_id, tag)| {
+                    pattern.matches(&tag.name) && in_category(db, tag_id, category_name.as_ref())
+                })
+            }
+            Condition::Category(category_name) => db
+                .categories()
+                .any(|(_, category)| &category.name == category_name),
+            Condition::Count(CountKey::Pieces, ..) => target != Target::Pieces,
+            // saved searches are expected to be expanded already
+            Condition::Saved(_) => false,
+            Condition::Related(related) => db.exists(*related),
+            _ => true,
+        }
+    }
+
+    /// The condition as it would be written in a query, for the ones that can be unknown.
+    fn term(&self) -> String {
+        match self {
+            Condition::Tag(tag_name) => tag_name.clone(),
+            Condition::TagWithCategory(category_name, tag_name) => {
+                format!(
+                    "{}:{}",
+                    category_name.as_deref().unwrap_or_default(),
+                    tag_name
+                )
+            }
+            Condition::TagMatch(pattern) => pattern.to_string(),
+            Condition::TagMatchWithCategory(category_name, pattern) => {
+                format!(
+                    "{}:{}",
+                    category_name.as_deref().unwrap_or_default(),
+                    pattern
+                )
+            }
+            Condition::Category(category_name) => format!("category:{}", category_name),
+            Condition::Count(_, op, count) => format!("count{}{}", op, count),
+            Condition::Saved(name) => format!("saved:{}", name),
+            Condition::Related(related) => format!("related:{}", related),
+            _ => format!("{:?}", self),
+        }
+    }
+}
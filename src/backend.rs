@@ -1,18 +1,60 @@
 use std::{
     ops::{Deref, DerefMut},
-    path::PathBuf,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
 };
 
+use anyhow::bail;
 use tokio::fs;
 
-use db::{v2::DbV2 as Db, BlobId, DbV1};
+use db::{
+    v3::{commands::AttachBlob, journal::Change, DbV3 as Db},
+    Blob, BlobId, MediaType, PieceId, StorageLayout,
+};
+use search::{index::Document, SearchIndex};
 #[allow(dead_code)]
 pub mod actor;
+pub mod blob;
+pub mod bulk;
+pub mod export;
+pub mod inbox;
+pub mod journal;
+pub mod media;
+pub mod session;
+pub mod store;
+pub mod thumbnails;
+
+use self::{
+    journal::{journal_file, Journal},
+    session::{ExternalChange, Owner, Saved, Session, Stamp},
+    store::{BlobStore, StoreConfig},
+};
 
 #[derive(Clone, Debug)]
 pub struct DbBackend {
     pub root: PathBuf,
     pub inner: Db,
+    pub index: SearchIndex,
+    /// Where blobs' files are kept, at the keys [`Db::storage_for`] gives.
+    pub store: Arc<dyn BlobStore>,
+    pub session: Arc<Mutex<Session>>,
+    /// Set whenever the database is borrowed mutably, so [`DbBackend::record`] only
+    /// looks for changes when there could be some.
+    pub edited: bool,
+    /// Changes whenever the database or the search index does, and is never the same for
+    /// two different databases, so results can be kept until it changes.
+    pub generation: u64,
+    /// The generation before this one and what was changed since, so results from then can
+    /// be brought up to date instead of worked out again. None if the index was rebuilt.
+    pub last_changes: Option<(u64, Arc<Vec<Change>>)>,
+}
+
+fn next_generation() -> u64 {
+    static NEXT: AtomicU64 = AtomicU64::new(0);
+    NEXT.fetch_add(1, Ordering::Relaxed)
 }
 
 impl Deref for DbBackend {
@@ -24,6 +66,7 @@ impl Deref for DbBackend {
 }
 impl DerefMut for DbBackend {
     fn deref_mut(&mut self) -> &mut Self::Target {
+        self.edited = true;
         &mut self.inner
     }
 }
@@ -33,38 +76,278 @@ pub fn data_file(mut path: PathBuf) -> PathBuf {
     path
 }
 
+/// Reads a database file, upgrading it if it's an older version.
+fn deserialize(data: &[u8]) -> anyhow::Result<Db> {
+    Ok(db::from_bytes(data)?)
+}
+
+/// Reads the database in `root` with its journal replayed, along with the stamps of its files
+/// and whether the journal has to be started again, since its last entry was cut short
+/// or it doesn't follow on from the snapshot. Having no journal yet is fine, one's started
+/// the first time there's something to put in it.
+fn load(root: &Path) -> anyhow::Result<(Db, Saved, bool)> {
+    let (db, snapshot, journal) = journal::load(root)?;
+    let saved = Saved {
+        snapshot: Stamp::new(&data_file(root.to_path_buf()), &snapshot)?,
+        journal: Stamp::of(&journal_file(root))?,
+    };
+    let stale = !journal.entries.is_empty() && !journal.follows(&snapshot);
+    Ok((db, saved, journal.torn || stale))
+}
+
 impl DbBackend {
+    /// Saves the database, unless it's read only or someone else has saved over it since it
+    /// was last loaded or saved here, which fails with [`ExternalChange`].
     pub fn save(&self) -> anyhow::Result<()> {
-        std::fs::write(
-            data_file(self.root.clone()),
-            bincode::serialize::<Db>(self)?,
-        )?;
+        let mut session = self.session.lock().unwrap();
+        if let Some(saved) = session.saved {
+            if !saved.matches(&self.root)? {
+                return Err(ExternalChange.into());
+            }
+        }
+        self.write(&mut session, false)
+    }
+
+    /// Saves the database even if someone else has saved over it, throwing their changes away.
+    /// They can still be recovered from the database's history.
+    pub fn save_over(&self) -> anyhow::Result<()> {
+        self.write(&mut self.session.lock().unwrap(), true)
+    }
+
+    /// Adds the changes recorded since the last save to the journal, then writes a new snapshot
+    /// if the journal has grown bigger than the last one. When `over` is set the journal is
+    /// someone else's, so a new snapshot is written straight away instead.
+    fn write(&self, session: &mut Session, over: bool) -> anyhow::Result<()> {
+        if let Some(holder) = session.read_only() {
+            bail!("The database is read only, since {} is editing it.", holder);
+        }
+        let (snapshot, journal) = (data_file(self.root.clone()), journal_file(&self.root));
+        let size = |path: &Path| path.metadata().map(|metadata| metadata.len()).unwrap_or(0);
+        session.record(&self.inner);
+
+        if let (Some(saved), false) = (session.saved, over) {
+            if !session.pending.is_empty() {
+                if size(&journal) == 0 {
+                    let data = std::fs::read(&snapshot)?;
+                    Journal::start(&journal, &data, &Owner::current().name())?;
+                }
+                Journal::append(&journal, &session.pending)?;
+            }
+            // marked saved straight away, so the changes aren't journaled twice if compacting fails
+            session.mark_saved(Saved {
+                journal: Stamp::of(&journal)?,
+                ..saved
+            });
+            if size(&journal) <= size(&snapshot) {
+                return Ok(());
+            }
+        }
+
+        let data = db::to_bytes(&self.inner)?;
+        journal::compact(&self.root, &data, &Owner::current().name())?;
+        session.mark_saved(Saved {
+            snapshot: Stamp::new(&snapshot, &data)?,
+            journal: Stamp::of(&journal)?,
+        });
         Ok(())
     }
 
+    /// Notes down what's changed since the last time as one change, to go in the journal
+    /// when the database is saved, and brings the search index up to date with everything
+    /// recorded since. Cheap unless the database was borrowed mutably since.
+    pub fn record(&mut self) {
+        let changes = {
+            let mut session = self.session.lock().unwrap();
+            if std::mem::take(&mut self.edited) {
+                session.record(&self.inner);
+            }
+            session.take_unindexed()
+        };
+        if changes.is_empty() {
+            return;
+        }
+        for change in &changes {
+            self.index.apply(&self.inner, change);
+        }
+        self.last_changes = Some((self.generation, Arc::new(changes)));
+        self.generation = next_generation();
+    }
+
+    /// Whether anything's been changed since the database was last saved. Edits still being
+    /// made count too, even if they don't end up changing anything.
+    pub fn unsaved(&self) -> bool {
+        self.edited || !self.session.lock().unwrap().pending.is_empty()
+    }
+
+    /// Who's editing the database, if it's read only here.
+    pub fn read_only(&self) -> Option<Owner> {
+        self.session.lock().unwrap().read_only().cloned()
+    }
+
+    /// Opens the database for editing even though someone else has it locked.
+    pub fn take_over(&self) -> anyhow::Result<()> {
+        self.session.lock().unwrap().take_over(&self.root)
+    }
+
+    /// Lets others edit the database, for when it's about to be closed.
+    pub fn unlock(&self) {
+        self.session.lock().unwrap().release();
+    }
+
     pub async fn from_directory(root: PathBuf) -> anyhow::Result<Self> {
         Self::from_file(data_file(root.clone())).await
     }
 
     pub async fn from_file(mut root: PathBuf) -> anyhow::Result<Self> {
-        let data = &fs::read(root.clone()).await?;
-        let db = bincode::deserialize::<DbV1>(data)
-            .map(|item| item.into())
-            .or_else(|_| bincode::deserialize::<Db>(data))?;
         root.pop();
-        Ok(Self { root, inner: db })
+        let (db, saved, restart) = load(&root)?;
+        let mut ret = Self {
+            store: StoreConfig::load(&root)?.open(&root)?,
+            session: Arc::new(Mutex::new(Session::open(&root, Some(saved), db.clone())?)),
+            root,
+            inner: db,
+            index: SearchIndex::default(),
+            edited: false,
+            generation: next_generation(),
+            last_changes: None,
+        };
+        // anything journaled after an entry cut short, or to a journal the snapshot has
+        // replaced, would be lost
+        if restart && ret.read_only().is_none() {
+            ret.save_over()?;
+        }
+        ret.reindex().await;
+        Ok(ret)
     }
 
     pub async fn init_at_directory(root: PathBuf) -> anyhow::Result<Self> {
         let db = Db::default();
-        let ret = Self { root, inner: db };
+        let ret = Self {
+            store: StoreConfig::load(&root)?.open(&root)?,
+            session: Arc::new(Mutex::new(Session::open(&root, None, db.clone())?)),
+            root,
+            inner: db,
+            index: SearchIndex::default(),
+            edited: false,
+            generation: next_generation(),
+            last_changes: None,
+        };
 
         Ok(ret)
     }
 
-    pub fn storage_for(&self, id: BlobId) -> PathBuf {
-        let mut temp = self.root.clone();
-        temp.push(self.inner.storage_for(id));
-        temp
+    /// Reads the database file again, after someone else has saved over it.
+    /// When merging, the changes made here since it was last saved are kept on top of theirs,
+    /// unless both changed the same things, which fails with [`MergeConflict`] and leaves
+    /// everything as it was.
+    ///
+    /// [`MergeConflict`]: db::v3::MergeConflict
+    pub async fn reload(&self, merge: bool) -> anyhow::Result<Self> {
+        let (theirs, saved, _) = load(&self.root)?;
+        let inner = {
+            let mut session = self.session.lock().unwrap();
+            let inner = if merge {
+                Db::merge(&session.base, &self.inner, theirs.clone())?
+            } else {
+                theirs.clone()
+            };
+            // what was recorded here went on top of the old base, so the merge is recorded afresh
+            session.rebase(saved, theirs);
+            inner
+        };
+
+        let mut ret = Self {
+            root: self.root.clone(),
+            inner,
+            index: SearchIndex::default(),
+            store: self.store.clone(),
+            session: self.session.clone(),
+            edited: false,
+            generation: next_generation(),
+            last_changes: None,
+        };
+        ret.reindex().await;
+        Ok(ret)
+    }
+
+    /// Rebuilds the search index from the database's descriptions and the text blobs in its store.
+    /// Blobs that can't be read are left out of the index.
+    pub async fn reindex(&mut self) {
+        let mut index = SearchIndex::build(&self.inner);
+        for (blob_id, blob) in self.inner.blobs() {
+            if blob.media_type() == MediaType::Text {
+                let key = self.inner.storage_for(blob_id);
+                if let Ok(path) = self.store.fetch(&key).await {
+                    if let Ok(text) = fs::read_to_string(path).await {
+                        index.index_text(Document::Blob(blob_id), &text);
+                    }
+                }
+            }
+        }
+        self.index = index;
+        self.generation = next_generation();
+        self.last_changes = None;
+    }
+
+    /// Adds `blob` to the piece `to`, returning its ID and the key its file belongs at.
+    /// Putting the file in the store is left to the caller.
+    pub fn add_blob(&mut self, to: PieceId, blob: Blob) -> (BlobId, PathBuf) {
+        let id = self.create_blob(blob);
+        self.attach_blob(AttachBlob { src: to, dest: id });
+        let location = self.layout_path(id);
+        self.blobs.get_mut(id).unwrap().location = Some(location.clone());
+        (id, location)
+    }
+
+    /// Moves every blob's file to where `layout` puts it, and stores new blobs that way from now on.
+    /// Files are renamed in the store rather than copied. Blobs whose files are missing are left
+    /// where they were, and are counted in the second number returned, after how many files were moved.
+    ///
+    /// Stops at the first file that can't be moved, leaving everything before it moved,
+    /// so the database should be saved either way.
+    pub async fn migrate_storage(
+        &mut self,
+        layout: StorageLayout,
+    ) -> anyhow::Result<(usize, usize)> {
+        self.storage_layout = layout;
+
+        let (mut moved, mut missing) = (0, 0);
+        for id in self.blobs.keys().collect::<Vec<_>>() {
+            let from = self.inner.storage_for(id);
+            let location = self.layout_path(id);
+            if from == location {
+                self.blobs.get_mut(id).unwrap().location = Some(location);
+                continue;
+            }
+            if !self.store.exists(&from).await? {
+                missing += 1;
+                continue;
+            }
+
+            self.store.rename(&from, &location).await?;
+            self.blobs.get_mut(id).unwrap().location = Some(location);
+            moved += 1;
+        }
+        Ok((moved, missing))
+    }
+
+    /// Copies every blob's file from `from` into this database's store, leaving those
+    /// already there alone. Returns how many were copied, then how many `from` doesn't have.
+    pub async fn copy_files_from(&self, from: &dyn BlobStore) -> anyhow::Result<(usize, usize)> {
+        let (mut copied, mut missing) = (0, 0);
+        for (id, _) in self.blobs() {
+            let key = self.inner.storage_for(id);
+            if self.store.exists(&key).await? {
+                continue;
+            }
+            match from.fetch(&key).await {
+                Ok(path) => {
+                    self.store.put(&key, &path).await?;
+                    copied += 1;
+                }
+                Err(_) => missing += 1,
+            }
+        }
+        Ok((copied, missing))
     }
 }
@@ -6,50 +6,24 @@ use std::{
 };
 
 use db::{
-    v2::commands::{
-        AttachBlob, AttachCategory, AttachTag, EditBlob, EditCategory, EditPiece, EditTag,
-    },
-    v2::DbV2 as Db,
-    v2::Piece,
-    v2::PieceId,
-    BlobId, BlobType, Category, CategoryId, Tag, TagId,
+    v3::commands::{AttachCategory, AttachTag, EditBlob, EditCategory, EditPiece, EditTag},
+    v3::Piece,
+    v3::PieceId,
+    BlobId, BlobType, Category, CategoryId, MediaType, Tag, TagId,
 };
 use futures_util::{stream::FuturesUnordered, StreamExt};
 use itertools::Itertools;
 use regex::Regex;
 use rfd::AsyncFileDialog;
+use search::index::Document;
 use tokio::{
     fs,
     sync::{mpsc, oneshot, watch},
 };
-mod blob {
-    use std::{
-        collections::hash_map::DefaultHasher,
-        hash::{Hash, Hasher},
-        path::PathBuf,
-    };
-
-    use chrono::Local;
-    use db::{Blob, BlobType};
-
-    pub async fn from_path(path: PathBuf, blob_type: BlobType) -> anyhow::Result<Blob> {
-        let raw_data = tokio::fs::read(&path).await?;
-        let mut hash = DefaultHasher::new();
-        raw_data.hash(&mut hash);
-        let hash = hash.finish();
-
-        Ok(Blob {
-            file_name: path.file_name().unwrap().to_string_lossy().into_owned(),
-            hash,
-            blob_type,
-            added: Local::today().naive_local(),
-        })
-    }
-}
 
 use crate::config::Config;
 
-use super::{data_file, DbBackend};
+use super::{blob, DbBackend};
 
 #[derive(Debug, Clone)]
 pub struct DbHandle {
@@ -317,19 +291,32 @@ async fn db_actor(
                     let files: Vec<_> = file_futures.collect().await;
 
                     let mut out_futures = FuturesUnordered::new();
+                    let mut text_blobs = Vec::new();
                     {
                         let mut db = data.write().unwrap();
 
                         for (path, blob) in files.into_iter().filter_map(Result::ok) {
-                            let id = db.create_blob(blob);
-
-                            db.attach_blob(AttachBlob { src: to, dest: id });
-                            out_futures.push(fs::copy(path, db.storage_for(id)));
+                            let is_text = blob.media_type() == MediaType::Text;
+                            let (id, key) = db.add_blob(to, blob);
+
+                            if is_text {
+                                text_blobs.push((id, path.clone()));
+                            }
+                            let store = db.store.clone();
+                            out_futures.push(async move { store.put(&key, &path).await });
                         }
                     }
                     while let Some(result) = out_futures.next().await {
                         result.unwrap();
                     }
+                    for (id, path) in text_blobs {
+                        if let Ok(text) = fs::read_to_string(path).await {
+                            data.write()
+                                .unwrap()
+                                .index
+                                .index_text(Document::Blob(id), &text);
+                        }
+                    }
 
                     dirty.send(()).unwrap();
                 });
@@ -341,9 +328,13 @@ async fn db_actor(
                     continue;
                 }
 
-                let (storage, file_name) = {
+                let (store, key, file_name) = {
                     let db = data.read().unwrap();
-                    (db.storage_for(id), db[id].file_name.clone())
+                    (
+                        db.store.clone(),
+                        db.storage_for(id),
+                        db[id].file_name.clone(),
+                    )
                 };
                 let dirty = dirty.clone();
 
@@ -361,6 +352,7 @@ async fn db_actor(
                         return;
                     };
 
+                    let storage = store.fetch(&key).await.unwrap();
                     fs::copy(storage, file.path()).await.unwrap();
 
                     dirty.send(()).unwrap();
@@ -380,16 +372,22 @@ async fn db_actor(
                 let dirty = dirty.clone();
                 tokio::spawn(async move {
                     let blob = blob::from_path(path.clone(), blob_type).await.unwrap();
+                    let is_text = blob.media_type() == MediaType::Text;
 
-                    let storage = {
+                    let (id, key, store) = {
                         let mut db = data.write().unwrap();
-
-                        let id = db.create_blob(blob);
-
-                        db.attach_blob(AttachBlob { src: to, dest: id });
-                        db.storage_for(id)
+                        let (id, key) = db.add_blob(to, blob);
+                        (id, key, db.store.clone())
                     };
-                    fs::copy(path, storage).await.unwrap();
+                    store.put(&key, &path).await.unwrap();
+                    if is_text {
+                        if let Ok(text) = fs::read_to_string(path).await {
+                            data.write()
+                                .unwrap()
+                                .index
+                                .index_text(Document::Blob(id), &text);
+                        }
+                    }
                     dirty.send(()).unwrap();
                 });
             }
@@ -403,13 +401,20 @@ async fn db_actor(
                         .collect_vec();
                     for blob_id in dangling_blobs {
                         db.delete(blob_id);
+                        db.index.remove(Document::Blob(blob_id));
                     }
                 }
 
                 let (paths, root) = {
                     let db = data.read().unwrap();
+                    // files in the database's folder only belong to it when it's the store
+                    if !db.store.is_local() {
+                        continue;
+                    }
                     (
-                        db.blobs().map(|(id, _)| db.storage_for(id)).collect_vec(),
+                        db.blobs()
+                            .map(|(id, _)| db.store.local_path(&db.storage_for(id)))
+                            .collect_vec(),
                         db.root.clone(),
                     )
                 };
@@ -437,30 +442,30 @@ async fn db_actor(
             AppAction::Db(db_action) => {
                 let mut db = data.write().unwrap();
 
+                let db = &mut *db;
+
                 match db_action {
                     DbAction::EditPiece(edit) => {
+                        let id = edit.id;
                         // TODO log falses here
                         db.edit(edit);
+                        db.index.refresh(&db.inner, Document::Piece(id));
                     }
                     DbAction::EditBlob(edit) => {
-                        let from = db.storage_for(edit.id);
-
-                        let id = edit.id;
+                        // the file stays put, so renames don't touch it
                         db.edit(edit);
-
-                        let to = db.storage_for(id);
-                        if from != to {
-                            std::fs::copy(&from, to).unwrap();
-                            std::fs::remove_file(from).unwrap();
-                        }
                     }
                     DbAction::EditTag(edit) => {
+                        let id = edit.id;
                         // TODO log falses here
                         db.edit(edit);
+                        db.index.refresh(&db.inner, Document::Tag(id));
                     }
                     DbAction::EditCategory(edit) => {
+                        let id = edit.id;
                         // TODO log falses here
                         db.edit(edit);
+                        db.index.refresh(&db.inner, Document::Category(id));
                     }
                     DbAction::NewPiece(sender) => {
                         let id = db.create_piece(Piece::default());
@@ -476,15 +481,19 @@ async fn db_actor(
                     }
                     DbAction::DeletePiece(id) => {
                         assert!(db.delete(id));
+                        db.index.remove(Document::Piece(id));
                     }
                     DbAction::DeleteBlob(id) => {
                         assert!(db.delete(id));
+                        db.index.remove(Document::Blob(id));
                     }
                     DbAction::DeleteTag(id) => {
                         assert!(db.delete(id));
+                        db.index.remove(Document::Tag(id));
                     }
                     DbAction::DeleteCategory(id) => {
                         assert!(db.delete(id));
+                        db.index.remove(Document::Category(id));
                     }
                     DbAction::AttachCategory(attach) => {
                         assert!(db.attach_category(attach));
@@ -514,13 +523,9 @@ async fn save_db_actor(
 ) -> anyhow::Result<()> {
     loop {
         dirty.changed().await?;
-        let (root, data) = {
-            let db = data.read().unwrap();
-            let root = data_file(db.root.clone());
-            let data = bincode::serialize::<Db>(&db).unwrap();
-
-            (root, data)
-        };
-        fs::write(root, &data).await.unwrap();
+        // refuses to save over anyone else's changes, rather than clobbering them
+        if let Err(err) = data.read().unwrap().save() {
+            eprintln!("Couldn't save the database: {}", err);
+        }
     }
 }
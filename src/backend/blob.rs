@@ -0,0 +1,55 @@
+use std::{
+    collections::hash_map::DefaultHasher,
+    hash::{Hash, Hasher},
+    path::PathBuf,
+    sync::Arc,
+};
+
+use chrono::Local;
+use db::{Blob, BlobType, MediaType};
+
+use super::media::{Ffmpeg, MediaTool, Posters};
+
+/// How much of a file is looked at to tell what it is.
+const HEADER: usize = 512;
+
+pub async fn from_path(path: PathBuf, blob_type: BlobType) -> anyhow::Result<Blob> {
+    from_path_with(path, blob_type, Arc::new(Ffmpeg)).await
+}
+
+/// Like [`from_path`], but reading video and audio with `tool`.
+pub async fn from_path_with(
+    path: PathBuf,
+    blob_type: BlobType,
+    tool: Arc<dyn MediaTool>,
+) -> anyhow::Result<Blob> {
+    let raw_data = tokio::fs::read(&path).await?;
+    let mut hash = DefaultHasher::new();
+    raw_data.hash(&mut hash);
+    let hash = hash.finish();
+
+    let mut blob = Blob {
+        file_name: path.file_name().unwrap().to_string_lossy().into_owned(),
+        hash,
+        blob_type,
+        added: Local::today().naive_local(),
+        content_type: MediaType::detect(&raw_data[..raw_data.len().min(HEADER)]),
+        duration: None,
+        location: None,
+    };
+
+    let media_type = blob.media_type();
+    if matches!(media_type, MediaType::Video | MediaType::Audio) {
+        blob.duration = tokio::task::spawn_blocking(move || {
+            // the poster is made now so it's ready by the time the thumbnail's shown,
+            // a failure here just means it's tried again then
+            if media_type == MediaType::Video {
+                let _ = Posters::default().get(&path, hash, tool.as_ref());
+            }
+            tool.duration(&path).ok()
+        })
+        .await?;
+    }
+
+    Ok(blob)
+}
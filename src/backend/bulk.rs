@@ -0,0 +1,74 @@
+use db::{
+    v3::commands::{AttachCategory, AttachTag, MergePiece},
+    CategoryId, PieceId, TagId,
+};
+use search::index::Document;
+
+use super::DbBackend;
+
+/// A change to many pieces or tags at once, made as a single step.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BulkEdit {
+    AddTag {
+        pieces: Vec<PieceId>,
+        tag: TagId,
+    },
+    RemoveTag {
+        pieces: Vec<PieceId>,
+        tag: TagId,
+    },
+    SetCategory {
+        tags: Vec<TagId>,
+        category: Option<CategoryId>,
+    },
+    DeletePieces(Vec<PieceId>),
+    DeleteTags(Vec<TagId>),
+    /// Merges every other piece into the first one.
+    MergePieces(Vec<PieceId>),
+}
+
+impl BulkEdit {
+    pub fn apply(self, db: &mut DbBackend) {
+        match self {
+            BulkEdit::AddTag { pieces, tag } => {
+                for src in pieces {
+                    db.attach_tag(AttachTag { src, dest: tag });
+                }
+            }
+            BulkEdit::RemoveTag { pieces, tag } => {
+                for src in pieces {
+                    db.remove_tag(AttachTag { src, dest: tag });
+                }
+            }
+            BulkEdit::SetCategory { tags, category } => {
+                for src in tags {
+                    db.attach_category(AttachCategory {
+                        src,
+                        dest: category,
+                    });
+                }
+            }
+            BulkEdit::DeletePieces(pieces) => {
+                for piece_id in pieces {
+                    db.delete(piece_id);
+                    db.index.remove(Document::Piece(piece_id));
+                }
+            }
+            BulkEdit::DeleteTags(tags) => {
+                for tag_id in tags {
+                    db.delete(tag_id);
+                    db.index.remove(Document::Tag(tag_id));
+                }
+            }
+            BulkEdit::MergePieces(pieces) => {
+                if let Some((&dest, rest)) = pieces.split_first() {
+                    for &src in rest {
+                        db.merge_piece(MergePiece { src, dest });
+                        db.index.remove(Document::Piece(src));
+                    }
+                    db.index.refresh(&db.inner, Document::Piece(dest));
+                }
+            }
+        }
+    }
+}
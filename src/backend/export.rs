@@ -0,0 +1,65 @@
+use std::{
+    fs::File,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use db::BlobId;
+use tokio::fs;
+use zip::{write::FileOptions, ZipWriter};
+
+use super::{store::BlobStore, DbBackend};
+
+/// Blobs copied out of a database, numbered so they keep their order
+/// when sorted by file name.
+#[derive(Debug, Clone)]
+pub struct Export {
+    store: Arc<dyn BlobStore>,
+    /// Each blob's key in the store, and the name it's exported as.
+    files: Vec<(PathBuf, String)>,
+}
+
+impl Export {
+    pub fn new(db: &DbBackend, blobs: impl IntoIterator<Item = BlobId>) -> Self {
+        Self {
+            store: db.store.clone(),
+            files: blobs
+                .into_iter()
+                .enumerate()
+                .map(|(position, blob_id)| {
+                    (
+                        db.storage_for(blob_id),
+                        format!("{:04} {}", position + 1, db[blob_id].file_name),
+                    )
+                })
+                .collect(),
+        }
+    }
+
+    /// Copies every file into `dest`, creating it if it doesn't exist.
+    pub async fn to_folder(self, dest: &Path) -> anyhow::Result<()> {
+        fs::create_dir_all(dest).await?;
+        for (key, file_name) in &self.files {
+            fs::copy(self.store.fetch(key).await?, dest.join(file_name)).await?;
+        }
+        Ok(())
+    }
+
+    /// Writes every file into a zip archive at `dest`.
+    pub async fn to_archive(self, dest: PathBuf) -> anyhow::Result<()> {
+        let mut files = Vec::new();
+        for (key, file_name) in self.files {
+            files.push((self.store.fetch(&key).await?, file_name));
+        }
+        tokio::task::spawn_blocking(move || -> anyhow::Result<()> {
+            let mut archive = ZipWriter::new(File::create(dest)?);
+            for (storage, file_name) in files {
+                archive.start_file(file_name, FileOptions::default())?;
+                std::io::copy(&mut File::open(storage)?, &mut archive)?;
+            }
+            archive.finish()?;
+            Ok(())
+        })
+        .await?
+    }
+}
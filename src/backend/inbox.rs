@@ -0,0 +1,234 @@
+use std::{
+    collections::BTreeSet,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime},
+};
+
+use db::{Blob, BlobType, MediaType, Piece, PieceId};
+use regex::Regex;
+use search::index::Document;
+use tokio::{fs, task::JoinHandle};
+
+use super::{blob, DbBackend};
+
+/// How often the inbox is checked for new files.
+const POLL: Duration = Duration::from_secs(5);
+/// Files changed more recently than this might still be being written, so they're left for later.
+const SETTLE: Duration = Duration::from_secs(2);
+/// Where files already in the database are moved to, inside the inbox.
+const DUPLICATES: &str = "duplicates";
+
+/// Watches a database's inbox folder, reading new files in the background
+/// until they're imported with [`InboxWatcher::import`].
+#[derive(Debug)]
+pub struct InboxWatcher {
+    dir: PathBuf,
+    found: Arc<Mutex<Vec<(PathBuf, Blob)>>>,
+    /// Files that couldn't be moved out of the inbox after they were imported.
+    failed: Arc<Mutex<Vec<anyhow::Error>>>,
+    task: JoinHandle<()>,
+}
+
+impl InboxWatcher {
+    pub fn start(dir: PathBuf) -> Self {
+        let found = Arc::new(Mutex::new(Vec::new()));
+        let task = tokio::spawn(watch(dir.clone(), found.clone()));
+        Self {
+            dir,
+            found,
+            failed: Default::default(),
+            task,
+        }
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// How many files have been found that haven't been imported yet.
+    pub fn waiting(&self) -> usize {
+        self.found.lock().unwrap().len()
+    }
+
+    /// Imports everything found since the last call, then moves the files out of the inbox.
+    /// Returns how many files were imported.
+    ///
+    /// The database is saved before any file is moved, so a file is never left in the store
+    /// without a blob pointing at it. If it can't be saved, nothing is imported and the files
+    /// are tried again next time. Files that couldn't be moved are reported on the next call.
+    ///
+    /// Nothing is imported while the database has unsaved changes, since saving the import
+    /// would save those too. The files wait, see [`InboxWatcher::waiting`].
+    pub fn import(&self, db: &mut DbBackend) -> anyhow::Result<usize> {
+        if let Some(err) = self.failed.lock().unwrap().pop() {
+            return Err(err);
+        }
+        if db.unsaved() {
+            return Ok(0);
+        }
+        let found = std::mem::take(&mut *self.found.lock().unwrap());
+        if found.is_empty() {
+            return Ok(0);
+        }
+        let pattern = Regex::new(&db.inbox.external_id_pattern)
+            .ok()
+            .filter(|_| !db.inbox.external_id_pattern.is_empty());
+
+        let before = (db.inner.clone(), db.index.clone());
+        let (mut duplicates, mut imported) = (Vec::new(), Vec::new());
+        for (path, blob) in &found {
+            if db.blobs().any(|(_, existing)| existing.hash == blob.hash) {
+                duplicates.push((path.clone(), blob.file_name.clone()));
+                continue;
+            }
+
+            let external_id = pattern
+                .as_ref()
+                .and_then(|pattern| external_id(pattern, &blob.file_name));
+            let piece_id = external_id
+                .as_ref()
+                .and_then(|external_id| find_piece(db, external_id))
+                .unwrap_or_else(|| {
+                    db.create_piece(Piece {
+                        external_id,
+                        media_type: blob.media_type(),
+                        ..Default::default()
+                    })
+                });
+
+            let is_text = blob.media_type() == MediaType::Text;
+            let (blob_id, key) = db.add_blob(piece_id, blob.clone());
+            if is_text {
+                if let Ok(text) = std::fs::read_to_string(path) {
+                    db.index.index_text(Document::Blob(blob_id), &text);
+                }
+            }
+            if db.tags_for_piece(piece_id).next().is_none() {
+                db.review.insert(piece_id);
+            }
+            imported.push((key, path.clone()));
+        }
+
+        if !imported.is_empty() {
+            if let Err(err) = db.save() {
+                let (inner, index) = before;
+                db.inner = inner;
+                db.index = index;
+                // the import might have been recorded before saving failed, so its undoing is too
+                db.edited = true;
+                self.found.lock().unwrap().extend(found);
+                return Err(err);
+            }
+        }
+
+        let (dir, store, failed) = (
+            self.dir.join(DUPLICATES),
+            db.store.clone(),
+            self.failed.clone(),
+        );
+        let count = imported.len();
+        tokio::spawn(async move {
+            for (path, file_name) in duplicates {
+                let moved = async {
+                    fs::create_dir_all(&dir).await?;
+                    move_file(&path, &free_path(&dir, &file_name).await).await?;
+                    anyhow::Result::<_>::Ok(())
+                };
+                if let Err(err) = moved.await {
+                    let err = err.context(format!("Couldn't move {}", path.display()));
+                    failed.lock().unwrap().push(err);
+                }
+            }
+            for (key, path) in imported {
+                if let Err(err) = store.put_moved(&key, &path).await {
+                    let err = err.context(format!("Couldn't move {}", path.display()));
+                    failed.lock().unwrap().push(err);
+                }
+            }
+        });
+        Ok(count)
+    }
+}
+
+impl Drop for InboxWatcher {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+async fn watch(dir: PathBuf, found: Arc<Mutex<Vec<(PathBuf, Blob)>>>) {
+    // files that have been read, but might not have been moved out yet
+    let mut seen = BTreeSet::new();
+    let mut interval = tokio::time::interval(POLL);
+    loop {
+        interval.tick().await;
+
+        let mut present = BTreeSet::new();
+        let mut entries = match fs::read_dir(&dir).await {
+            Ok(entries) => entries,
+            Err(_) => continue,
+        };
+        while let Ok(Some(entry)) = entries.next_entry().await {
+            let path = entry.path();
+            let settled = match entry.metadata().await {
+                Ok(metadata) if metadata.is_file() => metadata
+                    .modified()
+                    .ok()
+                    .and_then(|modified| SystemTime::now().duration_since(modified).ok())
+                    .map_or(true, |age| age >= SETTLE),
+                _ => continue,
+            };
+            present.insert(path.clone());
+            if !settled || seen.contains(&path) {
+                continue;
+            }
+
+            if let Ok(blob) = blob::from_path(path.clone(), BlobType::Canon).await {
+                seen.insert(path.clone());
+                found.lock().unwrap().push((path, blob));
+            }
+        }
+        seen.retain(|path| present.contains(path));
+    }
+}
+
+fn external_id(pattern: &Regex, file_name: &str) -> Option<String> {
+    let captures = pattern.captures(file_name)?;
+    let matched = captures.get(1).or_else(|| captures.get(0))?;
+    Some(matched.as_str().to_string())
+}
+
+fn find_piece(db: &DbBackend, external_id: &str) -> Option<PieceId> {
+    db.pieces()
+        .find(|(_, piece)| piece.external_id.as_deref() == Some(external_id))
+        .map(|(piece_id, _)| piece_id)
+}
+
+/// `file_name` in `dir`, numbered like `name (1).ext` if that's already taken.
+async fn free_path(dir: &Path, file_name: &str) -> PathBuf {
+    let name = Path::new(file_name);
+    let stem = name.file_stem().unwrap_or_default().to_string_lossy();
+    let extension = name
+        .extension()
+        .map(|extension| format!(".{}", extension.to_string_lossy()))
+        .unwrap_or_default();
+
+    let mut path = dir.join(file_name);
+    for number in 1.. {
+        if fs::metadata(&path).await.is_err() {
+            break;
+        }
+        path = dir.join(format!("{} ({}){}", stem, number, extension));
+    }
+    path
+}
+
+/// Renames `from` to `to`, falling back to copying when they're on different drives.
+async fn move_file(from: &Path, to: &Path) -> std::io::Result<()> {
+    if fs::rename(from, to).await.is_err() {
+        fs::copy(from, to).await?;
+        fs::remove_file(from).await?;
+    }
+    Ok(())
+}
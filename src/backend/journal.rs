@@ -0,0 +1,441 @@
+use std::{
+    convert::TryInto,
+    fs::{self, File, OpenOptions},
+    io::{ErrorKind, Write},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, bail};
+use chrono::{DateTime, Local, NaiveDateTime, TimeZone};
+use db::{v3::journal::Change, Db};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use super::{data_file, deserialize};
+
+/// How many old snapshots, and the journals leading on from them, are kept to recover from.
+const KEEP: usize = 16;
+/// How archived snapshots and journals are named, by when they were replaced.
+const ARCHIVE_NAME: &str = "%Y%m%dT%H%M%S%.3f";
+
+pub fn journal_file(root: &Path) -> PathBuf {
+    root.join("data.aodb.log")
+}
+
+pub fn history_dir(root: &Path) -> PathBuf {
+    root.join("history")
+}
+
+/// One edit's worth of changes, e.g. a piece's details being filled in or a folder being
+/// imported. Each journal starts with an empty entry, saying when the snapshot before it
+/// was written and which snapshot that was.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Entry {
+    /// When the changes were made, which can be a while before they were saved.
+    pub at: DateTime<Local>,
+    /// Who made the changes.
+    pub by: String,
+    pub changes: Vec<Change>,
+    /// The hash of the snapshot the journal follows on from, on its first entry.
+    pub snapshot: Option<[u8; 32]>,
+}
+
+/// The entries in a journal.
+#[derive(Debug, Default)]
+pub struct Journal {
+    pub entries: Vec<Entry>,
+    /// Set if the last entry was cut short, e.g. by a crash while saving.
+    pub torn: bool,
+}
+
+impl Journal {
+    /// Reads the journal at `path`, which has no entries if it doesn't exist.
+    /// An entry that was cut short is left out, along with anything after it.
+    pub fn read(path: &Path) -> anyhow::Result<Self> {
+        let data = match fs::read(path) {
+            Ok(data) => data,
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(Self::default()),
+            Err(err) => return Err(err.into()),
+        };
+
+        let mut journal = Self::default();
+        let mut rest = &data[..];
+        while !rest.is_empty() {
+            let entry = (rest.len() >= 8)
+                .then(|| {
+                    let (len, tail) = rest.split_at(8);
+                    let len = u64::from_le_bytes(len.try_into().unwrap()) as usize;
+                    let record = tail.get(..len)?;
+                    let entry = bincode::deserialize::<Entry>(record).ok()?;
+                    Some((entry, &tail[len..]))
+                })
+                .flatten();
+            match entry {
+                Some((entry, tail)) => {
+                    journal.entries.push(entry);
+                    rest = tail;
+                }
+                None => {
+                    journal.torn = true;
+                    break;
+                }
+            }
+        }
+        Ok(journal)
+    }
+
+    /// Starts a new journal at `path` for `snapshot`, replacing whatever was there.
+    pub fn start(path: &Path, snapshot: &[u8], by: &str) -> anyhow::Result<()> {
+        let start = Entry {
+            at: Local::now(),
+            by: by.to_string(),
+            changes: Vec::new(),
+            snapshot: Some(snapshot_hash(snapshot)),
+        };
+        replace(path, &records(&[start])?)
+    }
+
+    /// Adds `entries` to the end of the journal at `path`.
+    pub fn append(path: &Path, entries: &[Entry]) -> anyhow::Result<()> {
+        let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+        file.write_all(&records(entries)?)?;
+        file.sync_data()?;
+        Ok(())
+    }
+
+    /// Whether this journal was started for `snapshot`. One that wasn't is left over from a
+    /// crash while compacting, and what's in it is already in the snapshot. Databases saved
+    /// before there were journals have none, which doesn't follow on from anything either.
+    pub fn follows(&self, snapshot: &[u8]) -> bool {
+        matches!(
+            self.entries.first(),
+            Some(Entry { snapshot: Some(hash), .. }) if *hash == snapshot_hash(snapshot)
+        )
+    }
+}
+
+/// Entries as they're written to a journal, each with its length first,
+/// so one cut short can be told apart from a whole one.
+fn records(entries: &[Entry]) -> anyhow::Result<Vec<u8>> {
+    let mut records = Vec::new();
+    for entry in entries {
+        let data = bincode::serialize(entry)?;
+        records.extend((data.len() as u64).to_le_bytes());
+        records.extend(data);
+    }
+    Ok(records)
+}
+
+fn snapshot_hash(snapshot: &[u8]) -> [u8; 32] {
+    Sha256::digest(snapshot).into()
+}
+
+/// Replaces the file at `path` with `data` in one step, so it's never left half written.
+fn replace(path: &Path, data: &[u8]) -> anyhow::Result<()> {
+    let mut partial = path.as_os_str().to_owned();
+    partial.push(".partial");
+    let mut file = File::create(&partial)?;
+    file.write_all(data)?;
+    file.sync_all()?;
+    fs::rename(&partial, path)?;
+    Ok(())
+}
+
+/// Reads the database in `root`: its snapshot, with its journal replayed on top
+/// if it follows on from the snapshot.
+pub fn load(root: &Path) -> anyhow::Result<(Db, Vec<u8>, Journal)> {
+    let snapshot = fs::read(data_file(root.to_path_buf()))?;
+    let mut db = deserialize(&snapshot)?;
+    let journal = Journal::read(&journal_file(root))?;
+    if journal.follows(&snapshot) {
+        for entry in &journal.entries {
+            for change in &entry.changes {
+                db.apply(change.clone());
+            }
+        }
+    }
+    Ok((db, snapshot, journal))
+}
+
+/// Writes `snapshot` over the database file and starts its journal again, so loading doesn't
+/// have to replay as much. The old snapshot and journal are copied to the history folder.
+///
+/// Each file is replaced in one step. Crashing between the snapshot and the journal leaves
+/// the old journal with the new snapshot, which it doesn't follow on from, so it isn't replayed.
+pub fn compact(root: &Path, snapshot: &[u8], by: &str) -> anyhow::Result<()> {
+    let (data_file, journal) = (data_file(root.to_path_buf()), journal_file(root));
+    let history = history_dir(root);
+    let name = Local::now().format(ARCHIVE_NAME).to_string();
+
+    if data_file.exists() {
+        fs::create_dir_all(&history)?;
+        replace(
+            &history.join(format!("{}.aodb", name)),
+            &fs::read(&data_file)?,
+        )?;
+        if journal.exists() {
+            replace(&history.join(format!("{}.log", name)), &fs::read(&journal)?)?;
+        }
+    }
+    replace(&data_file, snapshot)?;
+    Journal::start(&journal, snapshot, by)?;
+
+    for (_, snapshot, journal) in archives(root)?.into_iter().rev().skip(KEEP) {
+        fs::remove_file(snapshot)?;
+        let _ = fs::remove_file(journal);
+    }
+    Ok(())
+}
+
+/// Every archived snapshot and journal, oldest first, along with when they were replaced.
+fn archives(root: &Path) -> anyhow::Result<Vec<(DateTime<Local>, PathBuf, PathBuf)>> {
+    let entries = match fs::read_dir(history_dir(root)) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(err.into()),
+    };
+
+    let mut archives = Vec::new();
+    for entry in entries {
+        let snapshot = entry?.path();
+        if snapshot.extension().and_then(|ext| ext.to_str()) != Some("aodb") {
+            continue;
+        }
+        let replaced = snapshot
+            .file_stem()
+            .and_then(|name| NaiveDateTime::parse_from_str(name.to_str()?, ARCHIVE_NAME).ok())
+            .and_then(|replaced| Local.from_local_datetime(&replaced).single());
+        if let Some(replaced) = replaced {
+            let journal = snapshot.with_extension("log");
+            archives.push((replaced, snapshot, journal));
+        }
+    }
+    archives.sort_by_key(|(replaced, _, _)| *replaced);
+    Ok(archives)
+}
+
+/// Every entry in the database's journals, the archived ones included, oldest first.
+pub fn history(root: &Path) -> anyhow::Result<Vec<Entry>> {
+    let mut entries = Vec::new();
+    for (_, _, journal) in archives(root)? {
+        entries.extend(Journal::read(&journal)?.entries);
+    }
+    entries.extend(Journal::read(&journal_file(root))?.entries);
+    Ok(entries)
+}
+
+/// The database as it was at `at`, from the last snapshot written before then
+/// and the entries of its journal saved by then.
+pub fn recover(root: &Path, at: DateTime<Local>) -> anyhow::Result<Db> {
+    // the first archive replaced after `at` is the one that covers it
+    let (snapshot, journal) = archives(root)?
+        .into_iter()
+        .find(|(replaced, _, _)| *replaced > at)
+        .map(|(_, snapshot, journal)| (snapshot, journal))
+        .unwrap_or_else(|| (data_file(root.to_path_buf()), journal_file(root)));
+
+    let journal = Journal::read(&journal)?;
+    match journal.entries.first() {
+        Some(first) if first.at > at => bail!(
+            "The history only goes back to {}.",
+            first.at.format("%Y-%m-%d %H:%M:%S")
+        ),
+        _ => {}
+    }
+    let snapshot = fs::read(snapshot)?;
+    let mut db = deserialize(&snapshot)?;
+    if journal.follows(&snapshot) {
+        for entry in journal
+            .entries
+            .into_iter()
+            .take_while(|entry| entry.at <= at)
+        {
+            for change in entry.changes {
+                db.apply(change);
+            }
+        }
+    }
+    Ok(db)
+}
+
+/// Reads a local time like "2021-06-01 13:45", with or without seconds.
+pub fn parse_time(text: &str) -> anyhow::Result<DateTime<Local>> {
+    ["%Y-%m-%d %H:%M:%S", "%Y-%m-%d %H:%M"]
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(text.trim(), format).ok())
+        .and_then(|time| Local.from_local_datetime(&time).single())
+        .ok_or_else(|| anyhow!("\"{}\" isn't a time like \"2021-06-01 13:45\".", text))
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        sync::atomic::{AtomicU64, Ordering},
+        thread::sleep,
+        time::Duration,
+    };
+
+    use db::Piece;
+
+    use super::*;
+
+    fn temp_dir() -> PathBuf {
+        static NEXT: AtomicU64 = AtomicU64::new(0);
+        let dir = std::env::temp_dir().join(format!(
+            "art-organize-journal-{}-{}",
+            std::process::id(),
+            NEXT.fetch_add(1, Ordering::Relaxed)
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    /// Adds a piece described as `description` to `db`, returning the entry that says so.
+    fn add_piece(db: &mut Db, description: &str) -> Entry {
+        let before = db.clone();
+        db.create_piece(Piece {
+            description: description.to_string(),
+            ..Piece::default()
+        });
+        // so entries and compactions are told apart by when they happened
+        sleep(Duration::from_millis(10));
+        let entry = Entry {
+            at: Local::now(),
+            by: "someone".to_string(),
+            changes: db.changes_since(&before),
+            snapshot: None,
+        };
+        sleep(Duration::from_millis(10));
+        entry
+    }
+
+    fn descriptions(db: &Db) -> Vec<&str> {
+        db.pieces()
+            .map(|(_, piece)| piece.description.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn test_load() {
+        let root = temp_dir();
+        let mut db = Db::default();
+        compact(&root, &db::to_bytes(&db).unwrap(), "someone").unwrap();
+        let entries = [add_piece(&mut db, "one"), add_piece(&mut db, "two")];
+        Journal::append(&journal_file(&root), &entries).unwrap();
+
+        let (loaded, _, journal) = load(&root).unwrap();
+        assert_eq!(descriptions(&loaded), ["one", "two"]);
+        assert_eq!(journal.entries.len(), 3);
+        assert!(!journal.torn);
+
+        compact(&root, &db::to_bytes(&loaded).unwrap(), "someone").unwrap();
+        let (loaded, _, journal) = load(&root).unwrap();
+        assert_eq!(descriptions(&loaded), ["one", "two"]);
+        assert_eq!(journal.entries.len(), 1);
+        assert_eq!(archives(&root).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_no_journal() {
+        // as saved before there were journals
+        let root = temp_dir();
+        let mut db = Db::default();
+        let snapshot = db::to_bytes(&db).unwrap();
+        fs::write(data_file(root.clone()), &snapshot).unwrap();
+
+        let (_, _, journal) = load(&root).unwrap();
+        assert!(journal.entries.is_empty());
+        assert!(!journal.torn);
+
+        let entry = add_piece(&mut db, "one");
+        Journal::start(&journal_file(&root), &snapshot, "someone").unwrap();
+        Journal::append(&journal_file(&root), &[entry]).unwrap();
+        let (loaded, snapshot, journal) = load(&root).unwrap();
+        assert!(journal.follows(&snapshot));
+        assert_eq!(descriptions(&loaded), ["one"]);
+    }
+
+    #[test]
+    fn test_truncated() {
+        let root = temp_dir();
+        let mut db = Db::default();
+        compact(&root, &db::to_bytes(&db).unwrap(), "someone").unwrap();
+        let entries = [add_piece(&mut db, "one"), add_piece(&mut db, "two")];
+        let path = journal_file(&root);
+        Journal::append(&path, &entries).unwrap();
+        let whole = fs::read(&path).unwrap();
+
+        // cut off in the middle of the last entry, then in the middle of its length
+        let last = records(&entries[1..]).unwrap().len();
+        for cut in [3, last - 4] {
+            fs::write(&path, &whole[..whole.len() - cut]).unwrap();
+            let (loaded, snapshot, journal) = load(&root).unwrap();
+            assert!(journal.torn);
+            assert!(journal.follows(&snapshot));
+            assert_eq!(journal.entries.len(), 2);
+            assert_eq!(descriptions(&loaded), ["one"]);
+        }
+    }
+
+    #[test]
+    fn test_compact_interrupted() {
+        let root = temp_dir();
+        let mut db = Db::default();
+        compact(&root, &db::to_bytes(&db).unwrap(), "someone").unwrap();
+        let entry = add_piece(&mut db, "one");
+        Journal::append(&journal_file(&root), &[entry]).unwrap();
+
+        // as if it crashed once the snapshot was replaced, but before the journal was
+        let mut compacted = db.clone();
+        compacted.pieces.get_mut(0.into()).unwrap().description = "edited".to_string();
+        fs::write(data_file(root.clone()), db::to_bytes(&compacted).unwrap()).unwrap();
+
+        let (loaded, snapshot, journal) = load(&root).unwrap();
+        assert!(!journal.follows(&snapshot));
+        assert_eq!(descriptions(&loaded), ["edited"]);
+    }
+
+    #[test]
+    fn test_history() {
+        let root = temp_dir();
+        let mut db = Db::default();
+        compact(&root, &db::to_bytes(&db).unwrap(), "someone").unwrap();
+        let first = add_piece(&mut db, "one");
+        Journal::append(&journal_file(&root), &[first]).unwrap();
+        compact(&root, &db::to_bytes(&db).unwrap(), "someone").unwrap();
+        let second = add_piece(&mut db, "two");
+        Journal::append(&journal_file(&root), &[second]).unwrap();
+
+        let changes = history(&root)
+            .unwrap()
+            .iter()
+            .map(|entry| entry.changes.len())
+            .collect::<Vec<_>>();
+        assert_eq!(changes, [0, 1, 0, 1]);
+    }
+
+    #[test]
+    fn test_recover() {
+        let root = temp_dir();
+        let mut db = Db::default();
+        let start = Local::now();
+        sleep(Duration::from_millis(10));
+        compact(&root, &db::to_bytes(&db).unwrap(), "someone").unwrap();
+        let (first, second) = (add_piece(&mut db, "one"), add_piece(&mut db, "two"));
+        let (one, two) = (first.at, second.at);
+        Journal::append(&journal_file(&root), &[first, second]).unwrap();
+        compact(&root, &db::to_bytes(&db).unwrap(), "someone").unwrap();
+        let third = add_piece(&mut db, "three");
+        let three = third.at;
+        Journal::append(&journal_file(&root), &[third]).unwrap();
+
+        assert!(recover(&root, start).is_err());
+        assert_eq!(descriptions(&recover(&root, one).unwrap()), ["one"]);
+        assert_eq!(descriptions(&recover(&root, two).unwrap()), ["one", "two"]);
+        assert_eq!(
+            descriptions(&recover(&root, three).unwrap()),
+            ["one", "two", "three"]
+        );
+    }
+}
@@ -0,0 +1,293 @@
+use std::{
+    path::{Path, PathBuf},
+    process::Command,
+    time::Duration,
+};
+
+use anyhow::{anyhow, bail, Context};
+
+use crate::config::PROJECT;
+
+/// How far into a video its poster frame is taken from, at most.
+const POSTER_AT: Duration = Duration::from_secs(5);
+
+/// Reads what's needed from video and audio files, usually by running an external program.
+pub trait MediaTool: Send + Sync {
+    /// How long the file plays for.
+    fn duration(&self, path: &Path) -> anyhow::Result<Duration>;
+    /// Saves the frame shown `at` into the video at `path` as a PNG at `dest`.
+    fn poster(&self, path: &Path, at: Duration, dest: &Path) -> anyhow::Result<()>;
+}
+
+/// `ffprobe` and `ffmpeg`, found on the PATH.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Ffmpeg;
+
+impl Ffmpeg {
+    fn run(program: &str, args: &[&std::ffi::OsStr]) -> anyhow::Result<String> {
+        let output = Command::new(program)
+            .args(args)
+            .output()
+            .with_context(|| format!("Couldn't run {}, is it installed?", program))?;
+        if !output.status.success() {
+            bail!(
+                "{} failed: {}",
+                program,
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    }
+}
+
+impl MediaTool for Ffmpeg {
+    fn duration(&self, path: &Path) -> anyhow::Result<Duration> {
+        let output = Self::run(
+            "ffprobe",
+            &[
+                "-v".as_ref(),
+                "error".as_ref(),
+                "-show_entries".as_ref(),
+                "format=duration".as_ref(),
+                "-of".as_ref(),
+                "default=noprint_wrappers=1:nokey=1".as_ref(),
+                path.as_os_str(),
+            ],
+        )?;
+        let seconds = output
+            .trim()
+            .parse::<f64>()
+            .map_err(|_| anyhow!("ffprobe gave no duration for {}.", path.display()))?;
+        Ok(Duration::from_secs_f64(seconds.max(0.0)))
+    }
+
+    fn poster(&self, path: &Path, at: Duration, dest: &Path) -> anyhow::Result<()> {
+        let at = format!("{:.3}", at.as_secs_f64());
+        Self::run(
+            "ffmpeg",
+            &[
+                "-v".as_ref(),
+                "error".as_ref(),
+                "-y".as_ref(),
+                "-ss".as_ref(),
+                at.as_ref(),
+                "-i".as_ref(),
+                path.as_os_str(),
+                "-frames:v".as_ref(),
+                "1".as_ref(),
+                "-f".as_ref(),
+                "image2".as_ref(),
+                dest.as_os_str(),
+            ],
+        )?;
+        Ok(())
+    }
+}
+
+/// Frames taken from videos to stand in for them as images, kept in the cache directory.
+///
+/// Like thumbnails they're keyed by content hash, so they're only extracted once per video.
+#[derive(Debug, Clone)]
+pub struct Posters {
+    dir: PathBuf,
+}
+
+impl Default for Posters {
+    fn default() -> Self {
+        Self {
+            dir: PROJECT.cache_dir().join("posters"),
+        }
+    }
+}
+
+impl Posters {
+    pub fn path_for(&self, hash: u64) -> PathBuf {
+        self.dir.join(format!("{:016x}.png", hash))
+    }
+
+    /// The poster frame for the video at `source`, extracting it first if it hasn't been.
+    pub fn get(&self, source: &Path, hash: u64, tool: &dyn MediaTool) -> anyhow::Result<PathBuf> {
+        let poster = self.path_for(hash);
+        if poster.exists() {
+            return Ok(poster);
+        }
+
+        // a tenth of the way in skips fades from black, without going too far into long videos
+        let at = tool
+            .duration(source)
+            .map_or(Duration::ZERO, |duration| (duration / 10).min(POSTER_AT));
+        std::fs::create_dir_all(&self.dir)?;
+        let partial = self.dir.join(format!("{:016x}.partial.png", hash));
+        tool.poster(source, at, &partial)?;
+        std::fs::rename(partial, &poster)?;
+        Ok(poster)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::backend::blob;
+    use db::{BlobType, MediaType};
+    use std::sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    };
+
+    /// Stands in for ffmpeg, remembering where posters were taken from.
+    #[derive(Default)]
+    struct Fake {
+        duration: Option<Duration>,
+        broken: bool,
+        posters: Mutex<Vec<Duration>>,
+    }
+
+    impl MediaTool for Fake {
+        fn duration(&self, _: &Path) -> anyhow::Result<Duration> {
+            self.duration.context("No duration.")
+        }
+
+        fn poster(&self, _: &Path, at: Duration, dest: &Path) -> anyhow::Result<()> {
+            self.posters.lock().unwrap().push(at);
+            if self.broken {
+                bail!("The video is broken.");
+            }
+            std::fs::write(dest, b"poster")?;
+            Ok(())
+        }
+    }
+
+    /// A fresh directory in the temp directory.
+    fn temp_dir() -> PathBuf {
+        static NEXT: AtomicU64 = AtomicU64::new(0);
+        let dir = std::env::temp_dir().join(format!(
+            "art-organize-media-{}-{}",
+            std::process::id(),
+            NEXT.fetch_add(1, Ordering::Relaxed)
+        ));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn posters_at(tool: &Fake) -> Vec<Duration> {
+        tool.posters.lock().unwrap().clone()
+    }
+
+    #[test]
+    fn test_poster_time() {
+        let dir = temp_dir();
+        let posters = Posters { dir: dir.clone() };
+        let source = dir.join("video.mp4");
+
+        for (duration, at) in [
+            (Some(Duration::from_secs(20)), Duration::from_secs(2)),
+            (Some(Duration::from_secs(600)), POSTER_AT),
+            (None, Duration::ZERO),
+        ] {
+            let tool = Fake {
+                duration,
+                ..Default::default()
+            };
+            let hash = at.as_millis() as u64;
+            let poster = posters.get(&source, hash, &tool).unwrap();
+            assert_eq!(poster, posters.path_for(hash));
+            assert_eq!(posters_at(&tool), vec![at]);
+
+            // only extracted once
+            posters.get(&source, hash, &tool).unwrap();
+            assert_eq!(posters_at(&tool).len(), 1);
+        }
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_poster_failure() {
+        let dir = temp_dir();
+        let posters = Posters { dir: dir.clone() };
+        let source = dir.join("video.mp4");
+
+        let broken = Fake {
+            broken: true,
+            ..Default::default()
+        };
+        assert!(posters.get(&source, 1, &broken).is_err());
+        assert!(!posters.path_for(1).exists());
+
+        // tried again next time
+        let fixed = Fake::default();
+        posters.get(&source, 1, &fixed).unwrap();
+        assert_eq!(posters_at(&fixed).len(), 1);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_from_path_with() {
+        let dir = temp_dir();
+        let read = |file_name: &str, contents: &[u8], tool: Fake| {
+            let path = dir.join(file_name);
+            std::fs::write(&path, contents).unwrap();
+            let tool = Arc::new(tool);
+            let dyn_tool: Arc<dyn MediaTool> = tool.clone();
+            async move {
+                let blob = blob::from_path_with(path, BlobType::Canon, dyn_tool)
+                    .await
+                    .unwrap();
+                (blob, posters_at(&tool))
+            }
+        };
+        let duration = Some(Duration::from_secs(42));
+
+        // a video whose poster can't be made is still imported, with its duration
+        let (video, posters) = read(
+            "video.mp4",
+            b"\0\0\0\x18ftypisom video",
+            Fake {
+                duration,
+                broken: true,
+                ..Default::default()
+            },
+        )
+        .await;
+        assert_eq!(video.content_type, Some(MediaType::Video));
+        assert_eq!(video.duration, duration);
+        assert_eq!(posters.len(), 1);
+
+        let (audio, posters) = read(
+            "song.mp3",
+            b"ID3 song",
+            Fake {
+                duration,
+                ..Default::default()
+            },
+        )
+        .await;
+        assert_eq!(audio.content_type, Some(MediaType::Audio));
+        assert_eq!(audio.duration, duration);
+        assert!(posters.is_empty());
+
+        // a duration that can't be read isn't an error
+        let (video, _) = read(
+            "broken.mkv",
+            b"\x1a\x45\xdf\xa3 video",
+            Fake {
+                broken: true,
+                ..Default::default()
+            },
+        )
+        .await;
+        assert_eq!(video.duration, None);
+
+        let (text, _) = read(
+            "notes.txt",
+            b"notes",
+            Fake {
+                duration,
+                ..Default::default()
+            },
+        )
+        .await;
+        assert_eq!(text.content_type, Some(MediaType::Text));
+        assert_eq!(text.duration, None);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,354 @@
+use std::{
+    collections::hash_map::DefaultHasher,
+    fmt::{self, Display},
+    fs::OpenOptions,
+    hash::{Hash, Hasher},
+    io::{self, ErrorKind, Write},
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::SystemTime,
+};
+
+use chrono::{DateTime, Local};
+use db::{v3::journal::Change, Db};
+use serde::{Deserialize, Serialize};
+
+use super::{
+    data_file,
+    journal::{journal_file, Entry},
+};
+
+/// Who has a database open for editing, as written to its lock file.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Owner {
+    pub user: String,
+    pub host: String,
+    pub pid: u32,
+    pub since: DateTime<Local>,
+}
+
+impl Owner {
+    /// Whoever is running this program, from now on.
+    pub fn current() -> Self {
+        let var = |names: &[&str], or: &str| {
+            names
+                .iter()
+                .find_map(|name| std::env::var(name).ok())
+                .unwrap_or_else(|| or.to_string())
+        };
+        Self {
+            user: var(&["USERNAME", "USER"], "someone"),
+            host: var(&["COMPUTERNAME", "HOSTNAME"], "an unknown computer"),
+            pid: std::process::id(),
+            since: Local::now(),
+        }
+    }
+
+    /// Who they are, as the journal puts it.
+    pub fn name(&self) -> String {
+        format!("{} on {}", self.user, self.host)
+    }
+}
+
+impl Display for Owner {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} on {} (since {})",
+            self.user,
+            self.host,
+            self.since.format("%Y-%m-%d %H:%M")
+        )
+    }
+}
+
+pub fn lock_file(root: &Path) -> PathBuf {
+    root.join("data.aodb.lock")
+}
+
+/// An advisory lock on a database, so only one person edits it at a time.
+/// Nothing stops other programs from writing to it, but this one opens it read only instead.
+#[derive(Debug)]
+struct Lock {
+    path: PathBuf,
+    owner: Owner,
+}
+
+impl Lock {
+    /// Takes the lock for the database in `root`, or says who already has it.
+    fn acquire(root: &Path) -> anyhow::Result<Result<Self, Owner>> {
+        let path = lock_file(root);
+        let owner = Owner::current();
+        match OpenOptions::new().write(true).create_new(true).open(&path) {
+            Ok(mut file) => {
+                file.write_all(toml::to_string(&owner)?.as_bytes())?;
+                Ok(Ok(Self { path, owner }))
+            }
+            Err(err) if err.kind() == ErrorKind::AlreadyExists => Ok(Err(Self::holder(&path)
+                .unwrap_or_else(|| Owner {
+                    user: "someone".to_string(),
+                    host: "an unknown computer".to_string(),
+                    pid: 0,
+                    since: Local::now(),
+                }))),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    /// Takes the lock from whoever has it, for when they've gone without giving it back.
+    fn take_over(root: &Path) -> anyhow::Result<Self> {
+        let path = lock_file(root);
+        let owner = Owner::current();
+        std::fs::write(&path, toml::to_string(&owner)?)?;
+        Ok(Self { path, owner })
+    }
+
+    fn holder(path: &Path) -> Option<Owner> {
+        toml::from_str(&std::fs::read_to_string(path).ok()?).ok()
+    }
+}
+
+impl Drop for Lock {
+    fn drop(&mut self) {
+        // someone might have taken it over since
+        if Self::holder(&self.path).as_ref() == Some(&self.owner) {
+            let _ = std::fs::remove_file(&self.path);
+        }
+    }
+}
+
+/// What a file was like when the database was last loaded or saved here,
+/// to tell whether someone else has saved over it since.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Stamp {
+    /// None if there was no file.
+    modified: Option<SystemTime>,
+    hash: u64,
+}
+
+impl Stamp {
+    /// The stamp of the file at `path`, which holds `data`.
+    pub fn new(path: &Path, data: &[u8]) -> io::Result<Self> {
+        let mut hash = DefaultHasher::new();
+        data.hash(&mut hash);
+        Ok(Self {
+            modified: Some(path.metadata()?.modified()?),
+            hash: hash.finish(),
+        })
+    }
+
+    /// The stamp of the file at `path` as it is now, which might not exist yet.
+    pub fn of(path: &Path) -> io::Result<Self> {
+        match std::fs::read(path) {
+            Ok(data) => Self::new(path, &data),
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(Self {
+                modified: None,
+                hash: 0,
+            }),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Whether the file at `path` still holds what it did. Only files that were touched
+    /// are read. A file that's gone only matches if it was already gone, since someone
+    /// else deleting or moving it away is as much a change as them saving over it.
+    pub fn matches(&self, path: &Path) -> io::Result<bool> {
+        match path.metadata() {
+            Ok(metadata) if Some(metadata.modified()?) == self.modified => Ok(true),
+            Ok(_) => Ok(Self::new(path, &std::fs::read(path)?)?.hash == self.hash),
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(self.modified.is_none()),
+            Err(err) => Err(err),
+        }
+    }
+}
+
+/// The stamps of a database's snapshot and journal.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Saved {
+    pub snapshot: Stamp,
+    pub journal: Stamp,
+}
+
+impl Saved {
+    pub fn matches(&self, root: &Path) -> io::Result<bool> {
+        Ok(self.snapshot.matches(&data_file(root.to_path_buf()))?
+            && self.journal.matches(&journal_file(root))?)
+    }
+}
+
+/// Saving was refused because someone else saved the database since it was loaded here.
+#[derive(Debug)]
+pub struct ExternalChange;
+
+impl Display for ExternalChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Someone else saved the database since it was opened here."
+        )
+    }
+}
+
+impl std::error::Error for ExternalChange {}
+
+/// How this copy of a database stands with its file, and with everyone else using it.
+///
+/// Shared by every clone of a [`DbBackend`](super::DbBackend), so undoing never rolls it back.
+#[derive(Debug)]
+pub struct Session {
+    /// Held while the database is open for editing here.
+    lock: Option<Lock>,
+    /// Who had the lock when the database was opened, if it wasn't us.
+    holder: Option<Owner>,
+    /// None until the database has been saved at least once.
+    pub saved: Option<Saved>,
+    /// The database as of `saved`, which merges are made against.
+    pub base: Arc<Db>,
+    /// The database as of the last entry in `pending`, or `base` if there isn't one.
+    recorded: Arc<Db>,
+    /// What's been changed here since the database was saved, to be added to its journal.
+    pub pending: Vec<Entry>,
+    /// What's been recorded since [`Session::take_unindexed`] was last called, for keeping
+    /// the search index up to date.
+    unindexed: Vec<Change>,
+}
+
+impl Session {
+    /// Opens the database in `root`, read only if someone else has it locked.
+    pub fn open(root: &Path, saved: Option<Saved>, base: Db) -> anyhow::Result<Self> {
+        let (lock, holder) = match Lock::acquire(root)? {
+            Ok(lock) => (Some(lock), None),
+            Err(holder) => (None, Some(holder)),
+        };
+        let base = Arc::new(base);
+        Ok(Self {
+            lock,
+            holder,
+            saved,
+            recorded: base.clone(),
+            base,
+            pending: Vec::new(),
+            unindexed: Vec::new(),
+        })
+    }
+
+    /// Notes down what's changed in `db` since it was last recorded, as one entry.
+    pub fn record(&mut self, db: &Db) {
+        let changes = db.changes_since(&self.recorded);
+        if changes.is_empty() {
+            return;
+        }
+        self.unindexed.extend(changes.iter().cloned());
+        self.pending.push(Entry {
+            at: Local::now(),
+            by: Owner::current().name(),
+            changes,
+            snapshot: None,
+        });
+        self.recorded = Arc::new(db.clone());
+    }
+
+    /// Marks everything recorded as saved.
+    pub fn mark_saved(&mut self, saved: Saved) {
+        self.saved = Some(saved);
+        self.base = self.recorded.clone();
+        self.pending.clear();
+    }
+
+    /// Starts again from `base`, which was saved as `saved`, forgetting anything not saved yet.
+    pub fn rebase(&mut self, saved: Saved, base: Db) {
+        self.recorded = Arc::new(base);
+        self.unindexed.clear();
+        self.mark_saved(saved);
+    }
+
+    /// The changes recorded since this was last called.
+    pub fn take_unindexed(&mut self) -> Vec<Change> {
+        std::mem::take(&mut self.unindexed)
+    }
+
+    /// Who's editing the database, if it isn't open for editing here.
+    pub fn read_only(&self) -> Option<&Owner> {
+        self.holder.as_ref()
+    }
+
+    pub fn take_over(&mut self, root: &Path) -> anyhow::Result<()> {
+        self.lock = Some(Lock::take_over(root)?);
+        self.holder = None;
+        Ok(())
+    }
+
+    /// Gives the lock back, leaving the database read only here.
+    pub fn release(&mut self) {
+        if let Some(lock) = self.lock.take() {
+            self.holder = Some(lock.owner.clone());
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_stamp_matches() {
+        let path = std::env::temp_dir().join(format!("art-organize-stamp-{}", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        let missing = Stamp::of(&path).unwrap();
+        assert!(missing.matches(&path).unwrap());
+
+        std::fs::write(&path, b"saved").unwrap();
+        assert!(!missing.matches(&path).unwrap());
+        let saved = Stamp::of(&path).unwrap();
+        assert!(saved.matches(&path).unwrap());
+
+        std::fs::remove_file(&path).unwrap();
+        assert!(!saved.matches(&path).unwrap());
+    }
+
+    #[test]
+    fn test_record() {
+        let root =
+            std::env::temp_dir().join(format!("art-organize-session-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        std::fs::create_dir_all(&root).unwrap();
+        let mut db = Db::default();
+        let mut session = Session::open(&root, None, db.clone()).unwrap();
+
+        session.record(&db);
+        assert!(session.pending.is_empty());
+        let piece = db.create_piece(Default::default());
+        session.record(&db);
+        db.pieces.get_mut(piece).unwrap().description = "edited".to_string();
+        session.record(&db);
+        session.record(&db);
+        let changes = session
+            .pending
+            .iter()
+            .map(|entry| entry.changes.len())
+            .collect::<Vec<_>>();
+        assert_eq!(changes, [1, 1]);
+        assert_eq!(session.take_unindexed().len(), 2);
+        assert!(session.take_unindexed().is_empty());
+
+        let stamp = Stamp::of(&root.join("missing")).unwrap();
+        session.mark_saved(Saved {
+            snapshot: stamp,
+            journal: stamp,
+        });
+        assert!(session.pending.is_empty());
+        assert!(db.changes_since(&session.base).is_empty());
+
+        session.rebase(
+            Saved {
+                snapshot: stamp,
+                journal: stamp,
+            },
+            Db::default(),
+        );
+        session.record(&db);
+        assert_eq!(session.pending.len(), 1);
+        assert_eq!(session.take_unindexed().len(), 1);
+    }
+}
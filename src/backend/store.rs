@@ -0,0 +1,813 @@
+use std::{
+    fmt::{self, Debug},
+    io::ErrorKind,
+    path::{Component, Path, PathBuf},
+    sync::Arc,
+};
+
+use anyhow::{anyhow, bail, Context};
+use chrono::{DateTime, Utc};
+use futures_util::{future::BoxFuture, FutureExt};
+use hmac::{Hmac, Mac};
+use itertools::Itertools;
+use reqwest::{Body, Client, Method, Response, StatusCode, Url};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tokio::{
+    fs,
+    io::{AsyncReadExt, AsyncWriteExt},
+};
+
+use crate::config::PROJECT;
+
+pub type StoreFuture<'a, T> = BoxFuture<'a, anyhow::Result<T>>;
+
+/// Where blobs' files are kept. Files are named by keys, the paths the database's storage
+/// layout gives them, relative to the store.
+///
+/// Everything that reads a file works on a local copy, so stores that aren't on disk keep
+/// what they've fetched in the cache directory.
+pub trait BlobStore: Send + Sync + Debug {
+    /// Whether files are kept on disk, so nothing ever has to be downloaded.
+    fn is_local(&self) -> bool;
+    /// Where the local copy of `key` is, or will be once it's fetched.
+    fn local_path(&self, key: &Path) -> PathBuf;
+    /// Makes sure there's a local copy of `key`, returning where it is.
+    fn fetch<'a>(&'a self, key: &'a Path) -> StoreFuture<'a, PathBuf>;
+    /// Stores a copy of the file at `from` as `key`, replacing anything already there.
+    fn put<'a>(&'a self, key: &'a Path, from: &'a Path) -> StoreFuture<'a, ()>;
+    /// Stores the file at `from` as `key`, removing it from where it was.
+    fn put_moved<'a>(&'a self, key: &'a Path, from: &'a Path) -> StoreFuture<'a, ()> {
+        async move {
+            self.put(key, from).await?;
+            fs::remove_file(from).await?;
+            Ok(())
+        }
+        .boxed()
+    }
+    fn exists<'a>(&'a self, key: &'a Path) -> StoreFuture<'a, bool>;
+    /// Moves the file at `from` to `to`, which mustn't be taken.
+    fn rename<'a>(&'a self, from: &'a Path, to: &'a Path) -> StoreFuture<'a, ()>;
+    /// Removes `key`, if it's there.
+    fn delete<'a>(&'a self, key: &'a Path) -> StoreFuture<'a, ()>;
+}
+
+/// Where a database keeps its files, read from `store.toml` in its folder.
+/// Without one, they're kept in the folder itself.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "kind", rename_all = "kebab-case")]
+pub enum StoreConfig {
+    Local,
+    S3 {
+        /// The server's address, such as `https://s3.amazonaws.com` or `http://localhost:9000`.
+        endpoint: String,
+        bucket: String,
+        #[serde(default = "default_region")]
+        region: String,
+        /// Read from `AWS_ACCESS_KEY_ID` if not given.
+        #[serde(default)]
+        access_key: Option<String>,
+        /// Read from `AWS_SECRET_ACCESS_KEY` if not given.
+        #[serde(default)]
+        secret_key: Option<String>,
+    },
+}
+
+impl Default for StoreConfig {
+    fn default() -> Self {
+        Self::Local
+    }
+}
+
+fn default_region() -> String {
+    "us-east-1".to_string()
+}
+
+pub fn store_file(root: &Path) -> PathBuf {
+    root.join("store.toml")
+}
+
+impl StoreConfig {
+    pub fn load(root: &Path) -> anyhow::Result<Self> {
+        let path = store_file(root);
+        match std::fs::read_to_string(&path) {
+            Ok(text) => {
+                toml::from_str(&text).with_context(|| format!("Couldn't read {}", path.display()))
+            }
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(Self::default()),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    /// Opens the store for the database in `root`.
+    pub fn open(self, root: &Path) -> anyhow::Result<Arc<dyn BlobStore>> {
+        Ok(match self {
+            StoreConfig::Local => Arc::new(LocalStore::new(root.to_path_buf())),
+            StoreConfig::S3 {
+                endpoint,
+                bucket,
+                region,
+                access_key,
+                secret_key,
+            } => {
+                let credential = |given: Option<String>, var: &str| {
+                    given
+                        .or_else(|| std::env::var(var).ok())
+                        .ok_or_else(|| anyhow!("No {} is set for the {} bucket.", var, bucket))
+                };
+                Arc::new(S3Store {
+                    endpoint: Url::parse(&endpoint)
+                        .with_context(|| format!("{} isn't a valid endpoint", endpoint))?,
+                    signer: Signer {
+                        access_key: credential(access_key, "AWS_ACCESS_KEY_ID")?,
+                        secret_key: credential(secret_key, "AWS_SECRET_ACCESS_KEY")?,
+                        region,
+                        service: "s3",
+                    },
+                    cache: PROJECT.cache_dir().join("blobs").join(&bucket),
+                    bucket,
+                    client: Client::new(),
+                })
+            }
+        })
+    }
+}
+
+async fn create_parent(path: &Path) -> std::io::Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).await?;
+    }
+    Ok(())
+}
+
+/// Files kept in a folder on disk, normally the database's own.
+#[derive(Debug, Clone)]
+pub struct LocalStore {
+    root: PathBuf,
+}
+
+impl LocalStore {
+    pub fn new(root: PathBuf) -> Self {
+        Self { root }
+    }
+}
+
+impl BlobStore for LocalStore {
+    fn is_local(&self) -> bool {
+        true
+    }
+
+    fn local_path(&self, key: &Path) -> PathBuf {
+        self.root.join(key)
+    }
+
+    fn fetch<'a>(&'a self, key: &'a Path) -> StoreFuture<'a, PathBuf> {
+        async move {
+            let path = self.local_path(key);
+            if fs::metadata(&path).await.is_err() {
+                bail!("{} is missing.", path.display());
+            }
+            Ok(path)
+        }
+        .boxed()
+    }
+
+    fn put<'a>(&'a self, key: &'a Path, from: &'a Path) -> StoreFuture<'a, ()> {
+        async move {
+            let to = self.local_path(key);
+            // files edited in place are already where they belong
+            if to != from {
+                create_parent(&to).await?;
+                fs::copy(from, &to).await?;
+            }
+            Ok(())
+        }
+        .boxed()
+    }
+
+    fn put_moved<'a>(&'a self, key: &'a Path, from: &'a Path) -> StoreFuture<'a, ()> {
+        async move {
+            let to = self.local_path(key);
+            create_parent(&to).await?;
+            // renaming fails across drives, so it falls back to copying
+            if fs::rename(from, &to).await.is_err() {
+                fs::copy(from, &to).await?;
+                fs::remove_file(from).await?;
+            }
+            Ok(())
+        }
+        .boxed()
+    }
+
+    fn exists<'a>(&'a self, key: &'a Path) -> StoreFuture<'a, bool> {
+        async move { Ok(fs::metadata(self.local_path(key)).await.is_ok()) }.boxed()
+    }
+
+    fn rename<'a>(&'a self, from: &'a Path, to: &'a Path) -> StoreFuture<'a, ()> {
+        async move {
+            let (from, to) = (self.local_path(from), self.local_path(to));
+            if fs::metadata(&to).await.is_ok() {
+                bail!("{} is already taken.", to.display());
+            }
+            create_parent(&to).await?;
+            fs::rename(&from, &to)
+                .await
+                .with_context(|| format!("Couldn't move {}", from.display()))
+        }
+        .boxed()
+    }
+
+    fn delete<'a>(&'a self, key: &'a Path) -> StoreFuture<'a, ()> {
+        async move {
+            match fs::remove_file(self.local_path(key)).await {
+                Err(err) if err.kind() != ErrorKind::NotFound => Err(err.into()),
+                _ => Ok(()),
+            }
+        }
+        .boxed()
+    }
+}
+
+/// Files kept as objects in a bucket on an S3-compatible server, such as MinIO.
+/// Objects are addressed by path (`endpoint/bucket/key`), which every compatible server supports,
+/// and requests are signed with AWS Signature Version 4.
+#[derive(Clone)]
+pub struct S3Store {
+    endpoint: Url,
+    bucket: String,
+    signer: Signer,
+    cache: PathBuf,
+    client: Client,
+}
+
+impl Debug for S3Store {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // leaves out the credentials
+        f.debug_struct("S3Store")
+            .field("endpoint", &self.endpoint.as_str())
+            .field("bucket", &self.bucket)
+            .field("region", &self.signer.region)
+            .field("cache", &self.cache)
+            .finish()
+    }
+}
+
+/// A request's body, along with the hash of it that its signature covers.
+struct Payload {
+    body: Body,
+    sha256: String,
+    length: u64,
+}
+
+impl Payload {
+    fn empty() -> Self {
+        Self {
+            body: Body::from(Vec::new()),
+            sha256: hex::encode(Sha256::digest(b"")),
+            length: 0,
+        }
+    }
+
+    /// Streams the file at `path` rather than reading it into memory. It's read through
+    /// once beforehand to hash it, since the signature has to come before the body.
+    async fn file(path: &Path) -> anyhow::Result<Self> {
+        let mut file = fs::File::open(path).await?;
+        let mut hasher = Sha256::new();
+        let mut buffer = vec![0; 64 * 1024];
+        let mut length = 0;
+        loop {
+            let read = file.read(&mut buffer).await?;
+            if read == 0 {
+                break;
+            }
+            hasher.update(&buffer[..read]);
+            length += read as u64;
+        }
+        Ok(Self {
+            body: Body::from(fs::File::open(path).await?),
+            sha256: hex::encode(hasher.finalize()),
+            length,
+        })
+    }
+}
+
+/// Signs requests with AWS Signature Version 4.
+#[derive(Clone)]
+struct Signer {
+    access_key: String,
+    secret_key: String,
+    region: String,
+    service: &'static str,
+}
+
+impl Signer {
+    /// The authorization header of a request made `at`, signing `headers`, whose names have
+    /// to be lowercase. `path` is percent encoded already, and there's never a query string.
+    fn authorization(
+        &self,
+        method: &Method,
+        path: &str,
+        headers: &[(&str, String)],
+        payload: &str,
+        at: DateTime<Utc>,
+    ) -> String {
+        let date = at.format("%Y%m%d").to_string();
+        let mut headers = headers.to_vec();
+        headers.sort();
+        let signed_headers = headers.iter().map(|(name, _)| name).join(";");
+        let canonical_request = format!(
+            "{}\n{}\n\n{}\n{}\n{}",
+            method,
+            path,
+            headers
+                .iter()
+                .map(|(name, value)| format!("{}:{}\n", name, value.trim()))
+                .join(""),
+            signed_headers,
+            payload
+        );
+        let scope = format!("{}/{}/{}/aws4_request", date, self.region, self.service);
+        let string_to_sign = format!(
+            "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+            at.format("%Y%m%dT%H%M%SZ"),
+            scope,
+            hex::encode(Sha256::digest(canonical_request.as_bytes()))
+        );
+        let signing_key = [date.as_str(), &self.region, self.service, "aws4_request"]
+            .iter()
+            .fold(
+                format!("AWS4{}", self.secret_key).into_bytes(),
+                |key, part| hmac(&key, part.as_bytes()),
+            );
+        format!(
+            "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+            self.access_key,
+            scope,
+            signed_headers,
+            hex::encode(hmac(&signing_key, string_to_sign.as_bytes()))
+        )
+    }
+}
+
+impl S3Store {
+    /// The path of the object for `key`, percent encoded.
+    fn object(&self, key: &Path) -> String {
+        let segments = key.components().filter_map(|component| match component {
+            Component::Normal(segment) => Some(encode(&segment.to_string_lossy())),
+            _ => None,
+        });
+        format!("/{}/{}", encode(&self.bucket), segments.format("/"))
+    }
+
+    async fn send(
+        &self,
+        method: Method,
+        object: &str,
+        headers: &[(&'static str, String)],
+        payload: Payload,
+    ) -> anyhow::Result<Response> {
+        let now = Utc::now();
+        let host = self
+            .endpoint
+            .host_str()
+            .ok_or_else(|| anyhow!("{} has no host.", self.endpoint))?;
+        let host = match self.endpoint.port() {
+            Some(port) => format!("{}:{}", host, port),
+            None => host.to_string(),
+        };
+
+        let mut signed = vec![
+            ("host", host),
+            ("x-amz-content-sha256", payload.sha256.clone()),
+            ("x-amz-date", now.format("%Y%m%dT%H%M%SZ").to_string()),
+        ];
+        signed.extend(headers.iter().cloned());
+        let authorization =
+            self.signer
+                .authorization(&method, object, &signed, &payload.sha256, now);
+
+        let mut url = self.endpoint.clone();
+        url.set_path(object);
+        // the host header is filled in from the url
+        let request = signed
+            .into_iter()
+            .filter(|(name, _)| *name != "host")
+            .fold(
+                self.client.request(method, url),
+                |request, (name, value)| request.header(name, value),
+            )
+            .header("authorization", authorization)
+            // streamed bodies would be sent in chunks otherwise, which S3 doesn't take
+            .header("content-length", payload.length)
+            .body(payload.body);
+        Ok(request.send().await?)
+    }
+
+    /// Turns anything but a success into an error, with what the server said went wrong.
+    async fn check(response: Response, key: &Path) -> anyhow::Result<Response> {
+        let status = response.status();
+        if status.is_success() {
+            return Ok(response);
+        }
+        let body = response.text().await.unwrap_or_default();
+        bail!(
+            "The store answered {} for {}: {}",
+            status,
+            key.display(),
+            body.trim()
+        )
+    }
+
+    /// Drops the local copy of `key`, so it's fetched again next time.
+    async fn forget(&self, key: &Path) -> std::io::Result<()> {
+        match fs::remove_file(self.local_path(key)).await {
+            Err(err) if err.kind() != ErrorKind::NotFound => Err(err),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl BlobStore for S3Store {
+    fn is_local(&self) -> bool {
+        false
+    }
+
+    fn local_path(&self, key: &Path) -> PathBuf {
+        self.cache.join(key)
+    }
+
+    fn fetch<'a>(&'a self, key: &'a Path) -> StoreFuture<'a, PathBuf> {
+        async move {
+            let path = self.local_path(key);
+            if fs::metadata(&path).await.is_ok() {
+                return Ok(path);
+            }
+
+            let response = self
+                .send(Method::GET, &self.object(key), &[], Payload::empty())
+                .await?;
+            let mut response = Self::check(response, key).await?;
+            create_parent(&path).await?;
+            // written next to it first, so nothing reads half a file
+            let partial = path.with_extension("partial");
+            let mut file = fs::File::create(&partial).await?;
+            while let Some(chunk) = response.chunk().await? {
+                file.write_all(&chunk).await?;
+            }
+            file.flush().await?;
+            drop(file);
+            fs::rename(partial, &path).await?;
+            Ok(path)
+        }
+        .boxed()
+    }
+
+    fn put<'a>(&'a self, key: &'a Path, from: &'a Path) -> StoreFuture<'a, ()> {
+        async move {
+            let cached = self.local_path(key);
+            let payload = Payload::file(from).await?;
+            // a failed upload mustn't leave the old copy looking current
+            if cached != from {
+                self.forget(key).await?;
+            }
+            let response = self
+                .send(Method::PUT, &self.object(key), &[], payload)
+                .await?;
+            Self::check(response, key).await?;
+
+            // keeps a copy, since whoever stored it will probably want to look at it
+            if cached != from {
+                create_parent(&cached).await?;
+                let partial = cached.with_extension("partial");
+                fs::copy(from, &partial).await?;
+                fs::rename(partial, &cached).await?;
+            }
+            Ok(())
+        }
+        .boxed()
+    }
+
+    fn exists<'a>(&'a self, key: &'a Path) -> StoreFuture<'a, bool> {
+        async move {
+            let response = self
+                .send(Method::HEAD, &self.object(key), &[], Payload::empty())
+                .await?;
+            if response.status() == StatusCode::NOT_FOUND {
+                return Ok(false);
+            }
+            Self::check(response, key).await?;
+            Ok(true)
+        }
+        .boxed()
+    }
+
+    fn rename<'a>(&'a self, from: &'a Path, to: &'a Path) -> StoreFuture<'a, ()> {
+        async move {
+            if self.exists(to).await? {
+                bail!("{} is already taken.", to.display());
+            }
+            // whatever was cached for `to` belonged to an object that's gone
+            self.forget(to).await?;
+            // objects can't be renamed, so they're copied on the server and the original deleted
+            let copy_source = [("x-amz-copy-source", self.object(from))];
+            let response = self
+                .send(
+                    Method::PUT,
+                    &self.object(to),
+                    &copy_source,
+                    Payload::empty(),
+                )
+                .await?;
+            Self::check(response, from).await?;
+
+            let cached = self.local_path(from);
+            if fs::metadata(&cached).await.is_ok() {
+                let moved = self.local_path(to);
+                create_parent(&moved).await?;
+                let _ = fs::rename(cached, moved).await;
+            }
+            self.delete(from).await
+        }
+        .boxed()
+    }
+
+    fn delete<'a>(&'a self, key: &'a Path) -> StoreFuture<'a, ()> {
+        async move {
+            self.forget(key).await?;
+            let response = self
+                .send(Method::DELETE, &self.object(key), &[], Payload::empty())
+                .await?;
+            Self::check(response, key).await?;
+            Ok(())
+        }
+        .boxed()
+    }
+}
+
+fn hmac(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC takes keys of any length");
+    mac.update(data);
+    mac.finalize().into_bytes().to_vec()
+}
+
+/// Percent encodes everything but the characters S3 leaves alone in object names.
+fn encode(segment: &str) -> String {
+    segment
+        .bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                (byte as char).to_string()
+            }
+            _ => format!("%{:02X}", byte),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use chrono::TimeZone;
+    use std::{
+        collections::BTreeMap,
+        io::{BufRead, BufReader, Read, Write},
+        net::TcpListener,
+        sync::{
+            atomic::{AtomicU64, Ordering},
+            Mutex,
+        },
+    };
+
+    fn example_signer(secret_key: &str, service: &'static str) -> Signer {
+        Signer {
+            access_key: "AKIDEXAMPLE".to_string(),
+            secret_key: secret_key.to_string(),
+            region: "us-east-1".to_string(),
+            service,
+        }
+    }
+
+    fn signature(authorization: &str) -> &str {
+        authorization.rsplit("Signature=").next().unwrap()
+    }
+
+    /// `get-vanilla` from the AWS Signature Version 4 test suite.
+    #[test]
+    fn test_sign_vanilla() {
+        let signer = example_signer("wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY", "service");
+        let authorization = signer.authorization(
+            &Method::GET,
+            "/",
+            &[
+                ("host", "example.amazonaws.com".to_string()),
+                ("x-amz-date", "20150830T123600Z".to_string()),
+            ],
+            &hex::encode(Sha256::digest(b"")),
+            Utc.ymd(2015, 8, 30).and_hms(12, 36, 0),
+        );
+        assert_eq!(
+            authorization,
+            "AWS4-HMAC-SHA256 Credential=AKIDEXAMPLE/20150830/us-east-1/service/aws4_request, \
+            SignedHeaders=host;x-amz-date, \
+            Signature=5fa00fa31553b73ebf1942676e86291e8372ff2a2260956d9b8aae1d763fbf31"
+        );
+    }
+
+    /// The GET and PUT object examples from the S3 documentation on signing.
+    #[test]
+    fn test_sign_s3() {
+        let signer = example_signer("wJalrXUtnFEMI/K7MDENG/bPxRfiCYEXAMPLEKEY", "s3");
+        let at = Utc.ymd(2013, 5, 24).and_hms(0, 0, 0);
+        let common = |payload: &str| {
+            vec![
+                ("host", "examplebucket.s3.amazonaws.com".to_string()),
+                ("x-amz-content-sha256", payload.to_string()),
+                ("x-amz-date", "20130524T000000Z".to_string()),
+            ]
+        };
+
+        let empty = hex::encode(Sha256::digest(b""));
+        let mut headers = common(&empty);
+        headers.push(("range", "bytes=0-9".to_string()));
+        let authorization = signer.authorization(&Method::GET, "/test.txt", &headers, &empty, at);
+        assert!(authorization.contains("SignedHeaders=host;range;x-amz-content-sha256;x-amz-date,"));
+        assert_eq!(
+            signature(&authorization),
+            "f0e8bdb87c964420e857bd35b5d6ed310bd44f0170aba48dd91039c6036bdb41"
+        );
+
+        let payload = hex::encode(Sha256::digest(b"Welcome to Amazon S3."));
+        assert_eq!(
+            payload,
+            "44ce7dd67c959e0d3524ffac1771dfbba87d2b6b4b4e99e42034a8b803f8b072"
+        );
+        let mut headers = common(&payload);
+        headers.push(("date", "Fri, 24 May 2013 00:00:00 GMT".to_string()));
+        headers.push(("x-amz-storage-class", "REDUCED_REDUNDANCY".to_string()));
+        let authorization = signer.authorization(
+            &Method::PUT,
+            &format!("/{}", encode("test$file.text")),
+            &headers,
+            &payload,
+            at,
+        );
+        assert_eq!(
+            signature(&authorization),
+            "98ad721746da40c64f1a55b78f14c238d841ea1380cd77a1b5971af0ece108bd"
+        );
+    }
+
+    type Objects = Arc<Mutex<BTreeMap<String, Vec<u8>>>>;
+
+    /// Serves a bucket from memory, the way S3 would, closing the connection after every request.
+    /// Bodies without a length are refused, like S3 does.
+    fn stand_in() -> (Url, Objects) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let endpoint = Url::parse(&format!("http://{}", listener.local_addr().unwrap())).unwrap();
+        let objects = Objects::default();
+        let served = objects.clone();
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut request_line = String::new();
+                reader.read_line(&mut request_line).unwrap();
+                let mut parts = request_line.split_whitespace();
+                let (method, path) = (parts.next().unwrap(), parts.next().unwrap().to_string());
+                let mut headers = BTreeMap::new();
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    match line.trim_end().split_once(':') {
+                        Some((name, value)) => {
+                            headers.insert(name.to_lowercase(), value.trim().to_string())
+                        }
+                        None => break,
+                    };
+                }
+                let mut body = vec![
+                    0;
+                    headers
+                        .get("content-length")
+                        .map_or(0, |length| { length.parse().unwrap() })
+                ];
+                reader.read_exact(&mut body).unwrap();
+
+                let signed = headers.get("authorization").map_or(false, |authorization| {
+                    authorization.starts_with("AWS4-HMAC-SHA256 Credential=test/")
+                }) && headers.get("x-amz-content-sha256")
+                    == Some(&hex::encode(Sha256::digest(&body)));
+                let mut objects = served.lock().unwrap();
+                let (status, reply) = if headers.contains_key("transfer-encoding") {
+                    ("411 Length Required", Vec::new())
+                } else if !signed {
+                    ("403 Forbidden", Vec::new())
+                } else {
+                    match (method, headers.get("x-amz-copy-source")) {
+                        ("PUT", Some(source)) => match objects.get(source).cloned() {
+                            Some(copied) => {
+                                objects.insert(path, copied);
+                                ("200 OK", Vec::new())
+                            }
+                            None => ("404 Not Found", Vec::new()),
+                        },
+                        ("PUT", None) => {
+                            objects.insert(path, body);
+                            ("200 OK", Vec::new())
+                        }
+                        ("GET" | "HEAD", _) => match objects.get(&path) {
+                            Some(object) if method == "GET" => ("200 OK", object.clone()),
+                            Some(_) => ("200 OK", Vec::new()),
+                            None => ("404 Not Found", Vec::new()),
+                        },
+                        ("DELETE", _) => {
+                            objects.remove(&path);
+                            ("204 No Content", Vec::new())
+                        }
+                        _ => ("405 Method Not Allowed", Vec::new()),
+                    }
+                };
+                drop(objects);
+
+                write!(
+                    stream,
+                    "HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                    status,
+                    reply.len()
+                )
+                .unwrap();
+                stream.write_all(&reply).unwrap();
+            }
+        });
+        (endpoint, objects)
+    }
+
+    /// A fresh directory in the temp directory.
+    fn temp_dir() -> PathBuf {
+        static NEXT: AtomicU64 = AtomicU64::new(0);
+        let dir = std::env::temp_dir().join(format!(
+            "art-organize-store-{}-{}",
+            std::process::id(),
+            NEXT.fetch_add(1, Ordering::Relaxed)
+        ));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[tokio::test]
+    async fn test_stand_in() {
+        let (endpoint, objects) = stand_in();
+        let dir = temp_dir();
+        let store = S3Store {
+            endpoint,
+            bucket: "bucket".to_string(),
+            signer: Signer {
+                access_key: "test".to_string(),
+                secret_key: "secret".to_string(),
+                region: "us-east-1".to_string(),
+                service: "s3",
+            },
+            cache: dir.join("cache"),
+            client: Client::new(),
+        };
+        let object = |path: &str| objects.lock().unwrap().get(path).cloned();
+        let read = |path: PathBuf| std::fs::read_to_string(path).unwrap();
+        let (key, moved) = (Path::new("blobs/ab/a b.png"), Path::new("pieces/1/a.png"));
+        let upload = dir.join("upload");
+
+        std::fs::write(&upload, "first").unwrap();
+        store.put(key, &upload).await.unwrap();
+        assert_eq!(
+            object("/bucket/blobs/ab/a%20b.png"),
+            Some(b"first".to_vec())
+        );
+        assert!(store.exists(key).await.unwrap());
+        assert!(!store.exists(moved).await.unwrap());
+
+        // downloaded again once the cached copy is gone
+        std::fs::remove_file(store.local_path(key)).unwrap();
+        assert_eq!(read(store.fetch(key).await.unwrap()), "first");
+
+        // storing over it replaces the cached copy too
+        std::fs::write(&upload, "second").unwrap();
+        store.put(key, &upload).await.unwrap();
+        assert_eq!(read(store.fetch(key).await.unwrap()), "second");
+
+        // a copy cached for the new name from before is dropped
+        std::fs::create_dir_all(store.local_path(moved).parent().unwrap()).unwrap();
+        std::fs::write(store.local_path(moved), "stale").unwrap();
+        store.rename(key, moved).await.unwrap();
+        assert_eq!(object("/bucket/blobs/ab/a%20b.png"), None);
+        assert_eq!(object("/bucket/pieces/1/a.png"), Some(b"second".to_vec()));
+        assert_eq!(read(store.fetch(moved).await.unwrap()), "second");
+        assert!(store.fetch(key).await.is_err());
+
+        store.put(key, &upload).await.unwrap();
+        assert!(store.rename(key, moved).await.is_err());
+
+        store.delete(moved).await.unwrap();
+        assert_eq!(object("/bucket/pieces/1/a.png"), None);
+        assert!(!store.local_path(moved).exists());
+        assert!(store.fetch(moved).await.is_err());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
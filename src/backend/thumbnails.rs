@@ -0,0 +1,84 @@
+use std::path::{Path, PathBuf};
+
+use image::{DynamicImage, ImageFormat};
+
+use crate::config::PROJECT;
+
+/// The longest side of a thumbnail, in pixels.
+pub const THUMBNAIL_SIZE: u32 = 256;
+/// How many images are decoded at once.
+pub const WORKERS: usize = 4;
+
+/// Thumbnails saved to disk, so they don't have to be decoded from the original every time.
+///
+/// Thumbnails are keyed by the blob's content hash and their size, so blobs with the same
+/// contents share one, and a cached thumbnail older than its blob's file is made again.
+#[derive(Debug, Clone)]
+pub struct ThumbnailCache {
+    dir: PathBuf,
+}
+
+impl Default for ThumbnailCache {
+    fn default() -> Self {
+        Self {
+            dir: PROJECT.cache_dir().join("thumbnails"),
+        }
+    }
+}
+
+impl ThumbnailCache {
+    fn path_for(&self, hash: u64) -> PathBuf {
+        self.dir
+            .join(format!("{:016x}-{}.png", hash, THUMBNAIL_SIZE))
+    }
+
+    /// Whether there's an up to date thumbnail for the file at `source`.
+    pub fn contains(&self, source: &Path, hash: u64) -> bool {
+        let modified = |path: &Path| path.metadata().and_then(|metadata| metadata.modified());
+        match (modified(&self.path_for(hash)), modified(source)) {
+            (Ok(cached), Ok(source)) => cached >= source,
+            _ => false,
+        }
+    }
+
+    /// Whether there's any thumbnail for `hash`, however old, for files that aren't on disk
+    /// to compare it with.
+    pub fn has(&self, hash: u64) -> bool {
+        self.path_for(hash).exists()
+    }
+
+    /// The thumbnail saved for `hash`, however old.
+    pub fn cached(&self, hash: u64) -> Option<DynamicImage> {
+        image::open(self.path_for(hash)).ok()
+    }
+
+    /// Loads the thumbnail for the file at `source`, making it from the image `decode` reads
+    /// and saving it first if it isn't cached.
+    /// Failing to save it isn't an error, it'll just be made again next time.
+    pub fn load(
+        &self,
+        source: &Path,
+        hash: u64,
+        decode: impl FnOnce(&Path) -> anyhow::Result<DynamicImage>,
+    ) -> anyhow::Result<DynamicImage> {
+        let cached = self.path_for(hash);
+        if self.contains(source, hash) {
+            if let Ok(image) = image::open(&cached) {
+                return Ok(image);
+            }
+        }
+
+        let image = decode(source)?.thumbnail(THUMBNAIL_SIZE, THUMBNAIL_SIZE);
+        let _ = self.save(&image, &cached);
+        Ok(image)
+    }
+
+    fn save(&self, image: &DynamicImage, cached: &Path) -> anyhow::Result<()> {
+        std::fs::create_dir_all(&self.dir)?;
+        // written next to it first, so other loads never see half a file
+        let partial = cached.with_extension("partial");
+        image.save_with_format(&partial, ImageFormat::Png)?;
+        std::fs::rename(partial, cached)?;
+        Ok(())
+    }
+}
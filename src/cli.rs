@@ -1,4 +1,5 @@
 use clap::Clap;
+use db::StorageLayout;
 use serde::{Deserialize, Serialize};
 use std::path::PathBuf;
 
@@ -26,6 +27,46 @@ pub enum SubCommand {
     /// Resets the configuration to the default values
     ResetConfig,
     Update,
+    /// Copies the files of every piece matching a saved search into a folder,
+    /// numbered in the order the search sorts them
+    ExportSaved {
+        /// Name of the saved search
+        name: String,
+        /// Folder to copy the files into, created if it doesn't exist
+        dest: PathBuf,
+        /// Fails on tags that don't exist, instead of ignoring them
+        #[clap(long)]
+        strict: bool,
+    },
+    /// Copies the files of a collection into a folder, numbered in the collection's order
+    ExportCollection {
+        /// Name of the collection
+        name: String,
+        /// Folder to copy the files into, or the archive to create with --archive
+        dest: PathBuf,
+        /// Writes a zip archive instead of a folder
+        #[clap(long)]
+        archive: bool,
+    },
+    /// Makes thumbnails for every image and video in the database that doesn't have an up to
+    /// date one, so the gallery doesn't have to
+    Thumbnails,
+    /// Moves every blob's file to where a storage layout puts it, and stores new blobs that way
+    MigrateStorage {
+        /// One of "flat", "sharded" (by content hash) or "by-piece"
+        layout: StorageLayout,
+    },
+    /// Copies blobs' files from the database's folder into the store set up in its store.toml,
+    /// skipping any already there
+    UploadBlobs,
+    /// Lists every save in the database's journal, with who made it and how much changed
+    History,
+    /// Puts the database back how it was at a time in its history. Recovering is saved like
+    /// any other change, so it can be undone the same way
+    Recover {
+        /// A local time like "2021-06-01 13:45"
+        at: String,
+    },
 }
 
 #[derive(Clap, Debug, Serialize, Deserialize)]
@@ -46,10 +46,8 @@ pub async fn main(mut db: DbBackend) {
         .request_device(
             &wgpu::DeviceDescriptor {
                 features: wgpu::Features::default(),
-                limits: wgpu::Limits {
-                    max_texture_dimension_2d: 24000,
-                    ..wgpu::Limits::default()
-                },
+                // full images are shrunk to fit the window, so the default limits are plenty
+                limits: wgpu::Limits::default(),
                 label: None,
             },
             None,
@@ -82,6 +80,9 @@ pub async fn main(mut db: DbBackend) {
 
     // Display the demo application that ships with egui.
     let mut frontend = Frontend::new(TextureLoadingTask::run());
+    frontend
+        .image_data_mut()
+        .set_display_size(size.width, size.height);
 
     let start_time = Instant::now();
     event_loop.run(move |event, _, control_flow| {
@@ -176,6 +177,8 @@ pub async fn main(mut db: DbBackend) {
             MainEventsCleared | UserEvent(()) => {
                 window.request_redraw();
             }
+            // the process exits without dropping anything, so the lock has to be given back here
+            LoopDestroyed => db.unlock(),
             WindowEvent { event, .. } => match event {
                 winit::event::WindowEvent::Resized(size) => {
                     // Resize with 0 width and height is used by winit to signal a minimize event on Windows.
@@ -185,8 +188,14 @@ pub async fn main(mut db: DbBackend) {
                         surface_config.width = size.width;
                         surface_config.height = size.height;
                         surface.configure(&device, &surface_config);
+                        frontend
+                            .image_data_mut()
+                            .set_display_size(size.width, size.height);
                     }
                 }
+                winit::event::WindowEvent::DroppedFile(path) => {
+                    frontend.file_dropped(path);
+                }
                 winit::event::WindowEvent::CloseRequested => {
                     *control_flow = ControlFlow::Exit;
                 }
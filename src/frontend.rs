@@ -1,48 +1,118 @@
 use std::{
+    path::PathBuf,
     sync::{Arc, Mutex},
     time::Instant,
 };
 
 use crate::{
-    backend::DbBackend,
+    backend::{bulk::BulkEdit, inbox::InboxWatcher, session::ExternalChange, DbBackend},
     config::Config,
-    frontend::texture_storage::{ImageData, ImageStatus},
+    frontend::{
+        import::{Dropped, Imports},
+        selection::Selection,
+        texture_storage::{ImageData, ImageRequest, ImageStatus, Priority},
+    },
     ui_memory::MemoryExt,
-    views::{gallery::Gallery, View, ViewResponse},
+    undo::UndoStack,
+    views::{blob_browser::BlobBrowser, gallery::Gallery, review::Review, View, ViewResponse},
 };
 use db::BlobId;
-use egui::{CentralPanel, Layout, TopBottomPanel};
+use egui::{Button, CentralPanel, Color32, Key, Layout, TopBottomPanel, Window};
 
 pub mod blob;
 pub mod category;
+pub mod collection;
 pub mod easy_mark_editor;
+pub mod import;
 pub mod piece;
+pub mod relation_editor;
+pub mod selection;
 pub mod tag;
 pub mod tag_editor;
 pub mod texture_storage;
+pub mod viewer;
 
 pub struct Frontend {
     history: Vec<Box<dyn View>>,
     image_data: ImageData,
     new_db: Arc<Mutex<Option<DbBackend>>>,
     last_save: Option<Instant>,
+    pub selection: Selection,
+    undo: UndoStack,
+    /// Files dropped onto the window since the last frame.
+    dropped: Vec<PathBuf>,
+    importing: Option<Dropped>,
+    imports: Imports,
+    inbox: Option<InboxWatcher>,
+    /// Set when saving found someone else had saved over the database.
+    conflict: bool,
+    /// Why the last merge, reload or save over failed, shown in the conflict window.
+    reload_error: Arc<Mutex<Option<String>>>,
+    /// Why taking over a database someone else has locked failed, shown under the read only banner.
+    take_over_error: Option<String>,
 }
 
 impl Frontend {
     pub fn new(image_data: ImageData) -> Self {
         Self {
-            history: vec![Box::new(Gallery)],
+            history: vec![Box::new(Gallery::default())],
             image_data,
             new_db: Arc::new(Mutex::new(None)),
             last_save: None,
+            selection: Selection::default(),
+            undo: UndoStack::default(),
+            dropped: Vec::new(),
+            importing: None,
+            imports: Imports::default(),
+            inbox: None,
+            conflict: false,
+            reload_error: Arc::new(Mutex::new(None)),
+            take_over_error: None,
         }
     }
 
+    /// Keeps the watcher in line with the database's inbox settings, importing whatever it's found.
+    fn watch_inbox(&mut self, db: &mut DbBackend) {
+        let watching = self.inbox.as_ref().map(InboxWatcher::dir);
+        if watching != db.inbox.dir.as_deref() {
+            self.inbox = db.inbox.dir.clone().map(InboxWatcher::start);
+        }
+        if let Some(inbox) = &self.inbox {
+            match inbox.import(db) {
+                Ok(_) => {}
+                Err(err) if err.is::<ExternalChange>() => self.conflict = true,
+                Err(err) => eprintln!("Couldn't import from the inbox: {:#}", err),
+            }
+        }
+    }
+
+    pub fn file_dropped(&mut self, path: PathBuf) {
+        self.dropped.push(path);
+    }
+
+    /// Makes `edit` as a single step that can be undone.
+    pub fn bulk_edit(&mut self, db: &mut DbBackend, edit: BulkEdit) {
+        self.undo.record(db, |db| edit.apply(db));
+    }
+
     pub fn image_for(&mut self, blob_id: BlobId, db: &DbBackend) -> ImageStatus {
         self.image_data.image_for(blob_id, db)
     }
-    pub fn thumbnail_for(&mut self, blob_id: BlobId, db: &DbBackend) -> ImageStatus {
-        self.image_data.thumbnail_for(blob_id, db)
+    pub fn thumbnail_for(
+        &mut self,
+        blob_id: BlobId,
+        db: &DbBackend,
+        priority: Priority,
+    ) -> ImageStatus {
+        self.image_data.thumbnail_for(blob_id, db, priority)
+    }
+    /// Tries loading a blob's image again after it failed.
+    pub fn retry_image(&mut self, blob_id: BlobId) {
+        self.image_data.retry(&ImageRequest::Image(blob_id));
+    }
+    /// Tries loading a blob's thumbnail again after it failed.
+    pub fn retry_thumbnail(&mut self, blob_id: BlobId) {
+        self.image_data.retry(&ImageRequest::Thumbnail(blob_id));
     }
 
     pub fn image_data_mut(&mut self) -> &mut ImageData {
@@ -54,14 +124,62 @@ impl Frontend {
     pub fn update(&mut self, db: &mut DbBackend, ctx: &egui::CtxRef, quit: &mut bool) {
         if let Ok(Some(new_db)) = self.new_db.try_lock().map(|mut inner| inner.take()) {
             *db = new_db;
+            self.undo = UndoStack::default();
+        }
+
+        // read only databases leave their files alone, so nothing is imported
+        let read_only = db.read_only();
+        self.imports.finish(db);
+        if read_only.is_some() {
+            self.inbox = None;
+        } else if !self.conflict {
+            // imports are saved straight away, which has to wait until the conflict is settled
+            self.watch_inbox(db);
+        }
+        if !self.dropped.is_empty() {
+            let files = std::mem::take(&mut self.dropped);
+            // files dropped onto views that can't take them are ignored
+            if let (Some(target), None) = (
+                self.history.last().and_then(|view| view.drop_target()),
+                &read_only,
+            ) {
+                self.importing = Some(Dropped::new(files, target));
+            }
+        }
+        if let Some(importing) = &mut self.importing {
+            if !importing.window(ctx, &self.imports, db) {
+                self.importing = None;
+            }
+        }
+
+        let (undo_pressed, redo_pressed) = {
+            let input = ctx.input();
+            let z = input.modifiers.command && input.key_pressed(Key::Z);
+            (
+                z && !input.modifiers.shift,
+                (z && input.modifiers.shift)
+                    || (input.modifiers.command && input.key_pressed(Key::Y)),
+            )
+        };
+        if undo_pressed {
+            self.undo.undo(db);
+        }
+        if redo_pressed {
+            self.undo.redo(db);
         }
 
         TopBottomPanel::top("menu").show(ctx, |ui| {
             egui::menu::bar(ui, |ui| {
                 ui.menu_button("File", |ui| {
-                    if ui.button("Save").clicked() {
-                        db.save().unwrap();
-                        self.last_save = Some(Instant::now());
+                    if ui
+                        .add_enabled(read_only.is_none(), Button::new("Save"))
+                        .clicked()
+                    {
+                        match db.save() {
+                            Ok(()) => self.last_save = Some(Instant::now()),
+                            Err(err) if err.is::<ExternalChange>() => self.conflict = true,
+                            Err(err) => panic!("{}", err),
+                        }
                         ui.close_menu();
                     }
                     if ui.button("Load").clicked() {
@@ -90,6 +208,32 @@ impl Frontend {
                         ui.close_menu()
                     }
                 });
+                ui.menu_button("Edit", |ui| {
+                    if ui
+                        .add_enabled(self.undo.can_undo(db), Button::new("Undo"))
+                        .clicked()
+                    {
+                        self.undo.undo(db);
+                        ui.close_menu();
+                    }
+                    if ui
+                        .add_enabled(self.undo.can_redo(db), Button::new("Redo"))
+                        .clicked()
+                    {
+                        self.undo.redo(db);
+                        ui.close_menu();
+                    }
+                });
+                ui.menu_button("View", |ui| {
+                    if ui.button("Blobs").clicked() {
+                        self.history.push(Box::new(BlobBrowser::default()));
+                        ui.close_menu();
+                    }
+                    if ui.button(format!("Review ({})", db.review.len())).clicked() {
+                        self.history.push(Box::new(Review::default()));
+                        ui.close_menu();
+                    }
+                });
                 ui.separator();
                 let mut pop_to = None;
                 for (idx, view) in self.history.iter().enumerate() {
@@ -108,6 +252,13 @@ impl Frontend {
                 }
 
                 ui.with_layout(Layout::right_to_left(), |ui| {
+                    let waiting = self.inbox.as_ref().map_or(0, InboxWatcher::waiting);
+                    if waiting > 0 && db.unsaved() {
+                        ui.label(match waiting {
+                            1 => "1 file in the inbox, save to import it.".to_string(),
+                            n => format!("{} files in the inbox, save to import them.", n),
+                        });
+                    }
                     if let Some(time) = self.last_save {
                         let difference = Instant::now() - time;
                         let minutes = difference.as_secs() / 60;
@@ -123,6 +274,33 @@ impl Frontend {
             });
         });
 
+        if let Some(holder) = &read_only {
+            TopBottomPanel::top("read_only").show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    ui.colored_label(
+                        Color32::YELLOW,
+                        format!("Read only: {} is editing this database.", holder),
+                    );
+                    if ui
+                        .button("Edit Anyway")
+                        .on_hover_text("For when they've closed it without it being unlocked")
+                        .clicked()
+                    {
+                        self.take_over_error = db.take_over().err().map(|err| format!("{:#}", err));
+                    }
+                });
+                if let Some(err) = &self.take_over_error {
+                    ui.colored_label(Color32::RED, format!("Couldn't take over: {}", err));
+                }
+            });
+        }
+        if let Ok(true) = self.reload_error.try_lock().map(|inner| inner.is_some()) {
+            self.conflict = true;
+        }
+        if self.conflict {
+            self.conflict_window(ctx, db);
+        }
+
         let mut current_view = self.history.pop().unwrap();
 
         let mut view_response = ViewResponse::Unchanged;
@@ -140,6 +318,77 @@ impl Frontend {
         }
 
         self.handle_view_response(view_response, current_view);
+
+        // edits are recorded once they're finished, so typing a description is one change
+        // rather than one per letter
+        if !ctx.wants_keyboard_input() && !ctx.is_using_pointer() {
+            db.record();
+        }
+    }
+
+    /// Asks what to do about someone else having saved over the database.
+    fn conflict_window(&mut self, ctx: &egui::CtxRef, db: &mut DbBackend) {
+        Window::new("Saved Elsewhere")
+            .collapsible(false)
+            .resizable(false)
+            .show(ctx, |ui| {
+                ui.label("Someone else has saved this database since it was opened here.");
+                if let Some(err) = &*self.reload_error.lock().unwrap() {
+                    ui.colored_label(Color32::RED, err.as_str());
+                }
+                ui.horizontal(|ui| {
+                    let reload = if ui
+                        .button("Merge")
+                        .on_hover_text("Keeps their changes, with yours on top")
+                        .clicked()
+                    {
+                        Some(true)
+                    } else if ui
+                        .button("Reload")
+                        .on_hover_text("Throws your changes away")
+                        .clicked()
+                    {
+                        Some(false)
+                    } else {
+                        None
+                    };
+                    if let Some(merge) = reload {
+                        let (handle, error) = (self.new_db.clone(), self.reload_error.clone());
+                        let current = db.clone();
+                        *error.lock().unwrap() = None;
+                        tokio::spawn(async move {
+                            // a failed merge reopens this window, saying why
+                            match current.reload(merge).await {
+                                Ok(new_db) => *handle.lock().unwrap() = Some(new_db),
+                                Err(err) => *error.lock().unwrap() = Some(format!("{:#}", err)),
+                            }
+                        });
+                        self.conflict = false;
+                    }
+
+                    if ui
+                        .button("Save Over")
+                        .on_hover_text("Throws their changes away")
+                        .clicked()
+                    {
+                        match db.save_over() {
+                            Ok(()) => {
+                                self.last_save = Some(Instant::now());
+                                self.conflict = false;
+                                *self.reload_error.lock().unwrap() = None;
+                            }
+                            Err(err) => {
+                                let err = format!("Couldn't save over: {:#}", err);
+                                *self.reload_error.lock().unwrap() = Some(err);
+                            }
+                        }
+                    }
+                    if ui.button("Cancel").clicked() {
+                        self.conflict = false;
+                        *self.reload_error.lock().unwrap() = None;
+                    }
+                });
+            });
     }
 
     fn handle_view_response(&mut self, view_response: ViewResponse, current_view: Box<dyn View>) {
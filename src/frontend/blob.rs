@@ -1,35 +1,341 @@
-use db::BlobId;
-use egui::{Button, ImageButton, Response, Ui};
+use std::{
+    collections::hash_map::DefaultHasher,
+    future::Future,
+    hash::{Hash, Hasher},
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use db::{BlobId, MediaType};
+use egui::{Button, Color32, ImageButton, Key, Response, ScrollArea, TextEdit, Ui, Vec2};
+use egui_demo_lib::easy_mark::easy_mark;
+use search::index::Document;
 
 use crate::{
-    backend::DbBackend,
-    frontend::{texture_storage::ImageStatus, Frontend},
+    backend::{store::BlobStore, DbBackend},
+    frontend::{
+        texture_storage::{ImageStatus, Priority},
+        viewer::{self, Compare},
+        Frontend,
+    },
     ui_memory::MemoryExt,
     views::{edit_blob::EditBlob, view_blob::ViewBlob},
 };
 
 pub fn display(ui: &mut Ui, frontend: &mut Frontend, db: &mut DbBackend, blob_id: BlobId) {
-    if let ImageStatus::Available(texture) = frontend.image_for(blob_id, db) {
+    match db[blob_id].media_type() {
+        MediaType::Text => display_text(ui, db, blob_id),
+        MediaType::Image => display_image(ui, frontend, db, blob_id),
+        MediaType::Video | MediaType::Audio => display_media(ui, frontend, db, blob_id),
+    }
+}
+
+/// Video and audio can't be played here, so they're opened in the default player instead.
+/// Videos show their poster frame meanwhile.
+fn display_media(ui: &mut Ui, frontend: &mut Frontend, db: &mut DbBackend, blob_id: BlobId) {
+    ui.horizontal(|ui| {
+        if ui
+            .button("▶ Play")
+            .on_hover_text("Opens in the default player")
+            .clicked()
+        {
+            let (store, key) = (db.store.clone(), db.storage_for(blob_id));
+            tokio::spawn(async move {
+                if let Ok(path) = store.fetch(&key).await {
+                    let _ = tokio::task::spawn_blocking(move || open::that(path)).await;
+                }
+            });
+        }
+        ui.label(
+            db[blob_id]
+                .duration
+                .map_or("Unknown length".to_string(), format_duration),
+        );
+    });
+    ui.separator();
+
+    if db[blob_id].media_type() == MediaType::Video {
+        display_image(ui, frontend, db, blob_id);
+    } else {
         ui.centered_and_justified(|ui| {
-            ui.add(
-                ImageButton::new(texture.id, texture.scaled(ui.available_size().into()))
-                    .selected(false)
-                    .frame(false),
-            )
-            .context_menu(|ui| {
+            ui.heading(format!("♪ {}", db[blob_id].file_name))
+                .context_menu(|ui| context_menu(ui, db, blob_id));
+        });
+    }
+}
+
+/// Formats as e.g. "1:05", or "1:02:05" past an hour.
+pub fn format_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    let (hours, minutes, seconds) = (seconds / 3600, seconds / 60 % 60, seconds % 60);
+    if hours > 0 {
+        format!("{}:{:02}:{:02}", hours, minutes, seconds)
+    } else {
+        format!("{}:{:02}", minutes, seconds)
+    }
+}
+
+fn display_image(ui: &mut Ui, frontend: &mut Frontend, db: &mut DbBackend, blob_id: BlobId) {
+    match frontend.image_for(blob_id, db) {
+        ImageStatus::Available(texture) => {
+            let id = ui.make_persistent_id(("zoom", blob_id));
+            viewer::show(ui, id, texture, None).context_menu(|ui| {
+                context_menu(ui, db, blob_id);
+            });
+        }
+        ImageStatus::Failed(reason) => {
+            ui.vertical_centered(|ui| {
+                ui.colored_label(
+                    Color32::LIGHT_RED,
+                    format!("Couldn't load image: {}", reason),
+                );
+                if ui.button("Retry").clicked() {
+                    frontend.retry_image(blob_id);
+                }
+            });
+        }
+        ImageStatus::Unavailable => {}
+    }
+}
+
+/// Like [`display`], with `other` compared against the blob. Only images can be compared,
+/// anything else is displayed on its own.
+pub fn display_compared(
+    ui: &mut Ui,
+    frontend: &mut Frontend,
+    db: &mut DbBackend,
+    blob_id: BlobId,
+    other: BlobId,
+    compare: Compare,
+) {
+    let is_image = |blob_id| db[blob_id].media_type() == MediaType::Image;
+    if !is_image(blob_id) || !is_image(other) {
+        return display(ui, frontend, db, blob_id);
+    }
+
+    match (
+        frontend.image_for(blob_id, db),
+        frontend.image_for(other, db),
+    ) {
+        (ImageStatus::Available(texture), ImageStatus::Available(other)) => {
+            // shares its zoom with the blob on its own, so switching comparisons keeps the place
+            let id = ui.make_persistent_id(("zoom", blob_id));
+            viewer::show(ui, id, texture, Some((other, compare))).context_menu(|ui| {
                 context_menu(ui, db, blob_id);
             });
+        }
+        _ => display_image(ui, frontend, db, blob_id),
+    }
+}
+
+/// Previous and next buttons for stepping through `blobs`, also bound to the arrow keys.
+/// Returns the blob stepped to.
+pub fn navigation(ui: &mut Ui, blobs: &[BlobId], current: BlobId) -> Option<BlobId> {
+    let position = blobs.iter().position(|blob_id| *blob_id == current)?;
+    if blobs.len() < 2 {
+        return None;
+    }
+
+    let mut step = None;
+    ui.horizontal(|ui| {
+        if ui.button("◀").clicked() {
+            step = Some(blobs.len() - 1);
+        }
+        ui.label(format!("{} / {}", position + 1, blobs.len()));
+        if ui.button("▶").clicked() {
+            step = Some(1);
+        }
+    });
+    if ui.input().key_pressed(Key::ArrowLeft) {
+        step = Some(blobs.len() - 1);
+    }
+    if ui.input().key_pressed(Key::ArrowRight) {
+        step = Some(1);
+    }
+
+    step.map(|step| blobs[(position + step) % blobs.len()])
+}
+
+/// Stands in for an image that failed to load, with the reason on hover.
+pub fn failed_tile(ui: &mut Ui, size: impl Into<Vec2>, reason: &str) -> Response {
+    ui.add_sized(size, Button::new("⚠").text_color(Color32::LIGHT_RED))
+        .on_hover_text(format!("{}\nClick to retry", reason))
+}
+
+/// Work on a blob's file done in the background, with its result once it's finished.
+#[derive(Clone)]
+struct Task<T>(Arc<Mutex<Option<Result<T, String>>>>);
+
+impl<T: Send + 'static> Task<T> {
+    fn spawn(job: impl Future<Output = anyhow::Result<T>> + Send + 'static) -> Self {
+        let finished = Arc::new(Mutex::new(None));
+        let result = finished.clone();
+        tokio::spawn(async move {
+            let done = job.await.map_err(|err| err.to_string());
+            *result.lock().unwrap() = Some(done);
         });
+        Self(finished)
+    }
+
+    /// The result, once the task has finished. Only given out once.
+    fn take(&self) -> Option<Result<T, String>> {
+        self.0.lock().unwrap().take()
+    }
+}
+
+#[derive(Clone)]
+struct TextBlob {
+    /// Reading the file, which is downloaded first if the store is remote.
+    loading: Option<Task<String>>,
+    /// Why the file couldn't be read, if it couldn't.
+    load_error: Option<String>,
+    content: String,
+    editing: bool,
+    dirty: bool,
+    /// Writing the file and putting it in the store, along with what was written.
+    saving: Option<(Task<()>, String)>,
+    /// Why the last save failed, if it did.
+    save_error: Option<String>,
+}
+
+impl TextBlob {
+    fn load(store: Arc<dyn BlobStore>, key: PathBuf) -> Self {
+        Self {
+            loading: Some(Task::spawn(async move {
+                let path = store.fetch(&key).await?;
+                Ok(tokio::fs::read_to_string(path).await?)
+            })),
+            load_error: None,
+            content: String::new(),
+            editing: false,
+            dirty: false,
+            saving: None,
+            save_error: None,
+        }
+    }
+
+    /// Writes the text to the store's local copy of the file, then puts it in the store.
+    fn save(&mut self, store: Arc<dyn BlobStore>, key: PathBuf) {
+        let content = self.content.clone();
+        let job = async move {
+            let path = store.local_path(&key);
+            if let Some(parent) = path.parent() {
+                tokio::fs::create_dir_all(parent).await?;
+            }
+            tokio::fs::write(&path, &content).await?;
+            store.put(&key, &path).await
+        };
+        self.saving = Some((Task::spawn(job), self.content.clone()));
+        self.save_error = None;
+    }
+
+    /// Picks up the results of whatever has finished since the last frame. The blob's hash
+    /// and the search index are only updated once a save has made it into the store.
+    fn poll(&mut self, ui: &Ui, db: &mut DbBackend, blob_id: BlobId) {
+        if let Some(loaded) = self.loading.as_ref().and_then(Task::take) {
+            match loaded {
+                Ok(content) => self.content = content,
+                Err(err) => self.load_error = Some(err),
+            }
+            self.loading = None;
+        }
+        if let Some((saved, content)) = &self.saving {
+            match saved.take() {
+                Some(Ok(())) => {
+                    let mut hash = DefaultHasher::new();
+                    content.as_bytes().hash(&mut hash);
+                    db.blobs.get_mut(blob_id).unwrap().hash = hash.finish();
+                    db.index.index_text(Document::Blob(blob_id), content);
+                    self.dirty = *content != self.content;
+                    self.saving = None;
+                }
+                Some(Err(err)) => {
+                    self.save_error = Some(err);
+                    self.saving = None;
+                }
+                None => {}
+            }
+        }
+        if self.loading.is_some() || self.saving.is_some() {
+            ui.ctx().request_repaint();
+        }
     }
 }
 
+fn display_text(ui: &mut Ui, db: &mut DbBackend, blob_id: BlobId) {
+    let (store, key) = (db.store.clone(), db.storage_for(blob_id));
+    let memory_id = ui.make_persistent_id(("text_blob", blob_id));
+    let (load_store, load_key) = (store.clone(), key.clone());
+
+    ui.with_memory(
+        memory_id,
+        || TextBlob::load(load_store, load_key),
+        |ui, text| {
+            text.poll(ui, db, blob_id);
+            if text.loading.is_some() {
+                ui.label("Loading...");
+                return;
+            }
+            if let Some(err) = &text.load_error {
+                let reason = format!("Couldn't read this blob: {}", err);
+                if failed_tile(ui, [256.0, 256.0], &reason).clicked() {
+                    *text = TextBlob::load(store, key);
+                }
+                return;
+            }
+
+            ui.horizontal(|ui| {
+                ui.label(format!("{} words", text.content.split_whitespace().count()));
+                ui.checkbox(&mut text.editing, "Edit");
+                if text.editing {
+                    let saving = text.saving.is_some();
+                    let save = Button::new(if saving { "Saving..." } else { "Save" });
+                    if ui.add_enabled(text.dirty && !saving, save).clicked() {
+                        text.save(store, key);
+                    }
+                }
+                if let Some(err) = &text.save_error {
+                    ui.colored_label(Color32::LIGHT_RED, format!("Couldn't save: {}", err));
+                }
+            });
+            ui.separator();
+
+            ScrollArea::vertical()
+                .auto_shrink([false, false])
+                .show(ui, |ui| {
+                    if text.editing {
+                        text.dirty |= ui
+                            .add(
+                                TextEdit::multiline(&mut text.content).desired_width(f32::INFINITY),
+                            )
+                            .changed();
+                    } else if db[blob_id].is_markdown() {
+                        easy_mark(ui, &text.content);
+                    } else {
+                        ui.label(&text.content);
+                    }
+                });
+        },
+    );
+}
+
 pub fn thumbnail(
     ui: &mut Ui,
     frontend: &mut Frontend,
     db: &mut DbBackend,
     blob_id: BlobId,
 ) -> Response {
-    let response = match frontend.thumbnail_for(blob_id, db) {
+    let status = if matches!(
+        db[blob_id].media_type(),
+        MediaType::Image | MediaType::Video
+    ) {
+        frontend.thumbnail_for(blob_id, db, Priority::of_next(ui, Vec2::splat(256.0)))
+    } else {
+        ImageStatus::Unavailable
+    };
+
+    let response = match status {
         ImageStatus::Available(texture) => {
             let response = ui.add(ImageButton::new(texture.id, texture.with_height(256.0)));
             if response.double_clicked() {
@@ -37,8 +343,15 @@ pub fn thumbnail(
             }
             response
         }
+        ImageStatus::Failed(reason) => {
+            let response = failed_tile(ui, [256.0, 256.0], &reason);
+            if response.clicked() {
+                frontend.retry_thumbnail(blob_id);
+            }
+            response
+        }
         ImageStatus::Unavailable => {
-            let response = ui.add_sized([256.0, 256.0], Button::new(&db[blob_id].file_name));
+            let response = ui.add_sized([256.0, 256.0], Button::new(label(db, blob_id)));
             if response.double_clicked() {
                 ui.push_view(ViewBlob { blob_id });
             }
@@ -53,9 +366,22 @@ pub fn thumbnail(
     response
 }
 
+/// The file name, along with the length of video and audio.
+pub fn label(db: &DbBackend, blob_id: BlobId) -> String {
+    let blob = &db[blob_id];
+    match blob.duration {
+        Some(duration) => format!("{}\n{}", blob.file_name, format_duration(duration)),
+        None => blob.file_name.clone(),
+    }
+}
+
 fn context_menu(ui: &mut Ui, db: &mut DbBackend, blob_id: BlobId) {
     if ui.button("Save to File").clicked() {
-        let (storage, file_name) = { (db.storage_for(blob_id), db[blob_id].file_name.clone()) };
+        let (store, key, file_name) = (
+            db.store.clone(),
+            db.storage_for(blob_id),
+            db[blob_id].file_name.clone(),
+        );
 
         tokio::spawn(async move {
             let mut dialog = rfd::AsyncFileDialog::new().set_file_name(&file_name);
@@ -71,6 +397,7 @@ fn context_menu(ui: &mut Ui, db: &mut DbBackend, blob_id: BlobId) {
                 return;
             };
 
+            let storage = store.fetch(&key).await.unwrap();
             tokio::fs::copy(storage, file.path()).await.unwrap();
         });
 
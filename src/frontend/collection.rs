@@ -0,0 +1,70 @@
+use db::{Collection, CollectionId, PieceId};
+use egui::{Response, Ui};
+use egui_demo_lib::easy_mark::easy_mark;
+use itertools::Itertools;
+
+use crate::{
+    backend::DbBackend,
+    ui_memory::MemoryExt,
+    views::{slideshow::Slideshow, view_collection::ViewCollection},
+};
+
+pub fn label(ui: &mut Ui, db: &DbBackend, collection_id: CollectionId) -> Response {
+    let collection = &db[collection_id];
+    let response = ui.selectable_label(false, &collection.name);
+    if response.clicked() {
+        ui.push_view(ViewCollection { collection_id });
+    }
+
+    if collection.description.trim() != "" {
+        response.clone().on_hover_ui(|ui| {
+            easy_mark(ui, &collection.description);
+        });
+    }
+
+    response
+}
+
+pub fn context_menu(ui: &mut Ui, collection_id: CollectionId) {
+    if ui.button("View").clicked() {
+        ui.push_view(ViewCollection { collection_id });
+        ui.close_menu();
+    }
+    if ui.button("Slideshow").clicked() {
+        ui.push_view(Slideshow::new(collection_id));
+        ui.close_menu();
+    }
+}
+
+pub fn list(db: &DbBackend, iter: impl Iterator<Item = CollectionId>, ui: &mut Ui) {
+    for collection_id in iter.sorted_by(|lhs, rhs| db[lhs].name.cmp(&db[rhs].name)) {
+        label(ui, db, collection_id).context_menu(|ui| context_menu(ui, collection_id));
+    }
+}
+
+/// Adds `piece_id` to the end of an existing collection, or to a new one.
+pub fn add_menu(ui: &mut Ui, db: &mut DbBackend, piece_id: PieceId) {
+    ui.menu_button("Add to Collection", |ui| {
+        for collection_id in db
+            .collections()
+            .sorted_by(|(_, lhs), (_, rhs)| lhs.name.cmp(&rhs.name))
+            .map(|(collection_id, _)| collection_id)
+            .collect::<Vec<_>>()
+        {
+            if ui.button(&db[collection_id].name).clicked() {
+                let collection = db.collections.get_mut(collection_id).unwrap();
+                collection.entries.push(piece_id.into());
+                ui.close_menu();
+            }
+        }
+        ui.separator();
+        if ui.button("New Collection").clicked() {
+            let collection_id = db.create_collection(Collection {
+                entries: vec![piece_id.into()],
+                ..Default::default()
+            });
+            ui.push_view(ViewCollection { collection_id });
+            ui.close_menu();
+        }
+    });
+}
@@ -2,8 +2,8 @@ use egui::{text_edit::CCursorRange, *};
 use egui_demo_lib::easy_mark::MemoizedEasymarkHighlighter;
 
 #[allow(clippy::ptr_arg)]
-pub fn easy_mark_editor(ui: &mut egui::Ui, code: &mut String) {
-    let response = {
+pub fn easy_mark_editor(ui: &mut egui::Ui, code: &mut String) -> Response {
+    let mut response = {
         let mut layouter = |ui: &egui::Ui, easymark: &str, wrap_width: f32| {
             let mut layout_job =
                 MemoizedEasymarkHighlighter::default().highlight(ui.visuals(), easymark);
@@ -20,9 +20,12 @@ pub fn easy_mark_editor(ui: &mut egui::Ui, code: &mut String) {
             if any_change {
                 state.set_ccursor_range(Some(ccursor_range));
                 state.store(ui.ctx(), response.id);
+                response.mark_changed();
             }
         }
     }
+
+    response
 }
 
 fn shortcuts(ui: &Ui, code: &mut dyn TextBuffer, ccursor_range: &mut CCursorRange) -> bool {
@@ -0,0 +1,154 @@
+use std::{
+    path::PathBuf,
+    sync::{Arc, Mutex},
+};
+
+use db::{Blob, BlobType, MediaType, Piece, PieceId};
+use egui::{ComboBox, Window};
+use search::index::Document;
+use strum::IntoEnumIterator;
+
+use crate::backend::{blob, DbBackend};
+
+/// What files dropped onto the window are added to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DropTarget {
+    /// Dropped files become new pieces.
+    Gallery,
+    /// Dropped files become blobs of this piece.
+    Piece(PieceId),
+}
+
+/// Files dropped onto the window, waiting for the user to choose how they're imported.
+#[derive(Debug, Clone)]
+pub struct Dropped {
+    files: Vec<PathBuf>,
+    target: DropTarget,
+    blob_type: BlobType,
+    /// Only used when dropped on the gallery.
+    one_piece: bool,
+}
+
+impl Dropped {
+    pub fn new(files: Vec<PathBuf>, target: DropTarget) -> Self {
+        Self {
+            files,
+            target,
+            blob_type: BlobType::Canon,
+            one_piece: false,
+        }
+    }
+
+    /// Returns false once the window is closed, whether or not anything was imported.
+    pub fn window(&mut self, ctx: &egui::CtxRef, imports: &Imports, db: &mut DbBackend) -> bool {
+        let mut open = true;
+        let mut done = false;
+
+        Window::new("Import Files")
+            .collapsible(false)
+            .resizable(false)
+            .open(&mut open)
+            .show(ctx, |ui| {
+                for file in &self.files {
+                    ui.label(file.display().to_string());
+                }
+                ui.separator();
+
+                match self.target {
+                    DropTarget::Piece(piece_id) if !db.exists(piece_id) => {
+                        ui.label("The piece no longer exists.");
+                        return;
+                    }
+                    DropTarget::Piece(_) => {}
+                    DropTarget::Gallery => {
+                        ui.radio_value(&mut self.one_piece, false, "One piece per file");
+                        ui.radio_value(&mut self.one_piece, true, "One piece for all files");
+                    }
+                }
+                ComboBox::from_label("Type")
+                    .selected_text(self.blob_type.to_string())
+                    .show_ui(ui, |ui| {
+                        for blob_type in BlobType::iter() {
+                            ui.selectable_value(
+                                &mut self.blob_type,
+                                blob_type,
+                                blob_type.to_string(),
+                            );
+                        }
+                    });
+
+                ui.horizontal(|ui| {
+                    if ui.button("Import").clicked() {
+                        self.import(imports, db);
+                        done = true;
+                    }
+                    if ui.button("Cancel").clicked() {
+                        done = true;
+                    }
+                });
+            });
+
+        open && !done
+    }
+
+    fn import(&self, imports: &Imports, db: &mut DbBackend) {
+        let mut piece = match self.target {
+            DropTarget::Piece(piece_id) => Some(piece_id),
+            DropTarget::Gallery => None,
+        };
+        for file in &self.files {
+            let to = match piece {
+                Some(piece_id) => piece_id,
+                None => {
+                    let piece_id = db.create_piece(Piece::default());
+                    if self.one_piece {
+                        piece = Some(piece_id);
+                    }
+                    piece_id
+                }
+            };
+            imports.start(to, file.clone(), self.blob_type);
+        }
+    }
+}
+
+/// Blobs being read in the background, to be added to the database once they're ready.
+#[derive(Debug, Clone, Default)]
+pub struct Imports {
+    finished: Arc<Mutex<Vec<(PieceId, PathBuf, Blob)>>>,
+}
+
+impl Imports {
+    pub fn start(&self, to: PieceId, path: PathBuf, blob_type: BlobType) {
+        let finished = self.finished.clone();
+        tokio::spawn(async move {
+            if let Ok(blob) = blob::from_path(path.clone(), blob_type).await {
+                finished.lock().unwrap().push((to, path, blob));
+            }
+        });
+    }
+
+    /// Adds every blob that's been read to its piece, copying its file into the database's store.
+    pub fn finish(&self, db: &mut DbBackend) {
+        let finished = std::mem::take(&mut *self.finished.lock().unwrap());
+        for (to, path, blob) in finished {
+            if !db.exists(to) {
+                continue;
+            }
+
+            // new pieces take after their first file
+            if db.blobs_for_piece(to).next().is_none() {
+                db.pieces.get_mut(to).unwrap().media_type = blob.media_type();
+            }
+            let is_text = blob.media_type() == MediaType::Text;
+            let (blob_id, key) = db.add_blob(to, blob);
+            if is_text {
+                if let Ok(text) = std::fs::read_to_string(&path) {
+                    db.index.index_text(Document::Blob(blob_id), &text);
+                }
+            }
+            let store = db.store.clone();
+            tokio::spawn(async move { store.put(&key, &path).await.unwrap() });
+        }
+    }
+}
@@ -1,60 +1,146 @@
-use db::PieceId;
-use egui::{ImageButton, PointerButton};
+use db::{BlobId, MediaType, PieceId};
+use egui::{Button, ImageButton, PointerButton, Response, Vec2};
 use egui_demo_lib::easy_mark::easy_mark;
 use itertools::Itertools;
 
 use crate::{
     backend::DbBackend,
     frontend::{
+        blob, collection,
         tag::{self},
-        texture_storage::ImageStatus,
+        texture_storage::{ImageStatus, Priority},
         Frontend,
     },
     ui_memory::MemoryExt,
     views::{edit_piece::EditPiece, view_piece::ViewPiece},
 };
-pub fn thumbnail(db: &DbBackend, piece_id: PieceId, frontend: &mut Frontend, ui: &mut egui::Ui) {
-    let blob_id = db
-        .blobs_for_piece(piece_id)
+/// The blob shown when a piece is displayed as a whole, canon blobs first.
+pub fn preview_blob(db: &DbBackend, piece_id: PieceId) -> Option<BlobId> {
+    blobs_in_order(db, piece_id).first().copied()
+}
+
+/// A piece's blobs in the order they're listed, canon blobs first, then oldest first.
+pub fn blobs_in_order(db: &DbBackend, piece_id: PieceId) -> Vec<BlobId> {
+    db.blobs_for_piece(piece_id)
         .sorted_by_key(|item| (db[item].blob_type, db[item].added))
-        .next();
-    if let Some(blob_id) = blob_id {
-        if let ImageStatus::Available(image) = frontend.thumbnail_for(blob_id, db) {
-            let response = ui.add(ImageButton::new(image.id, image.with_height(256.0)));
-            if response.clicked_by(PointerButton::Primary) {
-                ui.push_view(ViewPiece {
-                    piece_id,
-                    previewed: Some(blob_id),
-                });
-            }
+        .collect()
+}
 
-            response.context_menu(|ui| {
-                if ui.button("Edit").clicked() {
-                    ui.push_view(EditPiece {
-                        piece_id,
-                        previewed: Some(blob_id),
-                    });
-                    ui.close_menu();
-                }
-                if ui.button("View").clicked() {
-                    ui.push_view(ViewPiece {
-                        piece_id,
-                        previewed: Some(blob_id),
-                    });
-                    ui.close_menu();
+pub fn thumbnail(
+    db: &DbBackend,
+    piece_id: PieceId,
+    frontend: &mut Frontend,
+    ui: &mut egui::Ui,
+) -> Option<Response> {
+    thumbnail_with_blob(db, piece_id, preview_blob(db, piece_id)?, frontend, ui)
+}
+
+/// Like [`thumbnail`], but ctrl-click and shift-click select the piece instead of opening it.
+/// `shown` is every piece in view, in order.
+pub fn selectable_thumbnail(
+    db: &DbBackend,
+    piece_id: PieceId,
+    shown: &[PieceId],
+    frontend: &mut Frontend,
+    ui: &mut egui::Ui,
+) {
+    if let Some(response) = thumbnail(db, piece_id, frontend, ui) {
+        if response.clicked_by(PointerButton::Primary) {
+            let modifiers = ui.input().modifiers;
+            frontend.selection.click_piece(piece_id, shown, modifiers);
+        }
+        if frontend.selection.pieces.contains(&piece_id) {
+            ui.painter()
+                .rect_stroke(response.rect, 0.0, ui.visuals().selection.stroke);
+        }
+    }
+}
+
+/// Like [`thumbnail`], but showing `blob_id` instead of the piece's preview.
+/// Returns None while the thumbnail is still loading.
+pub fn thumbnail_with_blob(
+    db: &DbBackend,
+    piece_id: PieceId,
+    blob_id: BlobId,
+    frontend: &mut Frontend,
+    ui: &mut egui::Ui,
+) -> Option<Response> {
+    sized_thumbnail(db, piece_id, blob_id, 256.0, frontend, ui)
+}
+
+fn sized_thumbnail(
+    db: &DbBackend,
+    piece_id: PieceId,
+    blob_id: BlobId,
+    height: f32,
+    frontend: &mut Frontend,
+    ui: &mut egui::Ui,
+) -> Option<Response> {
+    let response = if matches!(db[blob_id].media_type(), MediaType::Text | MediaType::Audio) {
+        ui.add_sized([height, height], Button::new(blob::label(db, blob_id)))
+    } else {
+        let priority = Priority::of_next(ui, Vec2::splat(height));
+        match frontend.thumbnail_for(blob_id, db, priority) {
+            ImageStatus::Available(image) => {
+                ui.add(ImageButton::new(image.id, image.with_height(height)))
+            }
+            ImageStatus::Failed(reason) => {
+                let response = blob::failed_tile(ui, [height, height], &reason);
+                if response.clicked() {
+                    frontend.retry_thumbnail(blob_id);
                 }
-            });
+                return Some(response);
+            }
+            ImageStatus::Unavailable => return None,
         }
+    };
+
+    let selecting = {
+        let modifiers = ui.input().modifiers;
+        modifiers.shift || modifiers.command
+    };
+    if response.clicked_by(PointerButton::Primary) && !selecting {
+        ui.push_view(ViewPiece {
+            piece_id,
+            previewed: Some(blob_id),
+        });
     }
+
+    response.clone().context_menu(|ui| {
+        if ui.button("Edit").clicked() {
+            ui.push_view(EditPiece {
+                piece_id,
+                previewed: Some(blob_id),
+            });
+            ui.close_menu();
+        }
+        if ui.button("View").clicked() {
+            ui.push_view(ViewPiece {
+                piece_id,
+                previewed: Some(blob_id),
+            });
+            ui.close_menu();
+        }
+    });
+
+    Some(response)
 }
 
-pub fn info_panel(db: &mut DbBackend, piece_id: PieceId, ui: &mut egui::Ui) {
+pub fn info_panel(
+    db: &mut DbBackend,
+    piece_id: PieceId,
+    frontend: &mut Frontend,
+    ui: &mut egui::Ui,
+) {
     let piece = &db[piece_id];
+    ui.label(format!("ID: {}", piece_id));
     ui.label(format!(
         "External ID: {}",
         piece.external_id.as_deref().unwrap_or("<none>")
     ));
     ui.label(format!("Added: {}", piece.added));
+    ui.label(format!("Source: {}", piece.source_type));
+    ui.label(format!("Media: {}", piece.media_type));
     if let Some(price) = piece.base_price {
         ui.label(format!("Price: ${}", price));
     }
@@ -67,4 +153,36 @@ pub fn info_panel(db: &mut DbBackend, piece_id: PieceId, ui: &mut egui::Ui) {
     }
     ui.separator();
     tag::list(db, db.tags_for_piece(piece_id), ui);
+    relations(db, piece_id, frontend, ui);
+    ui.separator();
+    collection::list(db, db.collections_for_piece(piece_id), ui);
+    collection::add_menu(ui, db, piece_id);
+}
+
+/// Thumbnails of the related pieces, grouped by how they're related.
+fn relations(db: &DbBackend, piece_id: PieceId, frontend: &mut Frontend, ui: &mut egui::Ui) {
+    let relations = db
+        .relations_for_piece(piece_id)
+        .sorted_by_key(|relation| (relation.describe(piece_id), relation.other(piece_id)))
+        .collect::<Vec<_>>();
+
+    for (description, group) in &relations
+        .into_iter()
+        .group_by(|relation| relation.describe(piece_id))
+    {
+        ui.separator();
+        ui.label(description);
+        ui.horizontal_wrapped(|ui| {
+            for other in group.filter_map(|relation| relation.other(piece_id)) {
+                if let Some(blob_id) = preview_blob(db, other) {
+                    sized_thumbnail(db, other, blob_id, 64.0, frontend, ui);
+                } else if ui.button(format!("#{}", other)).clicked() {
+                    ui.push_view(ViewPiece {
+                        piece_id: other,
+                        previewed: None,
+                    });
+                }
+            }
+        });
+    }
 }
@@ -0,0 +1,77 @@
+use db::{PieceId, Relation, RelationType};
+use egui::{Button, ComboBox, Ui};
+use itertools::Itertools;
+use std::hash::Hash;
+use strum::IntoEnumIterator;
+
+use crate::{
+    backend::DbBackend,
+    ui_memory::{MemoryExt, TextItemEdit},
+};
+
+#[derive(Clone)]
+struct NewRelation {
+    kind: RelationType,
+    other: Option<i64>,
+}
+
+pub fn relation_editor<IdSource>(ui: &mut Ui, id: IdSource, piece_id: PieceId, db: &mut DbBackend)
+where
+    IdSource: Hash + std::fmt::Debug,
+{
+    let memory_id = ui.make_persistent_id(id);
+
+    ui.with_memory(
+        memory_id,
+        || NewRelation {
+            kind: RelationType::Sequel,
+            other: None,
+        },
+        |ui, new| {
+            ui.horizontal(|ui| {
+                ComboBox::from_id_source(memory_id.with("kind"))
+                    .selected_text(new.kind.to_string())
+                    .show_ui(ui, |ui| {
+                        for kind in RelationType::iter() {
+                            ui.selectable_value(&mut new.kind, kind, kind.to_string());
+                        }
+                    });
+                ui.add(
+                    TextItemEdit::new(memory_id.with("other"), &mut new.other)
+                        .hint_text("Piece ID"),
+                );
+
+                let relation = new.other.map(|other| Relation {
+                    src: piece_id,
+                    kind: new.kind,
+                    dest: PieceId::from(other as usize),
+                });
+                let valid = relation.map_or(false, |relation| {
+                    relation.src != relation.dest
+                        && db.exists(relation.dest)
+                        && !db.relations.contains(&relation)
+                });
+                if ui.add_enabled(valid, Button::new("Add")).clicked() {
+                    db.relate(relation.unwrap());
+                }
+            });
+        },
+    );
+
+    for relation in db
+        .relations_for_piece(piece_id)
+        .sorted_by_key(|relation| (relation.describe(piece_id), relation.other(piece_id)))
+        .collect::<Vec<_>>()
+    {
+        ui.horizontal(|ui| {
+            ui.label(format!(
+                "{} #{}",
+                relation.describe(piece_id),
+                relation.other(piece_id).unwrap()
+            ));
+            if ui.small_button("Remove").clicked() {
+                db.unrelate(relation);
+            }
+        });
+    }
+}
@@ -0,0 +1,240 @@
+use std::collections::BTreeSet;
+
+use db::{PieceId, TagId};
+use egui::{Button, Modifiers, ScrollArea, TextEdit, Ui};
+use itertools::Itertools;
+
+use crate::{
+    backend::{bulk::BulkEdit, export::Export, DbBackend},
+    frontend::{category, tag, Frontend},
+    ui_memory::MemoryExt,
+};
+
+/// Pieces and tags picked out for bulk edits, kept while moving between views.
+#[derive(Debug, Clone, Default)]
+pub struct Selection {
+    pub pieces: BTreeSet<PieceId>,
+    pub tags: BTreeSet<TagId>,
+    piece_anchor: Option<PieceId>,
+    tag_anchor: Option<TagId>,
+}
+
+impl Selection {
+    /// Selects pieces on ctrl-click and shift-click, where `shown` is every piece in view,
+    /// in order. Returns false for plain clicks, which are left to the caller.
+    pub fn click_piece(
+        &mut self,
+        piece_id: PieceId,
+        shown: &[PieceId],
+        modifiers: Modifiers,
+    ) -> bool {
+        click(
+            &mut self.pieces,
+            &mut self.piece_anchor,
+            piece_id,
+            shown,
+            modifiers,
+        )
+    }
+
+    /// Like [`Selection::click_piece`], but for tags.
+    pub fn click_tag(&mut self, tag_id: TagId, shown: &[TagId], modifiers: Modifiers) -> bool {
+        click(
+            &mut self.tags,
+            &mut self.tag_anchor,
+            tag_id,
+            shown,
+            modifiers,
+        )
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pieces.is_empty() && self.tags.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        *self = Self::default();
+    }
+
+    /// Forgets anything that was deleted since it was selected.
+    fn retain_existing(&mut self, db: &DbBackend) {
+        self.pieces.retain(|piece_id| db.exists(*piece_id));
+        self.tags.retain(|tag_id| db.exists(*tag_id));
+    }
+}
+
+fn click<T: Copy + Ord>(
+    selected: &mut BTreeSet<T>,
+    anchor: &mut Option<T>,
+    clicked: T,
+    shown: &[T],
+    modifiers: Modifiers,
+) -> bool {
+    let position = |item| shown.iter().position(|shown| *shown == item);
+
+    if modifiers.shift {
+        match (anchor.and_then(position), position(clicked)) {
+            (Some(start), Some(end)) => {
+                let (start, end) = (start.min(end), start.max(end));
+                selected.extend(&shown[start..=end]);
+            }
+            _ => {
+                selected.insert(clicked);
+                *anchor = Some(clicked);
+            }
+        }
+        true
+    } else if modifiers.command {
+        if !selected.remove(&clicked) {
+            selected.insert(clicked);
+        }
+        *anchor = Some(clicked);
+        true
+    } else {
+        false
+    }
+}
+
+/// Actions for everything selected, shown while anything is.
+pub fn bulk_panel(ui: &mut Ui, frontend: &mut Frontend, db: &mut DbBackend) {
+    frontend.selection.retain_existing(db);
+    if frontend.selection.is_empty() {
+        return;
+    }
+
+    let pieces = frontend
+        .selection
+        .pieces
+        .iter()
+        .copied()
+        .collect::<Vec<_>>();
+    let tags = frontend.selection.tags.iter().copied().collect::<Vec<_>>();
+    let mut edit = None;
+
+    ui.separator();
+    ui.horizontal(|ui| {
+        ui.label(match (pieces.len(), tags.len()) {
+            (pieces, 0) => format!("{} pieces selected", pieces),
+            (0, tags) => format!("{} tags selected", tags),
+            (pieces, tags) => format!("{} pieces and {} tags selected", pieces, tags),
+        });
+        if ui.button("Clear").clicked() {
+            frontend.selection.clear();
+        }
+    });
+
+    if !pieces.is_empty() {
+        ui.horizontal(|ui| {
+            ui.menu_button("Add Tag", |ui| {
+                if let Some(tag) = tag_picker(ui, "bulk_add_tag", db, db.tags.keys()) {
+                    edit = Some(BulkEdit::AddTag {
+                        pieces: pieces.clone(),
+                        tag,
+                    });
+                    ui.close_menu();
+                }
+            });
+            ui.menu_button("Remove Tag", |ui| {
+                let used = pieces
+                    .iter()
+                    .flat_map(|piece_id| db.tags_for_piece(*piece_id))
+                    .unique()
+                    .collect::<Vec<_>>();
+                if let Some(tag) = tag_picker(ui, "bulk_remove_tag", db, used.into_iter()) {
+                    edit = Some(BulkEdit::RemoveTag {
+                        pieces: pieces.clone(),
+                        tag,
+                    });
+                    ui.close_menu();
+                }
+            });
+        });
+        ui.horizontal(|ui| {
+            if ui
+                .add_enabled(pieces.len() > 1, Button::new("Merge"))
+                .on_hover_text("Merge the others into the one with the lowest ID")
+                .clicked()
+            {
+                edit = Some(BulkEdit::MergePieces(pieces.clone()));
+            }
+            if ui.button("Export").clicked() {
+                let export = Export::new(
+                    db,
+                    pieces
+                        .iter()
+                        .flat_map(|piece_id| db.blobs_for_piece(*piece_id)),
+                );
+                tokio::spawn(async move {
+                    if let Some(dest) = rfd::AsyncFileDialog::new().pick_folder().await {
+                        export.to_folder(dest.path()).await.unwrap();
+                    }
+                });
+            }
+            if ui.button("Delete Pieces").clicked() {
+                edit = Some(BulkEdit::DeletePieces(pieces.clone()));
+            }
+        });
+    }
+
+    if !tags.is_empty() {
+        ui.horizontal(|ui| {
+            ui.menu_button("Set Category", |ui| {
+                if ui.button("None").clicked() {
+                    edit = Some(BulkEdit::SetCategory {
+                        tags: tags.clone(),
+                        category: None,
+                    });
+                    ui.close_menu();
+                }
+                for category_id in db
+                    .categories
+                    .keys()
+                    .sorted_by_key(|category_id| &db[category_id].name)
+                {
+                    if category::label(ui, db, category_id).clicked() {
+                        edit = Some(BulkEdit::SetCategory {
+                            tags: tags.clone(),
+                            category: Some(category_id),
+                        });
+                        ui.close_menu();
+                    }
+                }
+            });
+            if ui.button("Delete Tags").clicked() {
+                edit = Some(BulkEdit::DeleteTags(tags.clone()));
+            }
+        });
+    }
+
+    if let Some(edit) = edit {
+        frontend.bulk_edit(db, edit);
+    }
+}
+
+/// A filterable list of `tags`, returning the one that was clicked.
+fn tag_picker(
+    ui: &mut Ui,
+    id: &str,
+    db: &DbBackend,
+    tags: impl Iterator<Item = TagId>,
+) -> Option<TagId> {
+    let memory_id = ui.make_persistent_id(id);
+
+    ui.with_memory(memory_id, String::new, |ui, filter| {
+        let mut picked = None;
+        ui.add(TextEdit::singleline(filter).hint_text("Search"));
+        ScrollArea::vertical().max_height(300.0).show(ui, |ui| {
+            for tag_id in tags
+                .filter(|tag_id| {
+                    filter.trim() == "" || db[tag_id].name.matches(filter.as_str()).count() > 0
+                })
+                .sorted_by_key(|tag_id| &db[tag_id].name)
+            {
+                if tag::label(ui, db, tag_id).clicked() {
+                    picked = Some(tag_id);
+                }
+            }
+        });
+        picked
+    })
+}
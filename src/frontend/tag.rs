@@ -1,3 +1,5 @@
+use std::collections::BTreeSet;
+
 use db::TagId;
 use egui::{Response, RichText};
 use egui_demo_lib::easy_mark::easy_mark;
@@ -11,13 +13,22 @@ use crate::{
 };
 
 pub fn label(ui: &mut egui::Ui, db: &DbBackend, tag_id: TagId) -> Response {
+    selectable_label(ui, db, tag_id, false)
+}
+
+pub fn selectable_label(
+    ui: &mut egui::Ui,
+    db: &DbBackend,
+    tag_id: TagId,
+    selected: bool,
+) -> Response {
     let mut text = RichText::new(&db[tag_id].name);
 
     if let Some(category_id) = db.category_for_tag(tag_id) {
         text = text.color(color32_from(db[category_id].color));
     }
 
-    let response = ui.selectable_label(false, text);
+    let response = ui.selectable_label(selected, text);
 
     let description = &db[tag_id].description;
     if description.trim() != "" {
@@ -41,6 +52,26 @@ pub fn label(ui: &mut egui::Ui, db: &DbBackend, tag_id: TagId) -> Response {
 }
 
 pub fn list(db: &DbBackend, iter: impl Iterator<Item = TagId>, ui: &mut egui::Ui) {
+    selectable_list(db, iter, &BTreeSet::new(), ui);
+}
+
+/// Like [`list`], highlighting the `selected` tags. Returns every tag in the order
+/// they were shown, along with the one that was clicked.
+pub fn selectable_list(
+    db: &DbBackend,
+    iter: impl Iterator<Item = TagId>,
+    selected: &BTreeSet<TagId>,
+    ui: &mut egui::Ui,
+) -> (Vec<TagId>, Option<TagId>) {
+    let mut shown = Vec::new();
+    let mut clicked = None;
+    let mut show = |ui: &mut egui::Ui, tag_id| {
+        if selectable_label(ui, db, tag_id, selected.contains(&tag_id)).clicked() {
+            clicked = Some(tag_id);
+        }
+        shown.push(tag_id);
+    };
+
     let iter = iter.collect::<Vec<_>>().into_iter();
     for category_id in iter
         .clone()
@@ -56,7 +87,7 @@ pub fn list(db: &DbBackend, iter: impl Iterator<Item = TagId>, ui: &mut egui::Ui
                 .filter(|tag_id| db.category_for_tag(*tag_id) == Some(category_id))
                 .sorted_by_key(|tag_id| &db[tag_id].name)
             {
-                label(ui, db, tag_id);
+                show(ui, tag_id);
             }
         });
     }
@@ -65,6 +96,8 @@ pub fn list(db: &DbBackend, iter: impl Iterator<Item = TagId>, ui: &mut egui::Ui
         .filter(|tag_id| db.category_for_tag(*tag_id).is_none())
         .sorted_by_key(|tag_id| &db[tag_id].name)
     {
-        label(ui, db, tag_id);
+        show(ui, tag_id);
     }
+
+    (shown, clicked)
 }
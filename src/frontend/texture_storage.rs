@@ -1,13 +1,25 @@
-use db::BlobId;
-use egui::TextureId;
+use db::{BlobId, MediaType};
+use egui::{Rect, TextureId, Ui, Vec2};
+use image::{imageops, imageops::FilterType, DynamicImage, RgbaImage};
 use std::{
     collections::{BTreeMap, BTreeSet},
     path::PathBuf,
-    sync::mpsc,
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        mpsc, Arc, Condvar, Mutex,
+    },
+    time::{Duration, Instant},
 };
 use wgpu::{util::DeviceExt, Device, Extent3d, Queue, TextureDescriptor};
 
-use crate::backend::DbBackend;
+use crate::backend::{
+    media::{Ffmpeg, Posters},
+    store::BlobStore,
+    thumbnails::{ThumbnailCache, THUMBNAIL_SIZE, WORKERS},
+    DbBackend,
+};
+
+pub mod decoders;
 
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
 pub enum ImageRequest {
@@ -16,75 +28,331 @@ pub enum ImageRequest {
     External(PathBuf),
 }
 
+/// Full images are shrunk to this until the window's size is known.
+const DEFAULT_DISPLAY_SIZE: u32 = 1920;
+/// How much GPU memory textures can take up before ones that aren't on screen are evicted.
+const TEXTURE_BUDGET: usize = 512 * 1024 * 1024;
+
+/// How urgently an image is needed, most urgent first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Priority {
+    /// On screen right now.
+    Visible,
+    /// Laid out, but scrolled out of view.
+    Hidden,
+}
+
+impl Priority {
+    /// Whether something of `size` added next to `ui` will be on screen.
+    pub fn of_next(ui: &Ui, size: Vec2) -> Self {
+        if ui.is_rect_visible(Rect::from_min_size(ui.cursor().min, size)) {
+            Priority::Visible
+        } else {
+            Priority::Hidden
+        }
+    }
+}
+
 pub struct ImageData {
-    pub image: BTreeMap<ImageRequest, Image>,
+    textures: TextureCache,
+    /// Requests sent to the loader that haven't come back yet.
+    pending: BTreeMap<ImageRequest, Priority>,
+    /// Pending requests asked for again this frame. The rest are cancelled,
+    /// since whatever wanted them is gone.
+    requested: BTreeSet<ImageRequest>,
+    /// Why each failed request failed, until it's retried.
+    failed: BTreeMap<ImageRequest, String>,
+    /// The longest side of the window, in pixels. Full images are shrunk down to fit it.
+    display_size: Arc<AtomicU32>,
     outgoing: ImageRequester,
     incoming: ImageDataReceiver,
 }
 
 impl ImageData {
     #[allow(dead_code)]
-    pub fn external_image_for(&self, path: PathBuf) -> ImageStatus {
+    pub fn external_image_for(&mut self, path: PathBuf) -> ImageStatus {
         let request = ImageRequest::External(path.clone());
-        if let Some(image) = self.image.get(&request).copied() {
-            ImageStatus::Available(image)
-        } else {
-            let _ = self.outgoing.send((request, path)).unwrap();
-            ImageStatus::Unavailable
-        }
+        self.request(request, Priority::Visible, || ImageSource {
+            path,
+            store: None,
+            hash: None,
+            video: false,
+        })
     }
-    pub fn image_for(&self, blob_id: BlobId, db: &DbBackend) -> ImageStatus {
+    pub fn image_for(&mut self, blob_id: BlobId, db: &DbBackend) -> ImageStatus {
         let request = ImageRequest::Image(blob_id);
-
-        self.request(request, db, blob_id)
+        self.request(request, Priority::Visible, || {
+            ImageSource::blob(db, blob_id)
+        })
     }
-    pub fn thumbnail_for(&self, blob_id: BlobId, db: &DbBackend) -> ImageStatus {
+    pub fn thumbnail_for(
+        &mut self,
+        blob_id: BlobId,
+        db: &DbBackend,
+        priority: Priority,
+    ) -> ImageStatus {
         let request = ImageRequest::Thumbnail(blob_id);
-        self.request(request, db, blob_id)
+        self.request(request, priority, || ImageSource::blob(db, blob_id))
     }
 
-    fn request(&self, request: ImageRequest, db: &DbBackend, blob_id: BlobId) -> ImageStatus {
-        if let Some(image) = self.image.get(&request).copied() {
-            ImageStatus::Available(image)
-        } else {
+    fn request(
+        &mut self,
+        request: ImageRequest,
+        priority: Priority,
+        source: impl FnOnce() -> ImageSource,
+    ) -> ImageStatus {
+        if let Some(image) = self.textures.get(&request) {
+            return ImageStatus::Available(image);
+        }
+        if let Some(reason) = self.failed.get(&request) {
+            return ImageStatus::Failed(reason.clone());
+        }
+
+        // asking again with a higher priority moves the request up the queue
+        if self
+            .pending
+            .get(&request)
+            .map_or(true, |old| priority < *old)
+        {
+            self.pending.insert(request.clone(), priority);
             let _ = self
                 .outgoing
-                .send((request, db.storage_for(blob_id)))
+                .send(LoaderMessage::Load(request.clone(), source(), priority))
                 .unwrap();
-            ImageStatus::Unavailable
         }
+        self.requested.insert(request);
+        ImageStatus::Unavailable
+    }
+
+    /// Forgets that `request` failed, so it's loaded again the next time it's asked for.
+    pub fn retry(&mut self, request: &ImageRequest) {
+        self.failed.remove(request);
     }
 
-    pub fn add_image(&mut self, request: ImageRequest, image: Image) {
-        self.image.insert(request, image);
+    pub fn set_display_size(&self, width: u32, height: u32) {
+        self.display_size
+            .store(width.max(height), Ordering::Relaxed);
     }
 
+    /// Uploads the images that finished loading, cancels requests nothing asked for this frame,
+    /// then evicts textures that weren't drawn this frame until they're back under budget.
+    /// Called once per frame.
     pub fn create_textures(
         &mut self,
         egui_rpass: &mut egui_wgpu_backend::RenderPass,
         queue: &Queue,
         device: &Device,
     ) {
-        while let Ok((request, image)) = self.incoming.try_recv() {
-            let image_texture_id = make_texture(device, queue, &image, egui_rpass);
-            self.add_image(
-                request,
-                Image {
-                    id: image_texture_id,
-                    width: image.width as u32,
-                    height: image.height as u32,
-                },
+        while let Ok((request, loaded)) = self.incoming.try_recv() {
+            if self.pending.remove(&request).is_none() {
+                continue;
+            }
+            let loaded = match loaded {
+                Ok(loaded) => loaded,
+                Err(reason) => {
+                    self.failed.insert(request, reason);
+                    continue;
+                }
+            };
+
+            let animated = loaded.len() > 1;
+            let mut bytes = 0;
+            let mut frames = Vec::new();
+            for (image, delay) in loaded {
+                let texture_id = make_texture(
+                    device,
+                    queue,
+                    &image,
+                    egui_rpass,
+                    self.textures.reusable.pop(),
+                );
+                bytes += image.data.len();
+                frames.push((
+                    Image {
+                        id: texture_id,
+                        width: image.width as u32,
+                        height: image.height as u32,
+                        original_size: image.original_size,
+                        animated,
+                    },
+                    delay,
+                ));
+            }
+            self.textures.insert(request, frames, bytes);
+        }
+
+        let requested = std::mem::take(&mut self.requested);
+        let stale = self
+            .pending
+            .keys()
+            .filter(|request| !requested.contains(request))
+            .cloned()
+            .collect::<Vec<_>>();
+        for request in stale {
+            self.pending.remove(&request);
+            let _ = self.outgoing.send(LoaderMessage::Cancel(request)).unwrap();
+        }
+
+        self.textures.evict(TEXTURE_BUDGET);
+        // the render pass can't free a texture outright, so an evicted one is swapped for a
+        // single pixel, letting the image go, and its ID is kept for the next upload
+        for texture_id in std::mem::take(&mut self.textures.evicted) {
+            make_texture(
+                device,
+                queue,
+                &RawImage::blank(),
+                egui_rpass,
+                Some(texture_id),
             );
+            self.textures.reusable.push(texture_id);
+        }
+    }
+}
+
+/// Textures on the GPU, along with when they were last drawn.
+#[derive(Default)]
+struct TextureCache {
+    entries: BTreeMap<ImageRequest, Entry>,
+    bytes: usize,
+    frame: u64,
+    /// Textures evicted since their images were last released.
+    evicted: Vec<TextureId>,
+    /// IDs of released textures, handed out again before new ones are made.
+    reusable: Vec<TextureId>,
+}
+
+struct Entry {
+    /// Every frame and how long it's shown for. Stills have just the one.
+    frames: Vec<(Image, Duration)>,
+    /// When the animation started playing.
+    started: Instant,
+    bytes: usize,
+    last_used: u64,
+}
+
+impl Entry {
+    /// The frame to show right now, looping the animation forever.
+    fn current(&self) -> Image {
+        let length = self
+            .frames
+            .iter()
+            .map(|(_, delay)| delay.as_millis())
+            .sum::<u128>();
+        if length == 0 {
+            return self.frames[0].0;
+        }
+
+        let mut at = self.started.elapsed().as_millis() % length;
+        for (image, delay) in &self.frames {
+            if at < delay.as_millis() {
+                return *image;
+            }
+            at -= delay.as_millis();
+        }
+        self.frames[0].0
+    }
+}
+
+impl TextureCache {
+    fn get(&mut self, request: &ImageRequest) -> Option<Image> {
+        let entry = self.entries.get_mut(request)?;
+        entry.last_used = self.frame;
+        Some(entry.current())
+    }
+
+    fn insert(&mut self, request: ImageRequest, frames: Vec<(Image, Duration)>, bytes: usize) {
+        self.bytes += bytes;
+        let entry = Entry {
+            frames,
+            started: Instant::now(),
+            bytes,
+            last_used: self.frame,
+        };
+        if let Some(old) = self.entries.insert(request, entry) {
+            self.bytes -= old.bytes;
+            self.evicted
+                .extend(old.frames.iter().map(|(image, _)| image.id));
+        }
+    }
+
+    /// Evicts the least recently drawn textures until `budget` is met,
+    /// leaving alone any drawn in the current frame.
+    fn evict(&mut self, budget: usize) {
+        if self.bytes > budget {
+            let mut stale = self
+                .entries
+                .iter()
+                .filter(|(_, entry)| entry.last_used < self.frame)
+                .map(|(request, entry)| (entry.last_used, request.clone()))
+                .collect::<Vec<_>>();
+            stale.sort();
+
+            for (_, request) in stale {
+                if self.bytes <= budget {
+                    break;
+                }
+                let entry = self.entries.remove(&request).unwrap();
+                self.bytes -= entry.bytes;
+                self.evicted
+                    .extend(entry.frames.iter().map(|(image, _)| image.id));
+            }
+        }
+        self.frame += 1;
+    }
+}
+
+/// Where an image is loaded from.
+#[derive(Debug, Clone)]
+pub struct ImageSource {
+    /// A key in `store` if there is one, otherwise a file on disk.
+    pub path: PathBuf,
+    pub store: Option<Arc<dyn BlobStore>>,
+    /// The content hash of a blob, used to find its cached thumbnail.
+    /// None for files outside the database.
+    pub hash: Option<u64>,
+    /// Videos are shown as their poster frame.
+    pub video: bool,
+}
+
+impl ImageSource {
+    pub fn blob(db: &DbBackend, blob_id: BlobId) -> Self {
+        Self {
+            path: db.storage_for(blob_id),
+            store: Some(db.store.clone()),
+            hash: Some(db[blob_id].hash),
+            video: db[blob_id].media_type() == MediaType::Video,
+        }
+    }
+
+    /// Whether reading the file means downloading it first.
+    fn is_remote(&self) -> bool {
+        self.store.as_ref().map_or(false, |store| !store.is_local())
+    }
+
+    /// The file on disk, fetching it from its store first if needed.
+    /// Only called from the loader's blocking threads, which are free to wait on the runtime.
+    fn local_path(&self) -> anyhow::Result<PathBuf> {
+        match &self.store {
+            Some(store) => tokio::runtime::Handle::current().block_on(store.fetch(&self.path)),
+            None => Ok(self.path.clone()),
         }
     }
 }
 
-pub type ImageRequester = mpsc::Sender<(ImageRequest, PathBuf)>;
-pub type ImageDataReceiver = mpsc::Receiver<(ImageRequest, RawImage)>;
+pub enum LoaderMessage {
+    Load(ImageRequest, ImageSource, Priority),
+    /// Drops a request that hasn't started loading yet.
+    Cancel(ImageRequest),
+}
+
+pub type ImageRequester = mpsc::Sender<LoaderMessage>;
+/// Every frame of an image, along with how long each is shown for.
+pub type RawFrames = Vec<(RawImage, Duration)>;
+pub type ImageDataReceiver = mpsc::Receiver<(ImageRequest, Result<RawFrames, String>)>;
 
 pub struct TextureLoadingTask {
-    incoming: mpsc::Receiver<(ImageRequest, PathBuf)>,
-    outgoing: mpsc::Sender<(ImageRequest, RawImage)>,
+    incoming: mpsc::Receiver<LoaderMessage>,
+    queue: Arc<(Mutex<LoadQueue>, Condvar)>,
 }
 
 impl TextureLoadingTask {
@@ -94,60 +362,220 @@ impl TextureLoadingTask {
 
         let handle = TextureLoadingTask {
             incoming: recv_request,
-            outgoing: send_image,
+            queue: Default::default(),
         };
 
-        tokio::task::spawn_blocking(move || {
-            let mut load_requested = BTreeSet::new();
-            while let Ok((request, path)) = handle.incoming.recv() {
-                if !load_requested.contains(&request) {
-                    load_requested.insert(request.clone());
-
-                    let send_image = handle.outgoing.clone();
-
-                    tokio::spawn(async move {
-                        let image_data = tokio::fs::read(&path).await?;
+        let display_size = Arc::new(AtomicU32::new(DEFAULT_DISPLAY_SIZE));
 
-                        use image::GenericImageView as _;
-                        let image = image::load_from_memory(&image_data)?;
-
-                        let image = match &request {
-                            ImageRequest::Thumbnail(_) => image.thumbnail(256, 256),
-                            ImageRequest::Image(_) | ImageRequest::External(_) => image,
-                        };
-
-                        let image = RawImage {
-                            width: image.width(),
-                            height: image.height(),
-                            data: image.to_rgba8().to_vec(),
-                        };
-
-                        send_image.send((request, image))?;
+        for _ in 0..WORKERS {
+            let queue = handle.queue.clone();
+            let send_image = send_image.clone();
+            let display_size = display_size.clone();
+            tokio::task::spawn_blocking(move || {
+                let cache = ThumbnailCache::default();
+                while let Some((request, source)) = next_job(&queue) {
+                    let display_size = display_size.load(Ordering::Relaxed);
+                    let loaded = load(&request, &source, &cache, display_size)
+                        .map_err(|err| err.to_string());
+                    if send_image.send((request, loaded)).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
 
-                        anyhow::Result::<()>::Ok(())
-                    });
+        tokio::task::spawn_blocking(move || {
+            let (jobs, ready) = &*handle.queue;
+            while let Ok(message) = handle.incoming.recv() {
+                let mut jobs = jobs.lock().unwrap();
+                match message {
+                    LoaderMessage::Load(request, source, priority) => {
+                        jobs.push(request, source, priority);
+                        ready.notify_one();
+                    }
+                    LoaderMessage::Cancel(request) => jobs.remove(&request),
                 }
             }
+            jobs.lock().unwrap().closed = true;
+            ready.notify_all();
         });
+
         ImageData {
-            image: Default::default(),
+            textures: TextureCache::default(),
+            pending: BTreeMap::new(),
+            requested: BTreeSet::new(),
+            failed: BTreeMap::new(),
+            display_size,
             outgoing: send_request,
             incoming: recv_image,
         }
     }
 }
 
-#[derive(Copy, Clone, Debug)]
+/// Requests waiting for a worker, most urgent first, then oldest first.
+#[derive(Default)]
+struct LoadQueue {
+    jobs: BTreeMap<(Priority, u64), (ImageRequest, ImageSource)>,
+    keys: BTreeMap<ImageRequest, (Priority, u64)>,
+    next: u64,
+    /// Set once nothing more will be requested, so the workers can stop.
+    closed: bool,
+}
+
+impl LoadQueue {
+    /// Replaces any earlier request for the same image.
+    fn push(&mut self, request: ImageRequest, source: ImageSource, priority: Priority) {
+        self.remove(&request);
+        let key = (priority, self.next);
+        self.next += 1;
+        self.keys.insert(request.clone(), key);
+        self.jobs.insert(key, (request, source));
+    }
+
+    fn remove(&mut self, request: &ImageRequest) {
+        if let Some(key) = self.keys.remove(request) {
+            self.jobs.remove(&key);
+        }
+    }
+
+    fn pop(&mut self) -> Option<(ImageRequest, ImageSource)> {
+        let key = *self.jobs.keys().next()?;
+        let job = self.jobs.remove(&key)?;
+        self.keys.remove(&job.0);
+        Some(job)
+    }
+}
+
+/// Waits for the most urgent request, returning None once there won't be any more.
+fn next_job(queue: &(Mutex<LoadQueue>, Condvar)) -> Option<(ImageRequest, ImageSource)> {
+    let (jobs, ready) = queue;
+    let mut jobs = jobs.lock().unwrap();
+    loop {
+        if let Some(job) = jobs.pop() {
+            return Some(job);
+        }
+        if jobs.closed {
+            return None;
+        }
+        jobs = ready.wait(jobs).unwrap();
+    }
+}
+
+fn load(
+    request: &ImageRequest,
+    source: &ImageSource,
+    cache: &ThumbnailCache,
+    display_size: u32,
+) -> anyhow::Result<RawFrames> {
+    let posters = Posters::default();
+    // files in remote stores aren't downloaded only to find they already have a thumbnail
+    if let (ImageRequest::Thumbnail(_), Some(hash), true) =
+        (request, source.hash, source.is_remote())
+    {
+        if let Some(thumbnail) = cache.cached(hash) {
+            return Ok(vec![(
+                RawImage::with_mipmaps(thumbnail.to_rgba8()),
+                Duration::ZERO,
+            )]);
+        }
+    }
+    let path = match (source.video, source.hash) {
+        (true, Some(hash)) if posters.path_for(hash).exists() => posters.path_for(hash),
+        (true, Some(hash)) => posters.get(&source.local_path()?, hash, &Ffmpeg)?,
+        _ => source.local_path()?,
+    };
+
+    let thumbnail = match (request, source.hash) {
+        (ImageRequest::Thumbnail(_), Some(hash)) => {
+            cache.load(&path, hash, decoders::decode_still)?
+        }
+        (ImageRequest::Thumbnail(_), None) => {
+            decoders::decode_still(&path)?.thumbnail(THUMBNAIL_SIZE, THUMBNAIL_SIZE)
+        }
+        (ImageRequest::Image(_) | ImageRequest::External(_), _) => {
+            return Ok(decoders::decode(&path)?
+                .into_iter()
+                .map(|frame| {
+                    let (width, height) = frame.image.dimensions();
+                    // there's no point keeping more pixels than the window can show
+                    let image = if width.max(height) > display_size {
+                        DynamicImage::ImageRgba8(frame.image)
+                            .resize(display_size, display_size, FilterType::Triangle)
+                            .to_rgba8()
+                    } else {
+                        frame.image
+                    };
+                    let image = RawImage {
+                        original_size: [width, height],
+                        ..RawImage::with_mipmaps(image)
+                    };
+                    (image, frame.delay)
+                })
+                .collect());
+        }
+    };
+
+    Ok(vec![(
+        RawImage::with_mipmaps(thumbnail.to_rgba8()),
+        Duration::ZERO,
+    )])
+}
+
+#[derive(Clone, Debug)]
 pub enum ImageStatus {
     Unavailable,
     Available(Image),
+    /// Loading failed, for the given reason.
+    Failed(String),
 }
 
 #[derive(Clone, Debug)]
 pub struct RawImage {
+    /// Every mip level, largest first.
     pub data: Vec<u8>,
     pub width: u32,
     pub height: u32,
+    pub mip_level_count: u32,
+    /// The size of the file's image, which may have been shrunk to fit the window.
+    pub original_size: [u32; 2],
+}
+
+impl RawImage {
+    /// A single transparent pixel.
+    fn blank() -> Self {
+        Self {
+            data: vec![0; 4],
+            width: 1,
+            height: 1,
+            mip_level_count: 1,
+            original_size: [1, 1],
+        }
+    }
+
+    /// Halves `image` down to a single pixel, so it stays smooth when drawn small.
+    fn with_mipmaps(image: RgbaImage) -> Self {
+        let (width, height) = image.dimensions();
+        let mip_level_count = 32 - width.max(height).max(1).leading_zeros();
+
+        let mut data = image.to_vec();
+        for level in 1..mip_level_count {
+            let mip = imageops::resize(
+                &image,
+                (width >> level).max(1),
+                (height >> level).max(1),
+                FilterType::Triangle,
+            );
+            data.extend_from_slice(&mip);
+        }
+
+        Self {
+            data,
+            width,
+            height,
+            mip_level_count,
+            original_size: [width, height],
+        }
+    }
 }
 
 #[derive(Clone, Copy, Debug)]
@@ -155,6 +583,10 @@ pub struct Image {
     pub id: TextureId,
     pub width: u32,
     pub height: u32,
+    /// The size of the file's image, which the texture may be smaller than.
+    pub original_size: [u32; 2],
+    /// Whether this is a frame of an animation, so it needs redrawing to play.
+    pub animated: bool,
 }
 
 impl Image {
@@ -164,10 +596,14 @@ impl Image {
 
         [height * aspect_ratio, height]
     }
+    pub fn size(&self) -> Vec2 {
+        Vec2::new(self.width as f32, self.height as f32)
+    }
+    pub fn original_size(&self) -> Vec2 {
+        Vec2::new(self.original_size[0] as f32, self.original_size[1] as f32)
+    }
     pub fn scaled(&self, max_size: [f32; 2]) -> [f32; 2] {
-        let zoom = 1.0;
-
-        let size = [self.width as f32 * zoom, self.height as f32 * zoom];
+        let size = [self.width as f32, self.height as f32];
         let aspect_ratio = size[0] / size[1];
         let new_aspect_ratio = max_size[0] / max_size[1];
 
@@ -185,11 +621,13 @@ impl Image {
     }
 }
 
+/// Uploads `image`, in place of the texture `reuse` if given.
 fn make_texture(
     device: &Device,
     queue: &Queue,
     image: &RawImage,
     egui_rpass: &mut egui_wgpu_backend::RenderPass,
+    reuse: Option<TextureId>,
 ) -> TextureId {
     let texture = device.create_texture_with_data(
         queue,
@@ -200,7 +638,7 @@ fn make_texture(
                 height: image.height,
                 depth_or_array_layers: 1,
             },
-            mip_level_count: 1,
+            mip_level_count: image.mip_level_count,
             sample_count: 1,
             dimension: wgpu::TextureDimension::D2,
             format: wgpu::TextureFormat::Rgba8UnormSrgb,
@@ -209,5 +647,20 @@ fn make_texture(
         &image.data,
     );
 
-    egui_rpass.egui_texture_from_wgpu_texture(device, &texture, wgpu::FilterMode::Linear)
+    match reuse {
+        Some(texture_id) => {
+            egui_rpass
+                .update_egui_texture_from_wgpu_texture(
+                    device,
+                    &texture,
+                    wgpu::FilterMode::Linear,
+                    texture_id,
+                )
+                .unwrap();
+            texture_id
+        }
+        None => {
+            egui_rpass.egui_texture_from_wgpu_texture(device, &texture, wgpu::FilterMode::Linear)
+        }
+    }
 }
@@ -0,0 +1,467 @@
+use std::{
+    io::{Cursor, Read},
+    path::{Path, PathBuf},
+    process::Command,
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+
+use anyhow::{anyhow, bail, Context};
+use image::{
+    codecs::{gif::GifDecoder, png::PngDecoder},
+    AnimationDecoder, DynamicImage, RgbaImage,
+};
+use lazy_static::lazy_static;
+
+/// Shown in place of files no decoder understands.
+const UNSUPPORTED: &[u8] = include_bytes!("../../../unsupported.png");
+/// Frames shorter than this are shown for [`DEFAULT_DELAY`] instead, the same as browsers do.
+const MIN_DELAY: Duration = Duration::from_millis(20);
+const DEFAULT_DELAY: Duration = Duration::from_millis(100);
+
+lazy_static! {
+    /// Tried in order, so more specific decoders come first.
+    static ref DECODERS: Vec<Box<dyn Decoder>> = vec![
+        Box::new(Animated),
+        Box::new(Psd),
+        Box::new(Archived),
+        Box::new(Clip),
+        Box::new(External::AVIF),
+        Box::new(External::JXL),
+        Box::new(Builtin),
+    ];
+}
+
+/// One frame of an image, along with how long it's shown for. Stills are a single frame.
+pub struct Frame {
+    pub image: RgbaImage,
+    pub delay: Duration,
+}
+
+impl Frame {
+    fn still(image: DynamicImage) -> Self {
+        Self {
+            image: image.to_rgba8(),
+            delay: Duration::ZERO,
+        }
+    }
+}
+
+/// Reads image files of some particular formats.
+pub trait Decoder: Send + Sync {
+    /// Whether this decodes the file starting with `header`.
+    fn decodes(&self, header: &[u8]) -> bool;
+
+    /// Decodes every frame of `bytes`, the contents of the file at `path`.
+    fn decode(&self, path: &Path, bytes: &[u8]) -> anyhow::Result<Vec<Frame>>;
+
+    /// Decodes just the frame used for thumbnails.
+    fn decode_first(&self, path: &Path, bytes: &[u8]) -> anyhow::Result<RgbaImage> {
+        self.decode(path, bytes)?
+            .into_iter()
+            .next()
+            .map(|frame| frame.image)
+            .ok_or_else(|| anyhow!("The file has no frames."))
+    }
+}
+
+fn decoder_for(bytes: &[u8]) -> Option<&'static dyn Decoder> {
+    DECODERS
+        .iter()
+        .find(|decoder| decoder.decodes(bytes))
+        .map(|decoder| decoder.as_ref())
+}
+
+/// Decodes every frame of the file at `path`, or the unsupported tile if nothing can.
+pub fn decode(path: &Path) -> anyhow::Result<Vec<Frame>> {
+    let bytes = std::fs::read(path)?;
+    match decoder_for(&bytes) {
+        Some(decoder) => decoder.decode(path, &bytes),
+        None => Ok(vec![Frame::still(unsupported())]),
+    }
+}
+
+/// Decodes the first frame of the file at `path`, or the unsupported tile if nothing can.
+pub fn decode_still(path: &Path) -> anyhow::Result<DynamicImage> {
+    let bytes = std::fs::read(path)?;
+    match decoder_for(&bytes) {
+        Some(decoder) => Ok(DynamicImage::ImageRgba8(
+            decoder.decode_first(path, &bytes)?,
+        )),
+        None => Ok(unsupported()),
+    }
+}
+
+/// The `length` bytes at `offset`, or None if they run past the end.
+fn span(bytes: &[u8], offset: usize, length: usize) -> Option<&[u8]> {
+    bytes.get(offset..offset.checked_add(length)?)
+}
+
+fn unsupported() -> DynamicImage {
+    image::load_from_memory(UNSUPPORTED).unwrap()
+}
+
+/// Anything the image crate reads on its own.
+struct Builtin;
+
+impl Decoder for Builtin {
+    fn decodes(&self, header: &[u8]) -> bool {
+        image::guess_format(header).is_ok()
+    }
+
+    fn decode(&self, _: &Path, bytes: &[u8]) -> anyhow::Result<Vec<Frame>> {
+        Ok(vec![Frame::still(image::load_from_memory(bytes)?)])
+    }
+}
+
+/// Animated GIF, PNG and WebP files.
+struct Animated;
+
+impl Animated {
+    fn is_gif(header: &[u8]) -> bool {
+        header.starts_with(b"GIF8")
+    }
+
+    /// APNGs have an animation control chunk before their first image data chunk.
+    fn is_apng(header: &[u8]) -> bool {
+        if !header.starts_with(b"\x89PNG\r\n\x1a\n") {
+            return false;
+        }
+        let mut offset = 8;
+        while let Some(chunk) = span(header, offset, 8) {
+            let length = u32::from_be_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]) as usize;
+            match &chunk[4..8] {
+                b"acTL" => return true,
+                b"IDAT" => return false,
+                _ => offset = offset.saturating_add(12).saturating_add(length),
+            }
+        }
+        false
+    }
+
+    /// Extended WebPs flag whether they're animated in their header.
+    fn is_animated_webp(header: &[u8]) -> bool {
+        header.len() > 20
+            && &header[0..4] == b"RIFF"
+            && &header[8..16] == b"WEBPVP8X"
+            && header[20] & 0b10 != 0
+    }
+
+    fn webp_frames(bytes: &[u8]) -> anyhow::Result<Vec<Frame>> {
+        let decoder = webp_animation::Decoder::new(bytes).map_err(|err| anyhow!("{:?}", err))?;
+        // timestamps are when each frame ends
+        let mut shown = 0;
+        let mut frames = Vec::new();
+        for frame in decoder.into_iter() {
+            let (width, height) = frame.dimensions();
+            let image = RgbaImage::from_raw(width, height, frame.data().to_vec())
+                .context("The frame is the wrong size.")?;
+            let timestamp = frame.timestamp().max(shown);
+            frames.push(Frame {
+                image,
+                delay: Duration::from_millis((timestamp - shown) as u64),
+            });
+            shown = timestamp;
+        }
+        Ok(frames)
+    }
+
+    fn frames<'a>(decoder: impl AnimationDecoder<'a>) -> anyhow::Result<Vec<Frame>> {
+        decoder
+            .into_frames()
+            .map(|frame| {
+                let frame = frame?;
+                let (numerator, denominator) = frame.delay().numer_denom_ms();
+                Ok(Frame {
+                    delay: Duration::from_millis((numerator / denominator.max(1)) as u64),
+                    image: frame.into_buffer(),
+                })
+            })
+            .collect()
+    }
+}
+
+impl Decoder for Animated {
+    fn decodes(&self, header: &[u8]) -> bool {
+        Self::is_gif(header) || Self::is_apng(header) || Self::is_animated_webp(header)
+    }
+
+    fn decode(&self, _: &Path, bytes: &[u8]) -> anyhow::Result<Vec<Frame>> {
+        let mut frames = if Self::is_gif(bytes) {
+            Self::frames(GifDecoder::new(Cursor::new(bytes))?)?
+        } else if Self::is_apng(bytes) {
+            Self::frames(PngDecoder::new(Cursor::new(bytes))?.apng())?
+        } else {
+            Self::webp_frames(bytes)?
+        };
+        for frame in &mut frames {
+            if frame.delay < MIN_DELAY {
+                frame.delay = DEFAULT_DELAY;
+            }
+        }
+        Ok(frames)
+    }
+
+    fn decode_first(&self, path: &Path, bytes: &[u8]) -> anyhow::Result<RgbaImage> {
+        if Self::is_gif(bytes) || Self::is_apng(bytes) {
+            // the image crate reads the first frame on its own, without the rest
+            Ok(image::load_from_memory(bytes)?.to_rgba8())
+        } else {
+            self.decode(path, bytes)?
+                .into_iter()
+                .next()
+                .map(|frame| frame.image)
+                .ok_or_else(|| anyhow!("The file has no frames."))
+        }
+    }
+}
+
+/// Photoshop files, shown as the composite saved alongside the layers.
+struct Psd;
+
+impl Decoder for Psd {
+    fn decodes(&self, header: &[u8]) -> bool {
+        header.starts_with(b"8BPS")
+    }
+
+    fn decode(&self, _: &Path, bytes: &[u8]) -> anyhow::Result<Vec<Frame>> {
+        let psd = psd::Psd::from_bytes(bytes).map_err(|err| anyhow!("{}", err))?;
+        let image = RgbaImage::from_raw(psd.width(), psd.height(), psd.rgba())
+            .context("The composite is the wrong size.")?;
+        Ok(vec![Frame::still(DynamicImage::ImageRgba8(image))])
+    }
+}
+
+/// Krita and OpenRaster files, which are zip archives with a flattened copy of the image inside.
+struct Archived;
+
+impl Archived {
+    const MIME_TYPES: &'static [&'static [u8]] = &[b"application/x-krita", b"image/openraster"];
+}
+
+impl Decoder for Archived {
+    /// Both formats start with an uncompressed "mimetype" file.
+    fn decodes(&self, header: &[u8]) -> bool {
+        header.starts_with(b"PK\x03\x04")
+            && header.get(30..38) == Some(&b"mimetype"[..])
+            && header.get(38..).map_or(false, |rest| {
+                Self::MIME_TYPES
+                    .iter()
+                    .any(|mime_type| rest.starts_with(mime_type))
+            })
+    }
+
+    fn decode(&self, _: &Path, bytes: &[u8]) -> anyhow::Result<Vec<Frame>> {
+        let mut archive = zip::ZipArchive::new(Cursor::new(bytes))?;
+        let mut merged = Vec::new();
+        archive
+            .by_name("mergedimage.png")?
+            .read_to_end(&mut merged)?;
+        Ok(vec![Frame::still(image::load_from_memory(&merged)?)])
+    }
+}
+
+/// Clip Studio Paint files, which keep a preview of the canvas in an embedded SQLite database.
+struct Clip;
+
+impl Clip {
+    /// Finds the contents of the chunk called `name`.
+    fn chunk<'a>(bytes: &'a [u8], name: &[u8]) -> anyhow::Result<&'a [u8]> {
+        let read_u64 = |offset: usize| -> anyhow::Result<usize> {
+            let mut be = [0; 8];
+            be.copy_from_slice(span(bytes, offset, 8).context("The file is truncated.")?);
+            Ok(u64::from_be_bytes(be) as usize)
+        };
+
+        // after the signature are the file's size and where its first chunk starts
+        let mut offset = read_u64(16)?;
+        loop {
+            let chunk_name = span(bytes, offset, 8).context("The file is truncated.")?;
+            let length = read_u64(offset + 8)?;
+            let contents = span(bytes, offset + 16, length).context("The file is truncated.")?;
+            if chunk_name == name {
+                return Ok(contents);
+            }
+            // always moves forward, even past empty chunks
+            offset += 16 + length;
+        }
+    }
+}
+
+impl Decoder for Clip {
+    fn decodes(&self, header: &[u8]) -> bool {
+        header.starts_with(b"CSFCHUNK")
+    }
+
+    fn decode(&self, _: &Path, bytes: &[u8]) -> anyhow::Result<Vec<Frame>> {
+        let database = TempFile::new("sqlite");
+        std::fs::write(&database.0, Self::chunk(bytes, b"CHNKSQLi")?)?;
+
+        let connection = rusqlite::Connection::open_with_flags(
+            &database.0,
+            rusqlite::OpenFlags::SQLITE_OPEN_READ_ONLY,
+        )?;
+        let preview: Vec<u8> =
+            connection.query_row("SELECT ImageData FROM CanvasPreview", [], |row| row.get(0))?;
+        Ok(vec![Frame::still(image::load_from_memory(&preview)?)])
+    }
+}
+
+/// Formats decoded by running their reference decoder, which needs to be installed separately.
+struct External {
+    program: &'static str,
+    signatures: &'static [(usize, &'static [u8])],
+}
+
+impl External {
+    const AVIF: Self = Self {
+        program: "avifdec",
+        signatures: &[(4, b"ftypavif"), (4, b"ftypavis")],
+    };
+    const JXL: Self = Self {
+        program: "djxl",
+        signatures: &[(0, b"\xff\x0a"), (0, b"\0\0\0\x0cJXL \r\n\x87\n")],
+    };
+}
+
+impl Decoder for External {
+    fn decodes(&self, header: &[u8]) -> bool {
+        self.signatures.iter().any(|(offset, signature)| {
+            header.get(*offset..offset + signature.len()) == Some(*signature)
+        })
+    }
+
+    fn decode(&self, path: &Path, _: &[u8]) -> anyhow::Result<Vec<Frame>> {
+        let decoded = TempFile::new("png");
+        let output = Command::new(self.program)
+            .arg(path)
+            .arg(&decoded.0)
+            .output()
+            .with_context(|| format!("Couldn't run {}, is it installed?", self.program))?;
+        if !output.status.success() {
+            bail!(
+                "{} failed: {}",
+                self.program,
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+        Ok(vec![Frame::still(image::open(&decoded.0)?)])
+    }
+}
+
+/// A uniquely named file in the temp directory, deleted when dropped.
+struct TempFile(PathBuf);
+
+impl TempFile {
+    fn new(extension: &str) -> Self {
+        static NEXT: AtomicU64 = AtomicU64::new(0);
+        Self(std::env::temp_dir().join(format!(
+            "art-organize-{}-{}.{}",
+            std::process::id(),
+            NEXT.fetch_add(1, Ordering::Relaxed),
+            extension
+        )))
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// A Clip Studio file holding `chunks`, each as its name and contents.
+    fn clip(chunks: &[(&[u8; 8], &[u8])]) -> Vec<u8> {
+        let mut bytes = b"CSFCHUNK".to_vec();
+        bytes.extend(0u64.to_be_bytes());
+        bytes.extend(24u64.to_be_bytes());
+        for (name, contents) in chunks {
+            bytes.extend(*name);
+            bytes.extend((contents.len() as u64).to_be_bytes());
+            bytes.extend(*contents);
+        }
+        bytes
+    }
+
+    /// A PNG with a chunk of each name in `chunks`, all empty.
+    fn png(chunks: &[&[u8; 4]]) -> Vec<u8> {
+        let mut bytes = b"\x89PNG\r\n\x1a\n".to_vec();
+        for name in chunks {
+            bytes.extend(0u32.to_be_bytes());
+            bytes.extend(*name);
+            bytes.extend([0; 4]);
+        }
+        bytes
+    }
+
+    #[test]
+    fn test_clip_chunk() {
+        let bytes = clip(&[(b"CHNKHead", b""), (b"CHNKSQLi", b"database")]);
+        assert!(Clip.decodes(&bytes));
+        assert_eq!(Clip::chunk(&bytes, b"CHNKSQLi").unwrap(), b"database");
+        assert_eq!(Clip::chunk(&bytes, b"CHNKHead").unwrap(), b"");
+        // empty chunks are stepped over rather than read forever
+        assert!(Clip::chunk(&bytes, b"CHNKExta").is_err());
+    }
+
+    #[test]
+    fn test_clip_malformed() {
+        let bytes = clip(&[(b"CHNKSQLi", b"database")]);
+        for end in 0..bytes.len() {
+            assert!(Clip::chunk(&bytes[..end], b"CHNKSQLi").is_err());
+        }
+
+        // lengths that would wrap around
+        let mut huge = clip(&[(b"CHNKHead", b"")]);
+        huge[32..40].copy_from_slice(&u64::MAX.to_be_bytes());
+        assert!(Clip::chunk(&huge, b"CHNKSQLi").is_err());
+        let mut first = clip(&[]);
+        first[16..24].copy_from_slice(&u64::MAX.to_be_bytes());
+        assert!(Clip::chunk(&first, b"CHNKSQLi").is_err());
+    }
+
+    #[test]
+    fn test_apng() {
+        assert!(Animated::is_apng(&png(&[b"IHDR", b"acTL", b"IDAT"])));
+        assert!(!Animated::is_apng(&png(&[b"IHDR", b"IDAT", b"acTL"])));
+        assert!(!Animated::is_apng(&png(&[b"IHDR"])));
+        assert!(!Animated::is_apng(b"\x89PNG"));
+
+        let mut huge = png(&[b"IHDR", b"acTL"]);
+        huge[8..12].copy_from_slice(&u32::MAX.to_be_bytes());
+        assert!(!Animated::is_apng(&huge));
+    }
+
+    #[test]
+    fn test_sniffers() {
+        assert!(Animated.decodes(b"GIF89a"));
+        let mut webp = b"RIFF\0\0\0\0WEBPVP8X\0\0\0\0".to_vec();
+        assert!(!Animated.decodes(&webp));
+        webp.push(0b10);
+        assert!(Animated.decodes(&webp));
+        webp[20] = 0;
+        assert!(!Animated.decodes(&webp));
+
+        assert!(Psd.decodes(b"8BPS\0\x01"));
+
+        let mut krita = b"PK\x03\x04".to_vec();
+        krita.resize(30, 0);
+        krita.extend(b"mimetype");
+        assert!(!Archived.decodes(&krita));
+        krita.extend(b"application/x-krita");
+        assert!(Archived.decodes(&krita));
+        assert!(!Archived.decodes(b"PK\x03\x04"));
+
+        assert!(External::AVIF.decodes(b"\0\0\0\x1cftypavif"));
+        assert!(!External::AVIF.decodes(b"\0\0\0\x1cftyp"));
+        assert!(External::JXL.decodes(b"\xff\x0a"));
+        assert!(External::JXL.decodes(b"\0\0\0\x0cJXL \r\n\x87\n"));
+        assert!(!External::JXL.decodes(b"\0\0\0\x0cJXL"));
+
+        assert!(decoder_for(b"").is_none());
+    }
+}
@@ -0,0 +1,129 @@
+use egui::{pos2, Color32, Id, Mesh, Rect, Response, Sense, Shape, Ui, Vec2};
+
+use crate::{frontend::texture_storage::Image, ui_memory::MemoryExt};
+
+/// How much one step of the mouse wheel zooms by.
+const ZOOM_SPEED: f32 = 0.002;
+const MIN_SCALE: f32 = 0.05;
+const MAX_SCALE: f32 = 32.0;
+
+/// How an image is zoomed and panned.
+#[derive(Debug, Clone, Copy, Default)]
+struct Zoom {
+    /// Screen pixels per pixel of the original image, or None to fit the image to the view.
+    scale: Option<f32>,
+    /// How far the image's center is from the view's center.
+    offset: Vec2,
+}
+
+impl Zoom {
+    const ACTUAL_SIZE: Self = Self {
+        scale: Some(1.0),
+        offset: Vec2::ZERO,
+    };
+
+    fn scale_for(&self, image: Vec2, view: Vec2) -> f32 {
+        self.scale
+            .unwrap_or_else(|| (view.x / image.x).min(view.y / image.y).min(1.0))
+    }
+}
+
+/// How a second image is shown alongside the first.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Compare {
+    /// Next to each other, zoomed and panned together.
+    SideBySide,
+    /// Drawn over the first, at this opacity.
+    Onion(f32),
+}
+
+/// Shows `image`, and `other` compared with it if given, zoomed with the mouse wheel around
+/// the cursor and panned by dragging. Double clicking switches between fitting the view and 1:1.
+pub fn show(ui: &mut Ui, id: Id, image: Image, other: Option<(Image, Compare)>) -> Response {
+    if image.animated || other.map_or(false, |(other, _)| other.animated) {
+        ui.ctx().request_repaint();
+    }
+
+    ui.with_memory(id, Zoom::default, |ui, zoom| {
+        ui.horizontal(|ui| {
+            if ui.selectable_label(zoom.scale.is_none(), "Fit").clicked() {
+                *zoom = Zoom::default();
+            }
+            if ui
+                .selectable_label(zoom.scale == Some(1.0), "1:1")
+                .clicked()
+            {
+                *zoom = Zoom::ACTUAL_SIZE;
+            }
+            if let Some(scale) = zoom.scale {
+                ui.label(format!("{:.0}%", scale * 100.0));
+            }
+        });
+
+        let (rect, response) = ui.allocate_exact_size(ui.available_size(), Sense::click_and_drag());
+        let panes = match other {
+            Some((_, Compare::SideBySide)) => {
+                let middle = rect.center().x;
+                vec![
+                    Rect::from_min_max(rect.min, pos2(middle, rect.max.y)),
+                    Rect::from_min_max(pos2(middle, rect.min.y), rect.max),
+                ]
+            }
+            _ => vec![rect],
+        };
+        // textures can be shrunk to fit the window, so sizes go by the original image
+        let size = image.original_size();
+        let scale = zoom.scale_for(size, panes[0].size());
+
+        if response.double_clicked() {
+            *zoom = if zoom.scale.is_none() {
+                Zoom::ACTUAL_SIZE
+            } else {
+                Zoom::default()
+            };
+        } else if response.dragged() {
+            zoom.scale = Some(scale);
+            zoom.offset += response.drag_delta();
+        }
+
+        let scroll = ui.input().scroll_delta.y;
+        let pointer = ui.input().pointer.hover_pos();
+        if let (true, Some(pointer)) = (response.hovered() && scroll != 0.0, pointer) {
+            let pane = panes
+                .iter()
+                .find(|pane| pane.contains(pointer))
+                .unwrap_or(&panes[0]);
+            let new_scale = (scale * (scroll * ZOOM_SPEED).exp()).clamp(MIN_SCALE, MAX_SCALE);
+            // keeps the pixel under the cursor where it is
+            let from_center = pointer - pane.center();
+            zoom.offset = from_center - (from_center - zoom.offset) * (new_scale / scale);
+            zoom.scale = Some(new_scale);
+        }
+
+        let scale = zoom.scale_for(size, panes[0].size());
+        let paint = |pane: Rect, image: Image, tint: Color32| {
+            // other images are drawn as wide as the first, so drafts line up with the canon
+            let shown = image.original_size() * (size.x / image.original_size().x) * scale;
+            let mut mesh = Mesh::with_texture(image.id);
+            mesh.add_rect_with_uv(
+                Rect::from_center_size(pane.center() + zoom.offset, shown),
+                Rect::from_min_max(pos2(0.0, 0.0), pos2(1.0, 1.0)),
+                tint,
+            );
+            ui.painter_at(pane).add(Shape::Mesh(mesh));
+        };
+
+        paint(panes[0], image, Color32::WHITE);
+        match other {
+            Some((other, Compare::SideBySide)) => paint(panes[1], other, Color32::WHITE),
+            Some((other, Compare::Onion(opacity))) => paint(
+                panes[0],
+                other,
+                Color32::from_white_alpha((opacity.clamp(0.0, 1.0) * 255.0) as u8),
+            ),
+            None => {}
+        }
+
+        response
+    })
+}
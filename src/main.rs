@@ -1,15 +1,31 @@
 // #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 #![feature(let_chains)]
 
-use anyhow::bail;
-use backend::DbBackend;
+use anyhow::{anyhow, bail};
+use backend::{
+    export::Export,
+    journal,
+    media::{Ffmpeg, Posters},
+    session::lock_file,
+    store::{store_file, LocalStore},
+    thumbnails::{self, ThumbnailCache},
+    DbBackend,
+};
 use clap::Clap;
 use cli::SubCommand;
 use config::Config;
+use db::MediaType;
+use frontend::texture_storage::decoders;
+use futures_util::{stream::FuturesUnordered, StreamExt};
 use rfd::{AsyncFileDialog, AsyncMessageDialog, MessageButtons, MessageLevel};
+use search::model::Query;
 use serde::{Deserialize, Serialize};
-use std::{path::PathBuf, time::Duration};
-use tokio::runtime::Builder;
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+use tokio::{runtime::Builder, sync::Semaphore};
 
 mod backend;
 mod cli;
@@ -80,6 +96,163 @@ async fn async_main() -> anyhow::Result<()> {
             SubCommand::Update => {
                 tokio::task::spawn_blocking(updater::update_app).await??;
             }
+            SubCommand::ExportSaved { name, dest, strict } => {
+                let db = open_db(&config).await?;
+
+                let saved_id = db
+                    .find_saved_search(&name)
+                    .ok_or_else(|| anyhow!("No search is saved as \"{}\".", name))?;
+                let query = match search::parse_query(db[saved_id].query.trim()) {
+                    Ok(("", query)) => query,
+                    _ => bail!("The search saved as \"{}\" is invalid.", name),
+                };
+                let pieces = Query { strict, ..query }.execute(&db, &db.index)?;
+
+                let blobs = pieces
+                    .into_iter()
+                    .flat_map(|piece_id| db.blobs_for_piece(piece_id));
+                Export::new(&db, blobs).to_folder(&dest).await?;
+            }
+            SubCommand::ExportCollection {
+                name,
+                dest,
+                archive,
+            } => {
+                let db = open_db(&config).await?;
+
+                let (collection_id, _) = db
+                    .collections()
+                    .find(|(_, collection)| collection.name == name)
+                    .ok_or_else(|| anyhow!("No collection is named \"{}\".", name))?;
+
+                let export = Export::new(&db, db.blobs_for_collection(collection_id));
+                if archive {
+                    export.to_archive(dest).await?;
+                } else {
+                    export.to_folder(&dest).await?;
+                }
+            }
+            SubCommand::Thumbnails => {
+                let db = open_db(&config).await?;
+
+                let cache = ThumbnailCache::default();
+                let workers = Arc::new(Semaphore::new(thumbnails::WORKERS));
+                let mut made = db
+                    .blobs()
+                    .filter(|(_, blob)| {
+                        matches!(blob.media_type(), MediaType::Image | MediaType::Video)
+                    })
+                    .map(|(blob_id, blob)| {
+                        let video = blob.media_type() == MediaType::Video;
+                        (db.storage_for(blob_id), blob.hash, video)
+                    })
+                    .filter(|(key, hash, _)| {
+                        // files in remote stores aren't downloaded to check their thumbnail's age
+                        let cached = cache.contains(&db.store.local_path(key), *hash)
+                            || (!db.store.is_local() && cache.has(*hash));
+                        !cached
+                    })
+                    .map(|(key, hash, video)| {
+                        let (cache, workers) = (cache.clone(), workers.clone());
+                        let store = db.store.clone();
+                        async move {
+                            let _permit = workers.acquire().await?;
+                            let source = match store.fetch(&key).await {
+                                Ok(source) => source,
+                                Err(err) => return anyhow::Result::<_>::Ok(Err((key, err))),
+                            };
+                            let made = tokio::task::spawn_blocking(move || {
+                                // videos get the thumbnail of their poster frame
+                                let decode = |path: &Path| {
+                                    if video {
+                                        let poster = Posters::default().get(path, hash, &Ffmpeg)?;
+                                        decoders::decode_still(&poster)
+                                    } else {
+                                        decoders::decode_still(path)
+                                    }
+                                };
+                                cache
+                                    .load(&source, hash, decode)
+                                    .map_err(|err| (source, err))
+                            })
+                            .await?;
+                            anyhow::Result::<_>::Ok(made)
+                        }
+                    })
+                    .collect::<FuturesUnordered<_>>();
+
+                let (mut count, mut failed) = (0, 0);
+                while let Some(result) = made.next().await {
+                    match result? {
+                        Ok(_) => count += 1,
+                        Err((source, err)) => {
+                            eprintln!(
+                                "Couldn't make a thumbnail for {}: {}",
+                                source.display(),
+                                err
+                            );
+                            failed += 1;
+                        }
+                    }
+                }
+                println!("Made {} thumbnails, {} failed.", count, failed);
+            }
+            SubCommand::MigrateStorage { layout } => {
+                let mut db = open_db_for_editing(&config).await?;
+
+                // saved even after a failure, since the files moved so far have to stay tracked
+                let migrated = db.migrate_storage(layout).await;
+                db.save()?;
+                let (moved, missing) = migrated?;
+                println!("Moved {} files to the {} layout.", moved, layout);
+                if missing > 0 {
+                    println!(
+                        "{} blobs were missing their files and were left alone.",
+                        missing
+                    );
+                }
+            }
+            SubCommand::UploadBlobs => {
+                let db = open_db(&config).await?;
+                let root = db.root.clone();
+                if db.store.is_local() {
+                    bail!(
+                        "{} doesn't set up a store to upload to.",
+                        store_file(&root).display()
+                    );
+                }
+
+                let (copied, missing) = db.copy_files_from(&LocalStore::new(root.clone())).await?;
+                println!("Uploaded {} files.", copied);
+                if missing > 0 {
+                    println!(
+                        "{} blobs had no file in {} and were left alone.",
+                        missing,
+                        root.display()
+                    );
+                }
+            }
+            SubCommand::History => {
+                for entry in journal::history(&default_root(&config)?)? {
+                    let at = entry.at.format("%Y-%m-%d %H:%M:%S");
+                    match entry.changes.len() {
+                        0 => println!("{}  {}  compacted", at, entry.by),
+                        1 => println!("{}  {}  1 change", at, entry.by),
+                        count => println!("{}  {}  {} changes", at, entry.by, count),
+                    }
+                }
+            }
+            SubCommand::Recover { at } => {
+                let at = journal::parse_time(&at)?;
+                let mut db = open_db_for_editing(&config).await?;
+
+                db.inner = journal::recover(&db.root, at)?;
+                db.save()?;
+                println!(
+                    "Recovered the database as it was at {}.",
+                    at.format("%Y-%m-%d %H:%M:%S")
+                );
+            }
         }
     } else {
         run_gui(config).await?;
@@ -88,6 +261,32 @@ async fn async_main() -> anyhow::Result<()> {
     Ok(())
 }
 
+/// The folder of the database the commands work on.
+fn default_root(config: &Config) -> anyhow::Result<PathBuf> {
+    config
+        .default_dir
+        .clone()
+        .ok_or_else(|| anyhow!("No database has been set up yet."))
+}
+
+async fn open_db(config: &Config) -> anyhow::Result<DbBackend> {
+    DbBackend::from_directory(default_root(config)?).await
+}
+
+/// Opens the database for a command that changes it, which can't wait for whoever
+/// else is editing it.
+async fn open_db_for_editing(config: &Config) -> anyhow::Result<DbBackend> {
+    let db = open_db(config).await?;
+    if let Some(holder) = db.read_only() {
+        bail!(
+            "{} is editing the database. Close it there first, or delete {} if it isn't really open.",
+            holder,
+            lock_file(&db.root).display()
+        );
+    }
+    Ok(db)
+}
+
 async fn run_gui(mut config: Config) -> anyhow::Result<()> {
     let root = match config.default_dir {
         Some(root) => root,
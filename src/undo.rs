@@ -1 +1,85 @@
+use crate::backend::DbBackend;
 
+/// How many steps can be undone before the oldest ones are forgotten.
+const LIMIT: usize = 32;
+
+/// Snapshots of the database taken around each undoable step.
+///
+/// Only some edits are recorded as steps, so a step is only undone while the
+/// database is still exactly as that step left it. Anything else would throw away
+/// the unrecorded edits made since, so the history is dropped instead.
+#[derive(Default)]
+pub struct UndoStack {
+    undo: Vec<Step>,
+    redo: Vec<Step>,
+}
+
+struct Step {
+    before: DbBackend,
+    after: DbBackend,
+}
+
+impl UndoStack {
+    /// Makes `edit` to `db` as a single step.
+    pub fn record(&mut self, db: &mut DbBackend, edit: impl FnOnce(&mut DbBackend)) {
+        // recorded on both sides, so each snapshot's search index is up to date with it
+        db.record();
+        let before = db.clone();
+        edit(db);
+        db.record();
+        if before.inner == db.inner {
+            return;
+        }
+
+        self.undo.push(Step {
+            before,
+            after: db.clone(),
+        });
+        if self.undo.len() > LIMIT {
+            self.undo.remove(0);
+        }
+        self.redo.clear();
+    }
+
+    pub fn can_undo(&self, db: &DbBackend) -> bool {
+        matches!(self.undo.last(), Some(step) if step.after.inner == db.inner)
+    }
+    pub fn can_redo(&self, db: &DbBackend) -> bool {
+        matches!(self.redo.last(), Some(step) if step.before.inner == db.inner)
+    }
+
+    /// Returns false if there was nothing that could be undone.
+    pub fn undo(&mut self, db: &mut DbBackend) -> bool {
+        if !self.can_undo(db) {
+            self.forget_stale(db);
+            return false;
+        }
+        let step = self.undo.pop().unwrap();
+        *db = step.before.clone();
+        db.edited = true;
+        self.redo.push(step);
+        true
+    }
+
+    /// Returns false if there was nothing that could be redone.
+    pub fn redo(&mut self, db: &mut DbBackend) -> bool {
+        if !self.can_redo(db) {
+            self.forget_stale(db);
+            return false;
+        }
+        let step = self.redo.pop().unwrap();
+        *db = step.after.clone();
+        db.edited = true;
+        self.undo.push(step);
+        true
+    }
+
+    fn forget_stale(&mut self, db: &DbBackend) {
+        if !self.can_undo(db) {
+            self.undo.clear();
+        }
+        if !self.can_redo(db) {
+            self.redo.clear();
+        }
+    }
+}
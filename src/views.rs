@@ -1,11 +1,18 @@
-use crate::{backend::DbBackend, frontend::Frontend};
+use crate::{
+    backend::DbBackend,
+    frontend::{import::DropTarget, Frontend},
+};
 
+pub mod blob_browser;
 pub mod edit_blob;
 pub mod edit_category;
 pub mod edit_piece;
 pub mod edit_tag;
 pub mod gallery;
+pub mod review;
+pub mod slideshow;
 pub mod view_blob;
+pub mod view_collection;
 pub mod view_piece;
 pub mod view_tag;
 
@@ -46,4 +53,8 @@ pub trait View: Send + Sync {
 
     fn center_panel(&mut self, ui: &mut egui::Ui, frontend: &mut Frontend, db: &mut DbBackend);
     fn side_panels(&mut self, _: &egui::CtxRef, _: &mut Frontend, _: &mut DbBackend) {}
+    /// What files dropped onto the window while this view is open are added to, if anything.
+    fn drop_target(&self) -> Option<DropTarget> {
+        None
+    }
 }
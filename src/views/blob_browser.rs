@@ -0,0 +1,62 @@
+use crate::{
+    backend::DbBackend,
+    frontend::{blob, Frontend},
+    views::View,
+};
+use db::BlobId;
+use egui::{ScrollArea, SidePanel, TextEdit};
+use search::parse_query;
+
+/// Every blob in the database, narrowed down using the search syntax for blobs.
+#[derive(Clone, Default)]
+pub struct BlobBrowser {
+    pub search: String,
+}
+
+impl BlobBrowser {
+    fn blobs(&self, db: &DbBackend) -> Result<Vec<BlobId>, String> {
+        match parse_query(self.search.trim()) {
+            Ok(("", query)) => query
+                .execute_blobs(db, &db.index)
+                .map_err(|err| err.to_string()),
+            _ => Err("Invalid search.".to_string()),
+        }
+    }
+}
+
+impl View for BlobBrowser {
+    fn center_panel(&mut self, ui: &mut egui::Ui, frontend: &mut Frontend, db: &mut DbBackend) {
+        let blobs = match self.blobs(db) {
+            Ok(blobs) => blobs,
+            Err(err) => {
+                ui.label(err);
+                return;
+            }
+        };
+        ScrollArea::vertical()
+            .auto_shrink([false, true])
+            .show(ui, |ui| {
+                ui.horizontal_wrapped(|ui| {
+                    for blob_id in blobs {
+                        blob::thumbnail(ui, frontend, db, blob_id);
+                    }
+                });
+            });
+    }
+
+    fn side_panels(&mut self, ctx: &egui::CtxRef, _: &mut Frontend, _: &mut DbBackend) {
+        SidePanel::left("information").show(ctx, |ui| {
+            ui.add(TextEdit::singleline(&mut self.search).hint_text("Search"));
+            ui.label(
+                "e.g. \"type:raw added:2023\", or \"yumi_*\" for the blobs of matching pieces",
+            );
+        });
+    }
+
+    fn name(&self, _: &DbBackend) -> String {
+        "Blobs".into()
+    }
+    fn boxed_clone(&self) -> Box<dyn View> {
+        Box::new(self.clone())
+    }
+}
@@ -1,10 +1,10 @@
-use db::{BlobId, BlobType};
+use db::{BlobId, BlobType, MediaType};
 use egui::{ComboBox, SidePanel};
 use strum::IntoEnumIterator;
 
 use crate::{
     backend::DbBackend,
-    frontend::{texture_storage::ImageStatus, Frontend},
+    frontend::{blob, texture_storage::ImageStatus, Frontend},
     ui_memory::TextItemEdit,
     views::View,
 };
@@ -23,7 +23,9 @@ impl View for EditBlob {
         format!("Edit {}", db[self.blob_id].file_name)
     }
     fn center_panel(&mut self, ui: &mut egui::Ui, frontend: &mut Frontend, db: &mut DbBackend) {
-        if let ImageStatus::Available(texture) = frontend.image_for(self.blob_id, db) {
+        if db[self.blob_id].media_type() != MediaType::Image {
+            blob::display(ui, frontend, db, self.blob_id);
+        } else if let ImageStatus::Available(texture) = frontend.image_for(self.blob_id, db) {
             ui.centered_and_justified(|ui| {
                 ui.image(texture.id, texture.scaled(ui.available_size().into()));
             });
@@ -32,6 +34,8 @@ impl View for EditBlob {
 
     fn side_panels(&mut self, ctx: &egui::CtxRef, _: &mut Frontend, db: &mut DbBackend) {
         SidePanel::left("left_edit_blob_panel").show(ctx, |ui| {
+            // so renaming it below leaves its file where it is
+            db.pin_storage(self.blob_id);
             let blob = db.blobs.get_mut(self.blob_id).unwrap();
 
             let parent_id = ui.make_persistent_id(self.blob_id);
@@ -42,6 +46,10 @@ impl View for EditBlob {
             );
             ui.add(TextItemEdit::new(parent_id.with("Added"), &mut blob.added).hint_text("Added"));
             ui.label(format!("Hash: {:#x}", blob.hash));
+            ui.label(format!("Media: {}", blob.media_type()));
+            if let Some(duration) = blob.duration {
+                ui.label(format!("Length: {}", blob::format_duration(duration)));
+            }
 
             ComboBox::from_label("Type")
                 .selected_text(blob.blob_type.to_string())
@@ -7,6 +7,7 @@ use crate::{
 use db::CategoryId;
 use egui::{ScrollArea, SidePanel};
 use itertools::Itertools;
+use search::index::Document;
 
 #[derive(Clone, Copy)]
 pub struct EditCategory {
@@ -63,7 +64,10 @@ impl View for EditCategory {
             ui.color_edit_button_srgba_unmultiplied(&mut category.color);
 
             ui.separator();
-            easy_mark_editor(ui, &mut category.description);
+            if easy_mark_editor(ui, &mut category.description).changed() {
+                db.index
+                    .refresh(&db.inner, Document::Category(self.category_id));
+            }
         });
     }
 
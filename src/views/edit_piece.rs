@@ -1,10 +1,15 @@
-use db::{BlobId, PieceId};
-use egui::{ScrollArea, SidePanel, TopBottomPanel};
+use db::{BlobId, MediaType, PieceId, SourceType};
+use egui::{ComboBox, ScrollArea, SidePanel, TopBottomPanel};
 use itertools::Itertools;
+use search::index::Document;
+use strum::IntoEnumIterator;
 
 use crate::{
     backend::DbBackend,
-    frontend::{blob, easy_mark_editor::easy_mark_editor, piece, tag_editor::tag_editor, Frontend},
+    frontend::{
+        blob, easy_mark_editor::easy_mark_editor, import::DropTarget, piece,
+        relation_editor::relation_editor, tag_editor::tag_editor, Frontend,
+    },
     ui_memory::TextItemEdit,
     views::View,
 };
@@ -41,6 +46,30 @@ impl View for EditPiece {
                             .hint_text("Added On"),
                     );
 
+                    ComboBox::from_label("Source")
+                        .selected_text(piece.source_type.to_string())
+                        .show_ui(ui, |ui| {
+                            for source_type in SourceType::iter() {
+                                ui.selectable_value(
+                                    &mut piece.source_type,
+                                    source_type,
+                                    source_type.to_string(),
+                                );
+                            }
+                        });
+
+                    ComboBox::from_label("Media")
+                        .selected_text(piece.media_type.to_string())
+                        .show_ui(ui, |ui| {
+                            for media_type in MediaType::iter() {
+                                ui.selectable_value(
+                                    &mut piece.media_type,
+                                    media_type,
+                                    media_type.to_string(),
+                                );
+                            }
+                        });
+
                     ui.add(
                         TextItemEdit::new(parent_id.with("base_price"), &mut piece.base_price)
                             .hint_text("Price"),
@@ -52,12 +81,16 @@ impl View for EditPiece {
                     );
 
                     ui.separator();
-                    easy_mark_editor(ui, &mut piece.description);
+                    if easy_mark_editor(ui, &mut piece.description).changed() {
+                        db.index.refresh(&db.inner, Document::Piece(self.piece_id));
+                    }
                     ui.separator();
                     tag_editor(ui, self.piece_id, self.piece_id, db);
+                    ui.separator();
+                    relation_editor(ui, ("relations", self.piece_id), self.piece_id, db);
                 });
                 ui[1].vertical(|ui| {
-                    piece::info_panel(db, self.piece_id, ui);
+                    piece::info_panel(db, self.piece_id, frontend, ui);
                 });
             });
         });
@@ -84,6 +117,10 @@ impl View for EditPiece {
         format!("Edit {}...", &db[self.piece_id].description[..10])
     }
 
+    fn drop_target(&self) -> Option<DropTarget> {
+        Some(DropTarget::Piece(self.piece_id))
+    }
+
     fn boxed_clone(&self) -> Box<dyn View> {
         Box::new(*self)
     }
@@ -7,6 +7,7 @@ use crate::{
 use db::TagId;
 use egui::{ComboBox, ScrollArea, SidePanel};
 use itertools::Itertools;
+use search::index::Document;
 
 #[derive(Clone, Copy)]
 pub struct EditTag {
@@ -68,7 +69,9 @@ impl View for EditTag {
             );
 
             ui.separator();
-            easy_mark_editor(ui, &mut tag.description);
+            if easy_mark_editor(ui, &mut tag.description).changed() {
+                db.index.refresh(&db.inner, Document::Tag(self.tag_id));
+            }
         });
     }
 
@@ -1,40 +1,260 @@
 use crate::{
     backend::DbBackend,
-    frontend::{piece, tag, Frontend},
-    views::View,
+    frontend::{blob, collection, import::DropTarget, piece, selection, tag, Frontend},
+    ui_memory::MemoryExt,
+    views::{view_collection::ViewCollection, View},
 };
-use egui::{ScrollArea, SidePanel};
+use chrono::Local;
+use db::{BlobId, Collection, PieceId, SavedSearch, TagId};
+use egui::{Button, CollapsingHeader, ScrollArea, SidePanel, TextEdit};
 use itertools::Itertools;
+use search::{
+    model::{OrderKey, Query, Target},
+    parse_query, IncrementalSearch, SearchError,
+};
+
+#[derive(Clone, Default)]
+pub struct Gallery {
+    pub search: String,
+    pub seed: u64,
+    /// Report unknown tags instead of ignoring them.
+    pub strict: bool,
+    /// Name the current search will be saved under.
+    pub saved_name: String,
+    /// Narrows down the tag list, using the search syntax for tags.
+    pub tag_filter: String,
+    /// The pieces the search last found, kept so it isn't run again every frame.
+    found: Option<Found>,
+}
+
+#[derive(Clone)]
+struct Found {
+    generation: u64,
+    search: IncrementalSearch,
+    /// The search's results, in its order.
+    pieces: Vec<PieceId>,
+}
+
+/// What the search matched, depending on what it searched through.
+enum Matches {
+    Pieces(Vec<PieceId>),
+    Tags(Vec<TagId>),
+    Blobs(Vec<BlobId>),
+}
+
+impl Gallery {
+    /// Returns None if the search couldn't be parsed.
+    fn query(&self) -> Option<Query> {
+        match parse_query(self.search.trim()) {
+            Ok(("", query)) => Some(Query {
+                seed: self.seed,
+                strict: self.strict,
+                ..query
+            }),
+            _ => None,
+        }
+    }
+
+    fn matches(&mut self, db: &DbBackend) -> Result<Matches, String> {
+        let query = self.query().ok_or_else(|| "Invalid search.".to_string())?;
+        match query.target {
+            Target::Pieces => self.pieces(query, db).map(Matches::Pieces),
+            Target::Tags => query.execute_tags(db, &db.index).map(Matches::Tags),
+            Target::Blobs => query.execute_blobs(db, &db.index).map(Matches::Blobs),
+        }
+        .map_err(|err| err.to_string())
+    }
+
+    /// Runs `query` for pieces, or brings the results of the last run up to date
+    /// with what's changed since, if it was the same query.
+    fn pieces(&mut self, query: Query, db: &DbBackend) -> Result<Vec<PieceId>, SearchError> {
+        let today = Local::today().naive_local();
+        match &mut self.found {
+            Some(found) if *found.search.query() == query => {
+                if found.generation == db.generation {
+                    return Ok(found.pieces.clone());
+                }
+                match &db.last_changes {
+                    Some((generation, changes)) if *generation == found.generation => {
+                        found.search.apply(changes, db, &db.index, today)?;
+                    }
+                    _ => found.search = IncrementalSearch::new(query, db, &db.index, today)?,
+                }
+            }
+            _ => {
+                self.found = Some(Found {
+                    generation: db.generation,
+                    search: IncrementalSearch::new(query, db, &db.index, today)?,
+                    pieces: Vec::new(),
+                });
+            }
+        }
+
+        let found = self.found.as_mut().unwrap();
+        let mut pieces = found.search.results().iter().copied().collect::<Vec<_>>();
+        found.search.query().sort(&mut pieces, db);
+        found.generation = db.generation;
+        found.pieces = pieces.clone();
+        Ok(pieces)
+    }
+
+    fn filtered_tags(&self, db: &DbBackend) -> Result<Vec<TagId>, String> {
+        match parse_query(self.tag_filter.trim()) {
+            Ok(("", query)) => query
+                .execute_tags(db, &db.index)
+                .map_err(|err| err.to_string()),
+            _ => Err("Invalid filter.".to_string()),
+        }
+    }
 
-#[derive(Clone, Copy)]
-pub struct Gallery;
+    fn saved_searches(&mut self, ui: &mut egui::Ui, db: &mut DbBackend) {
+        ui.horizontal(|ui| {
+            ui.add(TextEdit::singleline(&mut self.saved_name).hint_text("Name"));
+            let valid = !self.saved_name.trim().is_empty() && self.query().is_some();
+            if ui.add_enabled(valid, Button::new("Save")).clicked() {
+                let name = self.saved_name.trim();
+                match db.find_saved_search(name) {
+                    Some(saved_id) => {
+                        db.saved_searches.get_mut(saved_id).unwrap().query = self.search.clone();
+                    }
+                    None => {
+                        db.create_saved_search(SavedSearch {
+                            name: name.to_string(),
+                            query: self.search.clone(),
+                            ..Default::default()
+                        });
+                    }
+                }
+            }
+        });
+
+        CollapsingHeader::new("Saved Searches")
+            .default_open(true)
+            .show(ui, |ui| {
+                for saved_id in db
+                    .saved_searches()
+                    .sorted_by(|(_, lhs), (_, rhs)| lhs.name.cmp(&rhs.name))
+                    .map(|(saved_id, _)| saved_id)
+                    .collect::<Vec<_>>()
+                {
+                    let saved = &db[saved_id];
+                    let response = ui
+                        .selectable_label(saved.query == self.search, &saved.name)
+                        .on_hover_text(&saved.query);
+                    if response.clicked() {
+                        self.search = saved.query.clone();
+                        self.saved_name = saved.name.clone();
+                    }
+                    response.context_menu(|ui| {
+                        if ui.button("Delete").clicked() {
+                            db.delete(saved_id);
+                            ui.close_menu();
+                        }
+                    });
+                }
+            });
+    }
+
+    fn collections(&mut self, ui: &mut egui::Ui, db: &mut DbBackend) {
+        CollapsingHeader::new("Collections")
+            .default_open(true)
+            .show(ui, |ui| {
+                for collection_id in db
+                    .collections()
+                    .sorted_by(|(_, lhs), (_, rhs)| lhs.name.cmp(&rhs.name))
+                    .map(|(collection_id, _)| collection_id)
+                    .collect::<Vec<_>>()
+                {
+                    collection::label(ui, db, collection_id).context_menu(|ui| {
+                        collection::context_menu(ui, collection_id);
+                        ui.separator();
+                        if ui.button("Delete").clicked() {
+                            db.delete(collection_id);
+                            ui.close_menu();
+                        }
+                    });
+                }
+                if ui.button("New Collection").clicked() {
+                    let collection_id = db.create_collection(Collection::default());
+                    ui.push_view(ViewCollection { collection_id });
+                }
+            });
+    }
+}
 
 impl View for Gallery {
     fn center_panel(&mut self, ui: &mut egui::Ui, frontend: &mut Frontend, db: &mut DbBackend) {
+        let matches = match self.matches(db) {
+            Ok(matches) => matches,
+            Err(err) => {
+                ui.label(err);
+                return;
+            }
+        };
         ScrollArea::vertical()
             .auto_shrink([false, true])
-            .show(ui, |ui| {
-                ui.horizontal_wrapped(|ui| {
-                    for (piece_id, _) in db.pieces().sorted_by_key(|(_, item)| item.added).rev() {
-                        piece::thumbnail(db, piece_id, frontend, ui);
+            .show(ui, |ui| match matches {
+                Matches::Pieces(pieces) => {
+                    ui.horizontal_wrapped(|ui| {
+                        for &piece_id in &pieces {
+                            piece::selectable_thumbnail(db, piece_id, &pieces, frontend, ui);
+                        }
+                    });
+                }
+                Matches::Tags(tags) => {
+                    let (shown, clicked) =
+                        tag::selectable_list(db, tags.into_iter(), &frontend.selection.tags, ui);
+                    if let Some(tag_id) = clicked {
+                        let modifiers = ui.input().modifiers;
+                        frontend.selection.click_tag(tag_id, &shown, modifiers);
                     }
-                });
+                }
+                Matches::Blobs(blobs) => {
+                    ui.horizontal_wrapped(|ui| {
+                        for blob_id in blobs {
+                            blob::thumbnail(ui, frontend, db, blob_id);
+                        }
+                    });
+                }
             });
     }
 
-    fn side_panels(&mut self, ctx: &egui::CtxRef, _: &mut Frontend, db: &mut DbBackend) {
+    fn side_panels(&mut self, ctx: &egui::CtxRef, frontend: &mut Frontend, db: &mut DbBackend) {
         SidePanel::left("information").show(ctx, |ui| {
+            ui.add(TextEdit::singleline(&mut self.search).hint_text("Search"));
+            ui.checkbox(&mut self.strict, "Strict")
+                .on_hover_text("Report tags that don't exist, instead of ignoring them");
+            let shuffled = self.query().map_or(false, |query| {
+                query
+                    .ordering
+                    .iter()
+                    .any(|order| order.key == OrderKey::Random)
+            });
+            if shuffled && ui.button("Shuffle").clicked() {
+                self.seed = self.seed.wrapping_add(1);
+            }
+            selection::bulk_panel(ui, frontend, db);
+            self.saved_searches(ui, db);
+            self.collections(ui, db);
+            ui.separator();
+            ui.add(TextEdit::singleline(&mut self.tag_filter).hint_text("Filter tags"));
             ScrollArea::vertical()
                 .auto_shrink([false, true])
-                .show(ui, |ui| {
-                    tag::list(db, db.tags.keys(), ui);
+                .show(ui, |ui| match self.filtered_tags(db) {
+                    Ok(tags) => tag::list(db, tags.into_iter(), ui),
+                    Err(err) => {
+                        ui.label(err);
+                    }
                 });
         });
     }
     fn name(&self, _: &DbBackend) -> String {
         "Gallery".into()
     }
+    fn drop_target(&self) -> Option<DropTarget> {
+        Some(DropTarget::Gallery)
+    }
     fn boxed_clone(&self) -> Box<dyn View> {
-        Box::new(*self)
+        Box::new(self.clone())
     }
 }
@@ -0,0 +1,137 @@
+use std::{
+    path::PathBuf,
+    sync::{Arc, Mutex},
+};
+
+use db::Inbox;
+use egui::{Button, Color32, ScrollArea, SidePanel, TextEdit};
+use itertools::Itertools;
+use regex::Regex;
+
+use crate::{
+    backend::DbBackend,
+    frontend::{piece, selection, Frontend},
+    views::View,
+};
+
+/// Pieces imported from the inbox that still need looking over, along with the inbox's settings.
+#[derive(Clone, Default)]
+pub struct Review {
+    /// The inbox folder picked from the folder dialog, once it's closed.
+    chosen_dir: Arc<Mutex<Option<PathBuf>>>,
+}
+
+impl View for Review {
+    fn center_panel(&mut self, ui: &mut egui::Ui, frontend: &mut Frontend, db: &mut DbBackend) {
+        let pieces = db
+            .review
+            .iter()
+            .copied()
+            .sorted_by_key(|piece_id| db[piece_id].added)
+            .rev()
+            .collect::<Vec<_>>();
+        if pieces.is_empty() {
+            ui.label("Nothing to review.");
+            return;
+        }
+
+        ScrollArea::vertical()
+            .auto_shrink([false, true])
+            .show(ui, |ui| {
+                ui.horizontal_wrapped(|ui| {
+                    for &piece_id in &pieces {
+                        piece::selectable_thumbnail(db, piece_id, &pieces, frontend, ui);
+                    }
+                });
+            });
+    }
+
+    fn side_panels(&mut self, ctx: &egui::CtxRef, frontend: &mut Frontend, db: &mut DbBackend) {
+        SidePanel::left("information").show(ctx, |ui| {
+            if let Some(dir) = self.chosen_dir.lock().unwrap().take() {
+                db.inbox.dir = Some(dir);
+            }
+            self.inbox_settings(ui, &mut db.inbox);
+            ui.separator();
+
+            let selected = frontend
+                .selection
+                .pieces
+                .iter()
+                .filter(|piece_id| db.review.contains(piece_id))
+                .copied()
+                .collect::<Vec<_>>();
+            if ui
+                .add_enabled(!selected.is_empty(), Button::new("Mark Reviewed"))
+                .on_hover_text("Takes the selected pieces out of the queue")
+                .clicked()
+            {
+                for piece_id in selected {
+                    db.review.remove(&piece_id);
+                }
+            }
+            if ui
+                .button("Clear Tagged")
+                .on_hover_text("Takes every piece with tags out of the queue")
+                .clicked()
+            {
+                let tagged = db
+                    .review
+                    .iter()
+                    .copied()
+                    .filter(|piece_id| db.tags_for_piece(*piece_id).next().is_some())
+                    .collect::<Vec<_>>();
+                for piece_id in tagged {
+                    db.review.remove(&piece_id);
+                }
+            }
+            selection::bulk_panel(ui, frontend, db);
+        });
+    }
+
+    fn name(&self, db: &DbBackend) -> String {
+        format!("Review ({})", db.review.len())
+    }
+    fn boxed_clone(&self) -> Box<dyn View> {
+        Box::new(self.clone())
+    }
+}
+
+impl Review {
+    fn inbox_settings(&self, ui: &mut egui::Ui, inbox: &mut Inbox) {
+        ui.label("Inbox");
+        ui.label(
+            inbox
+                .dir
+                .as_ref()
+                .map_or("<none>".to_string(), |dir| dir.display().to_string()),
+        );
+        ui.horizontal(|ui| {
+            if ui.button("Choose Folder").clicked() {
+                let chosen_dir = self.chosen_dir.clone();
+                tokio::spawn(async move {
+                    if let Some(dir) = rfd::AsyncFileDialog::new().pick_folder().await {
+                        *chosen_dir.lock().unwrap() = Some(dir.path().to_path_buf());
+                    }
+                });
+            }
+            if ui
+                .add_enabled(inbox.dir.is_some(), Button::new("Stop Watching"))
+                .clicked()
+            {
+                inbox.dir = None;
+            }
+        });
+
+        let valid = Regex::new(&inbox.external_id_pattern).is_ok();
+        ui.add(
+            TextEdit::singleline(&mut inbox.external_id_pattern)
+                .hint_text("External ID pattern")
+                .text_color_opt((!valid).then(|| Color32::LIGHT_RED)),
+        )
+        .on_hover_text(
+            "A regular expression for file names, e.g. \"^(\\d+)_\". \
+            Files are added to the piece whose external ID matches the first group.",
+        );
+    }
+}
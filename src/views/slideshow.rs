@@ -0,0 +1,101 @@
+use db::{BlobId, CollectionId};
+use egui::{Key, TopBottomPanel};
+
+use crate::{
+    backend::DbBackend,
+    frontend::{blob, Frontend},
+    views::View,
+};
+
+/// How long each blob is shown for while playing.
+const STEP_SECONDS: f64 = 5.0;
+
+/// Steps through the blobs of a collection, one at a time.
+#[derive(Clone, Copy)]
+pub struct Slideshow {
+    pub collection_id: CollectionId,
+    pub position: usize,
+    pub playing: bool,
+    last_step: Option<f64>,
+}
+
+impl Slideshow {
+    pub fn new(collection_id: CollectionId) -> Self {
+        Self {
+            collection_id,
+            position: 0,
+            playing: false,
+            last_step: None,
+        }
+    }
+
+    fn blobs(&self, db: &DbBackend) -> Vec<BlobId> {
+        db.blobs_for_collection(self.collection_id).collect()
+    }
+
+    fn step(&mut self, forward: bool, len: usize) {
+        self.position = if forward {
+            (self.position + 1) % len
+        } else {
+            (self.position + len - 1) % len
+        };
+    }
+}
+
+impl View for Slideshow {
+    fn center_panel(&mut self, ui: &mut egui::Ui, frontend: &mut Frontend, db: &mut DbBackend) {
+        let blobs = self.blobs(db);
+        if blobs.is_empty() {
+            ui.label("This collection is empty.");
+            return;
+        }
+        self.position = self.position.min(blobs.len() - 1);
+        blob::display(ui, frontend, db, blobs[self.position]);
+    }
+
+    fn side_panels(&mut self, ctx: &egui::CtxRef, _: &mut Frontend, db: &mut DbBackend) {
+        let len = self.blobs(db).len();
+        if len == 0 {
+            return;
+        }
+
+        let now = ctx.input().time;
+        if !self.playing {
+            self.last_step = None;
+        } else if now - *self.last_step.get_or_insert(now) >= STEP_SECONDS {
+            self.step(true, len);
+            self.last_step = Some(now);
+        }
+
+        if ctx.input().key_pressed(Key::ArrowRight) {
+            self.step(true, len);
+            self.last_step = None;
+        }
+        if ctx.input().key_pressed(Key::ArrowLeft) {
+            self.step(false, len);
+            self.last_step = None;
+        }
+
+        TopBottomPanel::bottom("slideshow_controls").show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                if ui.button("Previous").clicked() {
+                    self.step(false, len);
+                    self.last_step = None;
+                }
+                ui.label(format!("{} / {}", self.position.min(len - 1) + 1, len));
+                if ui.button("Next").clicked() {
+                    self.step(true, len);
+                    self.last_step = None;
+                }
+                ui.checkbox(&mut self.playing, "Play");
+            });
+        });
+    }
+
+    fn name(&self, db: &DbBackend) -> String {
+        format!("Slideshow: {}", db[self.collection_id].name)
+    }
+    fn boxed_clone(&self) -> Box<dyn View> {
+        Box::new(*self)
+    }
+}
@@ -2,7 +2,7 @@ use db::BlobId;
 
 use crate::{
     backend::DbBackend,
-    frontend::{blob, Frontend},
+    frontend::{blob, piece, Frontend},
     views::View,
 };
 
@@ -13,6 +13,12 @@ pub struct ViewBlob {
 
 impl View for ViewBlob {
     fn center_panel(&mut self, ui: &mut egui::Ui, frontend: &mut Frontend, db: &mut DbBackend) {
+        if let Some(piece_id) = db.pieces_for_blob(self.blob_id).next() {
+            let blobs = piece::blobs_in_order(db, piece_id);
+            if let Some(blob_id) = blob::navigation(ui, &blobs, self.blob_id) {
+                self.blob_id = blob_id;
+            }
+        }
         blob::display(ui, frontend, db, self.blob_id);
     }
     fn name(&self, db: &DbBackend) -> String {
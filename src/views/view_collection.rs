@@ -0,0 +1,164 @@
+use db::{BlobId, CollectionId};
+use egui::{ComboBox, ScrollArea, Sense, SidePanel};
+
+use crate::{
+    backend::{export::Export, DbBackend},
+    frontend::{easy_mark_editor::easy_mark_editor, piece, Frontend},
+    ui_memory::{MemoryExt, TextItemEdit},
+    views::{slideshow::Slideshow, View},
+};
+
+#[derive(Clone, Copy)]
+pub struct ViewCollection {
+    pub collection_id: CollectionId,
+}
+
+impl View for ViewCollection {
+    fn center_panel(&mut self, ui: &mut egui::Ui, frontend: &mut Frontend, db: &mut DbBackend) {
+        let drag_id = ui.make_persistent_id(("collection_drag", self.collection_id));
+        let mut dragged = ui
+            .memory()
+            .data
+            .get_temp::<Option<usize>>(drag_id)
+            .flatten();
+        let mut hovered = None;
+        let mut removed = None;
+        let mut entries = db[self.collection_id].entries.clone();
+
+        ScrollArea::vertical()
+            .auto_shrink([false, true])
+            .show(ui, |ui| {
+                ui.horizontal_wrapped(|ui| {
+                    for (position, entry) in entries.iter_mut().enumerate() {
+                        ui.vertical(|ui| {
+                            let response = entry
+                                .blob
+                                .or_else(|| piece::preview_blob(db, entry.piece))
+                                .and_then(|blob_id| {
+                                    piece::thumbnail_with_blob(
+                                        db,
+                                        entry.piece,
+                                        blob_id,
+                                        frontend,
+                                        ui,
+                                    )
+                                });
+                            if let Some(response) = response {
+                                let response = response.interact(Sense::drag());
+                                if response.drag_started() {
+                                    dragged = Some(position);
+                                }
+                                if dragged.is_some() && ui.rect_contains_pointer(response.rect) {
+                                    hovered = Some(position);
+                                    ui.painter().rect_stroke(
+                                        response.rect,
+                                        0.0,
+                                        ui.visuals().selection.stroke,
+                                    );
+                                }
+                            }
+
+                            ui.horizontal(|ui| {
+                                ComboBox::from_id_source((self.collection_id, position))
+                                    .selected_text(
+                                        entry
+                                            .blob
+                                            .map(|blob_id| db[blob_id].file_name.clone())
+                                            .unwrap_or_else(|| "<whole piece>".to_string()),
+                                    )
+                                    .show_ui(ui, |ui| {
+                                        ui.selectable_value(
+                                            &mut entry.blob,
+                                            None::<BlobId>,
+                                            "<whole piece>",
+                                        );
+                                        for blob_id in db.blobs_for_piece(entry.piece) {
+                                            ui.selectable_value(
+                                                &mut entry.blob,
+                                                Some(blob_id),
+                                                &db[blob_id].file_name,
+                                            );
+                                        }
+                                    });
+                                if ui.small_button("Remove").clicked() {
+                                    removed = Some(position);
+                                }
+                            });
+                        });
+                    }
+                });
+            });
+
+        if let Some(position) = removed {
+            entries.remove(position);
+        } else if ui.input().pointer.any_released() {
+            if let (Some(from), Some(to)) = (dragged, hovered) {
+                let entry = entries.remove(from);
+                entries.insert(to, entry);
+            }
+            dragged = None;
+        }
+
+        ui.memory().data.insert_temp(drag_id, dragged);
+        db.collections.get_mut(self.collection_id).unwrap().entries = entries;
+    }
+
+    fn side_panels(&mut self, ctx: &egui::CtxRef, _: &mut Frontend, db: &mut DbBackend) {
+        SidePanel::left("information").show(ctx, |ui| {
+            if ui.button("Slideshow").clicked() {
+                ui.push_view(Slideshow::new(self.collection_id));
+            }
+            ui.horizontal(|ui| {
+                if ui.button("Export to Folder").clicked() {
+                    let export = Export::new(db, db.blobs_for_collection(self.collection_id));
+                    tokio::spawn(async move {
+                        if let Some(folder) = rfd::AsyncFileDialog::new().pick_folder().await {
+                            if let Err(err) = export.to_folder(folder.path()).await {
+                                eprintln!("Couldn't export collection: {}", err);
+                            }
+                        }
+                    });
+                }
+                if ui.button("Export to Archive").clicked() {
+                    let export = Export::new(db, db.blobs_for_collection(self.collection_id));
+                    let file_name = format!("{}.zip", db[self.collection_id].name);
+                    tokio::spawn(async move {
+                        if let Some(file) = rfd::AsyncFileDialog::new()
+                            .set_file_name(&file_name)
+                            .add_filter("Zip Archive", &["zip"])
+                            .save_file()
+                            .await
+                        {
+                            if let Err(err) = export.to_archive(file.path().to_path_buf()).await {
+                                eprintln!("Couldn't export collection: {}", err);
+                            }
+                        }
+                    });
+                }
+            });
+            ui.separator();
+
+            let collection = db.collections.get_mut(self.collection_id).unwrap();
+            let parent_id = ui.make_persistent_id(self.collection_id);
+
+            ui.add(
+                TextItemEdit::new(parent_id.with("name"), &mut collection.name).hint_text("Name"),
+            );
+            ui.add(
+                TextItemEdit::new(parent_id.with("added"), &mut collection.added)
+                    .hint_text("Added On"),
+            );
+            ui.label(format!("{} pieces", collection.entries.len()));
+
+            ui.separator();
+            easy_mark_editor(ui, &mut collection.description);
+        });
+    }
+
+    fn name(&self, db: &DbBackend) -> String {
+        db[self.collection_id].name.clone()
+    }
+    fn boxed_clone(&self) -> Box<dyn View> {
+        Box::new(*self)
+    }
+}
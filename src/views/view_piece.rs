@@ -1,10 +1,10 @@
 use db::{BlobId, PieceId};
-use egui::{ScrollArea, SidePanel, TopBottomPanel};
-use itertools::Itertools;
+use egui::{ComboBox, ScrollArea, SidePanel, Slider, TopBottomPanel, Ui};
 
 use crate::{
     backend::DbBackend,
-    frontend::{blob, piece, Frontend},
+    frontend::{blob, import::DropTarget, piece, viewer::Compare, Frontend},
+    ui_memory::MemoryExt,
     views::View,
 };
 
@@ -20,7 +20,29 @@ impl View for ViewPiece {
     }
     fn center_panel(&mut self, ui: &mut egui::Ui, frontend: &mut Frontend, db: &mut DbBackend) {
         if let Some(blob_id) = self.previewed {
-            blob::display(ui, frontend, db, blob_id);
+            let blobs = piece::blobs_in_order(db, self.piece_id);
+            let memory_id = ui.make_persistent_id(("compare", self.piece_id));
+            let compare = ui.with_memory(
+                memory_id,
+                || None,
+                |ui, compare| {
+                    ui.horizontal(|ui| {
+                        if let Some(blob_id) = blob::navigation(ui, &blobs, blob_id) {
+                            self.previewed = Some(blob_id);
+                        }
+                        ui.separator();
+                        compare_controls(ui, db, &blobs, blob_id, compare);
+                    });
+                    *compare
+                },
+            );
+
+            match compare.filter(|(other, _)| *other != blob_id && blobs.contains(other)) {
+                Some((other, mode)) => {
+                    blob::display_compared(ui, frontend, db, blob_id, other, mode)
+                }
+                None => blob::display(ui, frontend, db, blob_id),
+            }
         } else {
             ui.label("No Image");
         }
@@ -30,7 +52,7 @@ impl View for ViewPiece {
         SidePanel::left("information")
             .resizable(false)
             .show(ctx, |ui| {
-                piece::info_panel(db, self.piece_id, ui);
+                piece::info_panel(db, self.piece_id, frontend, ui);
             });
         TopBottomPanel::bottom("image_list")
             .resizable(false)
@@ -38,10 +60,7 @@ impl View for ViewPiece {
                 ui.set_min_height(276.0);
                 ScrollArea::horizontal().show(ui, |ui| {
                     ui.horizontal(|ui| {
-                        for blob_id in db
-                            .blobs_for_piece(self.piece_id)
-                            .sorted_by_key(|item| (db[item].blob_type, db[item].added))
-                        {
+                        for blob_id in piece::blobs_in_order(db, self.piece_id) {
                             if blob::thumbnail(ui, frontend, db, blob_id).clicked() {
                                 self.previewed = Some(blob_id);
                             }
@@ -51,7 +70,48 @@ impl View for ViewPiece {
             });
     }
 
+    fn drop_target(&self) -> Option<DropTarget> {
+        Some(DropTarget::Piece(self.piece_id))
+    }
+
     fn boxed_clone(&self) -> Box<dyn View> {
         Box::new(*self)
     }
 }
+
+/// Picks another of the piece's blobs to compare the previewed one with, e.g. a draft with the canon.
+fn compare_controls(
+    ui: &mut Ui,
+    db: &DbBackend,
+    blobs: &[BlobId],
+    previewed: BlobId,
+    compare: &mut Option<(BlobId, Compare)>,
+) {
+    let label = |blob_id: BlobId| format!("{} ({})", db[blob_id].file_name, db[blob_id].blob_type);
+
+    ComboBox::from_label("Compare")
+        .selected_text(compare.map_or("Nothing".to_string(), |(other, _)| label(other)))
+        .show_ui(ui, |ui| {
+            if ui.selectable_label(compare.is_none(), "Nothing").clicked() {
+                *compare = None;
+            }
+            for &other in blobs.iter().filter(|blob_id| **blob_id != previewed) {
+                let selected = compare.map_or(false, |(blob_id, _)| blob_id == other);
+                if ui.selectable_label(selected, label(other)).clicked() {
+                    let mode = compare.map_or(Compare::SideBySide, |(_, mode)| mode);
+                    *compare = Some((other, mode));
+                }
+            }
+        });
+
+    if let Some((_, mode)) = compare {
+        ui.radio_value(mode, Compare::SideBySide, "Side by Side");
+        let onion = matches!(mode, Compare::Onion(_));
+        if ui.radio(onion, "Onion Skin").clicked() && !onion {
+            *mode = Compare::Onion(0.5);
+        }
+        if let Compare::Onion(opacity) = mode {
+            ui.add(Slider::new(opacity, 0.0..=1.0).text("Opacity"));
+        }
+    }
+}
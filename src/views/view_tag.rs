@@ -1,6 +1,6 @@
 use crate::{
     backend::DbBackend,
-    frontend::{piece, Frontend},
+    frontend::{piece, selection, Frontend},
     ui_memory::color32_from,
     views::View,
 };
@@ -19,18 +19,19 @@ impl View for ViewTag {
         ScrollArea::vertical()
             .auto_shrink([false, true])
             .show(ui, |ui| {
+                let pieces = db
+                    .pieces_for_tag(self.tag_id)
+                    .sorted_by_key(|piece_id| db[piece_id].added)
+                    .rev()
+                    .collect::<Vec<_>>();
                 ui.horizontal_wrapped(|ui| {
-                    for piece_id in db
-                        .pieces_for_tag(self.tag_id)
-                        .sorted_by_key(|piece_id| db[piece_id].added)
-                        .rev()
-                    {
-                        piece::thumbnail(db, piece_id, frontend, ui);
+                    for &piece_id in &pieces {
+                        piece::selectable_thumbnail(db, piece_id, &pieces, frontend, ui);
                     }
                 });
             });
     }
-    fn side_panels(&mut self, ctx: &egui::CtxRef, _: &mut Frontend, db: &mut DbBackend) {
+    fn side_panels(&mut self, ctx: &egui::CtxRef, frontend: &mut Frontend, db: &mut DbBackend) {
         SidePanel::left("information")
             .resizable(false)
             .show(ctx, |ui| {
@@ -45,6 +46,7 @@ impl View for ViewTag {
                     ui.separator();
                     easy_mark(ui, &tag.description);
                 }
+                selection::bulk_panel(ui, frontend, db);
             });
     }
 